@@ -9,6 +9,20 @@ macro_rules! ast_derive {
                 ),+
             }
 
+            impl $name {
+                // The address of the node, which tells apart two nodes that
+                // are equal because they're written the same way. Empty has
+                // no node, so it's 0.
+                pub fn node_id(&self) -> usize {
+                    match self {
+                        $name::Empty => 0,
+                        $(
+                            $name::$type(x) => Rc::as_ptr(x) as usize
+                        ),+
+                    }
+                }
+            }
+
             $(
                 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
                 pub struct $type {
@@ -18,6 +32,7 @@ macro_rules! ast_derive {
                 }
 
                 impl $type {
+                    #[allow(clippy::new_ret_no_self)]
                     pub fn new(
                         $(
                             $prop: $class
@@ -48,9 +63,12 @@ macro_rules! ast_derive {
             impl<R> [<$name Accept>]<R> for $name {
                 fn accept<V: [<$name Visitor>]<R>>(&self, visitor: &mut V) -> Result<R, RatexError> {
                     match self {
-                        $name::Empty => {
-                            panic!("Cannot visit empty");
-                        }
+                        // callers are meant to check for Empty first, but a stray
+                        // one is reported rather than taking the process down
+                        $name::Empty => Err(RatexError {
+                            source: RatexErrorType::EmptyNode(stringify!($name)),
+                            backtrace: None,
+                        }),
 
                         $(
                             $name::$type(x) => visitor.[<visit_ $type:snake>](Rc::clone(x))
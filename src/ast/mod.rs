@@ -5,40 +5,80 @@ use std::rc::Rc;
 
 use crate::ast::ast_macro::ast_derive;
 use crate::class::{RatexClass, RatexInstance};
+use crate::environment::Environment;
+use crate::error::{RatexError, RatexErrorType};
 use crate::interpreter::RatexInterpreter;
 use crate::token::RatexToken;
-use crate::RatexError;
 
 mod ast_macro;
 
-#[derive(Debug)]
 pub enum Object {
     Bool(bool),
-    String(String),
+    // literals from the same source share one allocation, see Scanner::intern.
+    // Strings made while running aren't interned.
+    String(Rc<str>),
     Number(f64),
     Function(Rc<RefCell<dyn RatexCallable>>),
-    Class(RatexClass),
+    Class(Rc<RatexClass>),
     Instance(Rc<RefCell<RatexInstance>>),
+    List(Rc<RefCell<Vec<Object>>>),
+    // fixed once built, so unlike a list it's shared without a RefCell
+    Tuple(Rc<Vec<Object>>),
     Nil,
 }
 
 impl Object {
     pub fn is_truthy(&self) -> bool {
         match self {
-            Object::Bool(b) => return *b,
-            Object::String(s) => return s.len() > 0,
-            Object::Number(n) => return *n != 0.0,
-            Object::Function(_) => return true,
-            Object::Class(_) => return true,
-            Object::Instance(_) => return true,
-            Object::Nil => return false,
+            Object::Bool(b) => *b,
+            Object::String(s) => !s.is_empty(),
+            Object::Number(n) => *n != 0.0,
+            Object::Function(_) => true,
+            Object::Class(_) => true,
+            Object::Instance(_) => true,
+            Object::List(l) => !l.borrow().is_empty(),
+            Object::Tuple(t) => !t.is_empty(),
+            Object::Nil => false,
+        }
+    }
+
+    // The name of the value's type as a return type would spell it, which
+    // for an instance is the name of its class.
+    pub fn type_name(&self) -> String {
+        match self {
+            Object::Bool(_) => "bool".to_owned(),
+            Object::String(_) => "string".to_owned(),
+            Object::Number(_) => "number".to_owned(),
+            Object::Function(_) => "function".to_owned(),
+            Object::Class(_) => "class".to_owned(),
+            Object::Instance(i) => i.borrow().name(),
+            Object::List(_) => "list".to_owned(),
+            Object::Tuple(_) => "tuple".to_owned(),
+            Object::Nil => "nil".to_owned(),
         }
     }
 }
 
+// Hashes what PartialEq compares, so values that are equal hash the same:
+// functions by identity, lists by their current contents, and classes and
+// instances by the class name, which equal ones always share.
 impl Hash for Object {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         core::mem::discriminant(self).hash(state);
+
+        match self {
+            Object::Bool(b) => b.hash(state),
+            Object::String(s) => s.hash(state),
+            // 0.0 and -0.0 are equal, so they have to hash the same
+            Object::Number(n) if *n == 0.0 => 0.0f64.to_bits().hash(state),
+            Object::Number(n) => n.to_bits().hash(state),
+            Object::Function(f) => (Rc::as_ptr(f) as *const () as usize).hash(state),
+            Object::Class(c) => c.name().hash(state),
+            Object::Instance(i) => i.borrow().name().hash(state),
+            Object::List(l) => hash_list(l, state),
+            Object::Tuple(t) => t.hash(state),
+            Object::Nil => {}
+        }
     }
 }
 
@@ -47,36 +87,152 @@ impl Eq for Object {}
 impl Clone for Object {
     fn clone(&self) -> Self {
         match self {
-            Object::Bool(b) => Object::Bool(b.clone()),
+            Object::Bool(b) => Object::Bool(*b),
             Object::String(s) => Object::String(s.clone()),
-            Object::Number(n) => Object::Number(n.clone()),
-            Object::Function(f) => Object::Function(Rc::clone(&f)),
-            Object::Class(c) => Object::Class(c.clone()),
+            Object::Number(n) => Object::Number(*n),
+            Object::Function(f) => Object::Function(Rc::clone(f)),
+            Object::Class(c) => Object::Class(Rc::clone(c)),
             Object::Instance(i) => Object::Instance(i.clone()),
+            Object::List(l) => Object::List(Rc::clone(l)),
+            Object::Tuple(t) => Object::Tuple(Rc::clone(t)),
             Object::Nil => Object::Nil,
         }
     }
 }
 
+// Conversions for native functions written in Rust, so they don't have to
+// match on Object by hand for simple values.
+impl From<f64> for Object {
+    fn from(n: f64) -> Self {
+        Object::Number(n)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(b: bool) -> Self {
+        Object::Bool(b)
+    }
+}
+
+impl From<String> for Object {
+    fn from(s: String) -> Self {
+        Object::String(s.into())
+    }
+}
+
+impl From<&str> for Object {
+    fn from(s: &str) -> Self {
+        Object::String(s.into())
+    }
+}
+
+impl TryFrom<Object> for f64 {
+    type Error = RatexError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Number(n) => Ok(n),
+            other => Err(conversion_error("number", other)),
+        }
+    }
+}
+
+impl TryFrom<Object> for bool {
+    type Error = RatexError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Bool(b) => Ok(b),
+            other => Err(conversion_error("bool", other)),
+        }
+    }
+}
+
+impl TryFrom<Object> for String {
+    type Error = RatexError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::String(s) => Ok(s.to_string()),
+            other => Err(conversion_error("string", other)),
+        }
+    }
+}
+
+fn conversion_error(expected: &'static str, got: Object) -> RatexError {
+    RatexError {
+        source: RatexErrorType::ConversionFailed(expected, Box::new(got)),
+        backtrace: None,
+    }
+}
+
 impl PartialEq for Object {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Object::Bool(b1), Object::Bool(b2)) => b1 == b2,
-            (Object::String(s1), Object::String(s2)) => s1 == s2,
+            // interned strings are usually the same allocation, so checking
+            // the pointer first skips comparing the characters
+            (Object::String(s1), Object::String(s2)) => Rc::ptr_eq(s1, s2) || s1 == s2,
             (Object::Number(n1), Object::Number(n2)) => n1 == n2,
             (Object::Function(f1), Object::Function(f2)) => Rc::ptr_eq(f1, f2),
-            (Object::Class(c1), Object::Class(c2)) => c1 == c2,
-            (Object::Instance(i1), Object::Instance(i2)) => i1 == i2,
+            // a class's methods close over scopes that can hold the class
+            // itself, so only the same class is equal
+            (Object::Class(c1), Object::Class(c2)) => Rc::ptr_eq(c1, c2),
+            // an instance can hold itself, so comparing fields could go on
+            // forever
+            (Object::Instance(i1), Object::Instance(i2)) => Rc::ptr_eq(i1, i2),
+            (Object::List(l1), Object::List(l2)) => lists_equal(l1, l2),
+            (Object::Tuple(t1), Object::Tuple(t2)) => t1 == t2,
             (Object::Nil, Object::Nil) => true,
             _ => false,
         }
     }
 }
 
+thread_local! {
+    // The lists being compared or hashed further up the stack. A list that
+    // holds itself meets the same list again, and stops there rather than
+    // recursing forever.
+    static COMPARING: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+    static HASHING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+// Two lists met again while comparing them are taken as equal there, so
+// lists that hold themselves the same way are equal.
+fn lists_equal(l1: &Rc<RefCell<Vec<Object>>>, l2: &Rc<RefCell<Vec<Object>>>) -> bool {
+    let pair = (Rc::as_ptr(l1) as usize, Rc::as_ptr(l2) as usize);
+
+    if COMPARING.with(|comparing| comparing.borrow().contains(&pair)) {
+        return true;
+    }
+
+    COMPARING.with(|comparing| comparing.borrow_mut().push(pair));
+    let equal = *l1.borrow() == *l2.borrow();
+    COMPARING.with(|comparing| comparing.borrow_mut().pop());
+
+    equal
+}
+
+fn hash_list<H: std::hash::Hasher>(list: &Rc<RefCell<Vec<Object>>>, state: &mut H) {
+    let address = Rc::as_ptr(list) as usize;
+
+    if HASHING.with(|hashing| hashing.borrow().contains(&address)) {
+        return;
+    }
+
+    HASHING.with(|hashing| hashing.borrow_mut().push(address));
+    list.borrow().hash(state);
+    HASHING.with(|hashing| hashing.borrow_mut().pop());
+}
+
 ast_derive! {
     Expr,
     Binary(left: Rc<Expr>, operator: RatexToken, right: Rc<Expr>),
     Logical(left: Rc<Expr>, operator: RatexToken, right: Rc<Expr>),
+    // two or more comparisons in a row, `a < b <= c`. Each operand is
+    // compared with the next, stopping at the first comparison that's false,
+    // so every operand is evaluated at most once.
+    Chain(operands: Vec<Rc<Expr>>, operators: Vec<RatexToken>),
     Set(object: Rc<Expr>, name: RatexToken, value: Rc<Expr>),
     This(keyword: RatexToken),
     Unary(operator: RatexToken, right: Rc<Expr>),
@@ -86,21 +242,91 @@ ast_derive! {
     Assign(name: RatexToken, value: Rc<Expr>),
     Call(callee: Rc<Expr>, paren: RatexToken, arguments: Vec<Rc<Expr>>),
     Get(object: Rc<Expr>, name: RatexToken),
-    Lambda(params: Vec<RatexToken>, body: Vec<Rc<Stmt>>)
+    ListLiteral(elements: Vec<Rc<Expr>>),
+    TupleLiteral(elements: Vec<Rc<Expr>>),
+    // `(a, b) = value`. Each target is an Assign with an Empty value, so the
+    // resolver can give every name its own distance.
+    AssignTuple(equals: RatexToken, targets: Vec<Rc<Expr>>, value: Rc<Expr>),
+    Index(object: Rc<Expr>, bracket: RatexToken, index: Rc<Expr>),
+    IndexSet(object: Rc<Expr>, bracket: RatexToken, index: Rc<Expr>, value: Rc<Expr>),
+    Slice(object: Rc<Expr>, bracket: RatexToken, start: Rc<Expr>, end: Rc<Expr>),
+    SliceSet(object: Rc<Expr>, bracket: RatexToken, start: Rc<Expr>, end: Rc<Expr>, value: Rc<Expr>),
+    // the name, if given, is only bound inside the lambda so that it can
+    // call itself
+    Lambda(name: Option<RatexToken>, params: Vec<RatexToken>, param_types: Vec<Option<RatexToken>>, defaults: Vec<Rc<Expr>>, variadic: bool, body: Vec<Rc<Stmt>>),
+    TypeCheck(object: Rc<Expr>, keyword: RatexToken, class: Rc<Expr>),
+    // a while or for loop used as an expression, whose value is what it
+    // breaks with
+    Loop(statement: Rc<Stmt>),
+    // each arm is a pattern and the value it gives. An Empty pattern is the
+    // `_` wildcard, a Variable binds the value to its name, and a Call is a
+    // constructor pattern whose arguments are the patterns for its fields.
+    Match(keyword: RatexToken, scrutinee: Rc<Expr>, arms: Vec<(Rc<Expr>, Rc<Expr>)>),
+    // an expression the optimizer found doesn't change while the loop around
+    // it runs, so it's worked out once per run of the loop. The index is its
+    // place in that loop's cache.
+    Invariant(index: usize, expr: Rc<Expr>)
 }
 
 ast_derive! {
     Stmt,
     Block(statements: Vec<Rc<Stmt>>),
-    Class(name: RatexToken, methods: Vec<Rc<Stmt>>),
+    Class(name: RatexToken, fields: Vec<RatexToken>, methods: Vec<Rc<Stmt>>),
+    Variant(name: RatexToken, constructors: Vec<Rc<Stmt>>),
     Expression(expr: Rc<Expr>),
     If(condition: Rc<Expr>, then_stmt: Rc<Stmt>, else_stmt: Rc<Stmt>),
-    Fun(name: RatexToken, params: Vec<RatexToken>, body: Vec<Rc<Stmt>>),
-    While(condition: Rc<Expr>, body: Rc<Stmt>),
-    Break(),
+    // the types, where given, are checked as arguments are bound and each
+    // time the function returns
+    Fun(name: RatexToken, params: Vec<RatexToken>, param_types: Vec<Option<RatexToken>>, defaults: Vec<Rc<Expr>>, variadic: bool, return_type: Option<RatexToken>, body: Vec<Rc<Stmt>>),
+    // `hoisted` is how many Invariant expressions the optimizer put in the
+    // loop, which is 0 unless it ran
+    While(label: Option<RatexToken>, condition: Rc<Expr>, body: Rc<Stmt>, else_stmt: Rc<Stmt>, hoisted: usize),
+    For(label: Option<RatexToken>, initialiser: Rc<Stmt>, condition: Rc<Expr>, increment: Rc<Expr>, body: Rc<Stmt>, else_stmt: Rc<Stmt>, hoisted: usize),
+    Break(keyword: RatexToken, label: Option<RatexToken>, value: Rc<Expr>),
+    Yield(keyword: RatexToken),
+    Try(body: Vec<Rc<Stmt>>, name: RatexToken, handler: Vec<Rc<Stmt>>),
+    // binds a resource for the body and calls its close() however the body
+    // is left
+    With(name: RatexToken, resource: Rc<Expr>, body: Vec<Rc<Stmt>>),
+    Throw(keyword: RatexToken, value: Rc<Expr>),
     Print(expr: Rc<Expr>),
     Return(keyword: RatexToken, value: Rc<Expr>),
-    Var(name: RatexToken, initialiser: Rc<Expr>)
+    Var(name: RatexToken, initialiser: Rc<Expr>, constant: bool),
+    // `var (a, b) = value`, which unpacks a tuple into new variables
+    VarTuple(paren: RatexToken, names: Vec<RatexToken>, initialiser: Rc<Expr>, constant: bool)
+}
+
+impl Match {
+    // The names a pattern binds, in the order they're defined.
+    pub fn bindings(pattern: &Expr) -> Vec<RatexToken> {
+        match pattern {
+            Expr::Variable(variable) => vec![variable.name.clone()],
+            Expr::Call(call) => call
+                .arguments
+                .iter()
+                .flat_map(|field| Match::bindings(field))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    // Whether a pattern matches every value.
+    pub fn is_irrefutable(pattern: &Expr) -> bool {
+        matches!(pattern, Expr::Empty | Expr::Variable(_))
+    }
+}
+
+impl AssignTuple {
+    // The names being assigned to, in order.
+    pub fn names(&self) -> Vec<RatexToken> {
+        self.targets
+            .iter()
+            .filter_map(|target| match target.as_ref() {
+                Expr::Assign(assign) => Some(assign.name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 impl Display for Object {
@@ -108,15 +334,59 @@ impl Display for Object {
         match self {
             Object::Bool(b) => write!(f, "{b}"),
             Object::String(s) => write!(f, "{s}"),
+            // spelled like JavaScript rather than Rust's inf and NaN
+            Object::Number(n) if n.is_nan() => write!(f, "NaN"),
+            Object::Number(n) if n.is_infinite() && *n > 0.0 => write!(f, "Infinity"),
+            Object::Number(n) if n.is_infinite() => write!(f, "-Infinity"),
             Object::Number(n) => write!(f, "{n}"),
-            Object::Function(fun) => write!(f, "<function {}>", fun.borrow().name()),
+            Object::Function(fun) => write!(f, "<fn {}>", fun.borrow().name()),
             Object::Class(c) => write!(f, "<class {}>", c.name()),
-            Object::Instance(i) => write!(f, "<{} class instance>", i.borrow().name()),
+            Object::Instance(i) => write!(f, "<{} instance>", i.borrow().name()),
+            Object::List(l) => {
+                let items: Vec<String> = l.borrow().iter().map(|o| o.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            // a one-element tuple keeps its comma, the way it's written
+            Object::Tuple(t) if t.len() == 1 => write!(f, "({},)", t[0]),
+            Object::Tuple(t) => {
+                let items: Vec<String> = t.iter().map(|o| o.to_string()).collect();
+                write!(f, "({})", items.join(", "))
+            }
+            Object::Nil => write!(f, "nil"),
+        }
+    }
+}
+
+// For debugging the interpreter: shows the variant and its contents, while
+// callables, classes and instances are shown by name.
+impl Debug for Object {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Object::Bool(b) => f.debug_tuple("Bool").field(b).finish(),
+            Object::String(s) => f.debug_tuple("String").field(s).finish(),
+            Object::Number(n) => f.debug_tuple("Number").field(n).finish(),
+            Object::Function(fun) => f
+                .debug_tuple("Function")
+                .field(&format_args!("{}", fun.borrow().name()))
+                .finish(),
+            Object::Class(c) => f
+                .debug_tuple("Class")
+                .field(&format_args!("{}", c.name()))
+                .finish(),
+            Object::Instance(i) => f
+                .debug_tuple("Instance")
+                .field(&format_args!("{}", i.borrow().name()))
+                .finish(),
+            Object::List(l) => f.debug_tuple("List").field(&*l.borrow()).finish(),
+            Object::Tuple(t) => f.debug_tuple("Tuple").field(&**t).finish(),
             Object::Nil => write!(f, "Nil"),
         }
     }
 }
 
+// The body of a function and the environment it closes over.
+pub type TaskParts = (Vec<Rc<Stmt>>, Rc<RefCell<Environment>>);
+
 pub trait RatexCallable: Debug {
     fn call(
         &self,
@@ -126,5 +396,108 @@ pub trait RatexCallable: Debug {
 
     fn arity(&self) -> Result<usize, RatexError>;
 
+    // The fewest arguments a call can pass, which is less than arity() when
+    // some parameters have defaults.
+    fn min_arity(&self) -> Result<usize, RatexError> {
+        self.arity()
+    }
+
+    // Whether calls can pass any number of arguments past arity().
+    fn variadic(&self) -> bool {
+        false
+    }
+
     fn name(&self) -> String;
+
+    // The body and closure of a function written in ratex, which is what a
+    // spawned task needs to run it one statement at a time. Natives have none.
+    fn task_parts(&self) -> Option<TaskParts> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expect_conversion_failed<T: Debug>(
+        result: Result<T, RatexError>,
+        expected: &str,
+        got: Object,
+    ) {
+        match result {
+            Err(RatexError {
+                source: RatexErrorType::ConversionFailed(name, value),
+                ..
+            }) => {
+                assert_eq!(name, expected);
+                assert_eq!(*value, got);
+            }
+            other => panic!("expected ConversionFailed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn debug_shows_each_variant() {
+        let klass = Rc::new(RatexClass::new(
+            "Point".to_owned(),
+            Vec::new(),
+            std::collections::HashMap::new(),
+            None,
+        ));
+        let list = Object::List(Rc::new(RefCell::new(vec![Object::Nil, Object::from(1.0)])));
+        let tuple = Object::Tuple(Rc::new(vec![Object::from(true), Object::from("a")]));
+
+        assert_eq!(format!("{:?}", Object::from(true)), "Bool(true)");
+        assert_eq!(format!("{:?}", Object::from("hi")), "String(\"hi\")");
+        assert_eq!(format!("{:?}", Object::from(1.5)), "Number(1.5)");
+        assert_eq!(
+            format!(
+                "{:?}",
+                Object::Function(crate::functions::ClockFunction::new())
+            ),
+            "Function(clock)"
+        );
+        assert_eq!(
+            format!("{:?}", Object::Class(Rc::clone(&klass))),
+            "Class(Point)"
+        );
+        assert_eq!(
+            format!("{:?}", Object::Instance(RatexInstance::new(klass))),
+            "Instance(Point)"
+        );
+        assert_eq!(format!("{:?}", list), "List([Nil, Number(1.0)])");
+        assert_eq!(format!("{:?}", tuple), "Tuple([Bool(true), String(\"a\")])");
+        assert_eq!(format!("{:?}", Object::Nil), "Nil");
+    }
+
+    #[test]
+    fn from_rust_values() {
+        assert_eq!(Object::from(1.5), Object::Number(1.5));
+        assert_eq!(Object::from(true), Object::Bool(true));
+        assert_eq!(Object::from("hi".to_owned()), Object::String("hi".into()));
+        assert_eq!(Object::from("hi"), Object::String("hi".into()));
+    }
+
+    #[test]
+    fn try_from_matching_objects() {
+        assert_eq!(f64::try_from(Object::Number(2.0)).unwrap(), 2.0);
+        assert!(bool::try_from(Object::Bool(true)).unwrap());
+        assert_eq!(String::try_from(Object::from("hi")).unwrap(), "hi");
+    }
+
+    #[test]
+    fn try_from_other_objects() {
+        expect_conversion_failed(
+            f64::try_from(Object::from("1")),
+            "number",
+            Object::from("1"),
+        );
+        expect_conversion_failed(bool::try_from(Object::Nil), "bool", Object::Nil);
+        expect_conversion_failed(
+            String::try_from(Object::Number(1.0)),
+            "string",
+            Object::Number(1.0),
+        );
+    }
 }
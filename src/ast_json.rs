@@ -0,0 +1,457 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{
+        Assign, AssignTuple, Binary, Block, Break, Call, Chain, Class, Expr, ExprAccept,
+        ExprVisitor, Expression, For, Fun, Get, Grouping, If, Index, IndexSet, Invariant, Lambda,
+        ListLiteral, Literal, Logical, Loop, Match, Object, Print, Return, Set, Slice, SliceSet,
+        Stmt, StmtAccept, StmtVisitor, This, Throw, Try, TupleLiteral, TypeCheck, Unary, Var,
+        VarTuple, Variable, Variant, While, With, Yield,
+    },
+    error::RatexError,
+    token::RatexToken,
+};
+
+// Serialises a parsed program as a JSON array with one object per statement.
+// Every node carries a "type" field naming its AST variant.
+pub fn to_json(statements: &[Rc<Stmt>]) -> String {
+    let mut serialiser = JsonSerialiser {};
+    serialiser.stmts(statements)
+}
+
+struct JsonSerialiser {}
+
+impl JsonSerialiser {
+    fn expr(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Empty => "null".to_owned(),
+            _ => expr.accept(self).unwrap_or_default(),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> String {
+        match stmt {
+            Stmt::Empty => "null".to_owned(),
+            _ => stmt.accept(self).unwrap_or_default(),
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Rc<Expr>]) -> String {
+        let items: Vec<String> = exprs.iter().map(|e| self.expr(e)).collect();
+        format!("[{}]", items.join(","))
+    }
+
+    fn stmts(&mut self, stmts: &[Rc<Stmt>]) -> String {
+        let items: Vec<String> = stmts.iter().map(|s| self.stmt(s)).collect();
+        format!("[{}]", items.join(","))
+    }
+
+    fn token(&self, token: &RatexToken) -> String {
+        format!(
+            "{{\"lexeme\":{},\"line\":{}}}",
+            string(&token.lexeme),
+            token.line
+        )
+    }
+
+    fn optional_token(&self, token: &Option<RatexToken>) -> String {
+        match token {
+            Some(token) => self.token(token),
+            None => "null".to_owned(),
+        }
+    }
+
+    fn tokens(&self, tokens: &[RatexToken]) -> String {
+        let items: Vec<String> = tokens.iter().map(|t| self.token(t)).collect();
+        format!("[{}]", items.join(","))
+    }
+
+    fn optional_tokens(&self, tokens: &[Option<RatexToken>]) -> String {
+        let items: Vec<String> = tokens.iter().map(|t| self.optional_token(t)).collect();
+        format!("[{}]", items.join(","))
+    }
+
+    fn node(&self, kind: &str, fields: Vec<(&str, String)>) -> String {
+        let mut out = format!("{{\"type\":{}", string(kind));
+
+        for (key, value) in fields {
+            out.push_str(&format!(",{}:{}", string(key), value));
+        }
+
+        out.push('}');
+        out
+    }
+}
+
+fn string(s: &str) -> String {
+    let mut out = String::from('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+impl ExprVisitor<String> for JsonSerialiser {
+    fn visit_binary(&mut self, target: Rc<Binary>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("left", self.expr(&target.left)),
+            ("operator", self.token(&target.operator)),
+            ("right", self.expr(&target.right)),
+        ];
+        Ok(self.node("Binary", fields))
+    }
+
+    fn visit_chain(&mut self, target: Rc<Chain>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("operands", self.exprs(&target.operands)),
+            ("operators", self.tokens(&target.operators)),
+        ];
+        Ok(self.node("Chain", fields))
+    }
+
+    fn visit_logical(&mut self, target: Rc<Logical>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("left", self.expr(&target.left)),
+            ("operator", self.token(&target.operator)),
+            ("right", self.expr(&target.right)),
+        ];
+        Ok(self.node("Logical", fields))
+    }
+
+    fn visit_set(&mut self, target: Rc<Set>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("object", self.expr(&target.object)),
+            ("name", self.token(&target.name)),
+            ("value", self.expr(&target.value)),
+        ];
+        Ok(self.node("Set", fields))
+    }
+
+    fn visit_this(&mut self, target: Rc<This>) -> Result<String, RatexError> {
+        let fields = vec![("keyword", self.token(&target.keyword))];
+        Ok(self.node("This", fields))
+    }
+
+    fn visit_unary(&mut self, target: Rc<Unary>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("operator", self.token(&target.operator)),
+            ("right", self.expr(&target.right)),
+        ];
+        Ok(self.node("Unary", fields))
+    }
+
+    fn visit_literal(&mut self, target: Rc<Literal>) -> Result<String, RatexError> {
+        let value = match &target.value {
+            Object::String(s) => string(s),
+            Object::Nil => "null".to_owned(),
+            value => value.to_string(),
+        };
+        Ok(self.node("Literal", vec![("value", value)]))
+    }
+
+    fn visit_grouping(&mut self, target: Rc<Grouping>) -> Result<String, RatexError> {
+        let fields = vec![("expr", self.expr(&target.expr))];
+        Ok(self.node("Grouping", fields))
+    }
+
+    fn visit_variable(&mut self, target: Rc<Variable>) -> Result<String, RatexError> {
+        let fields = vec![("name", self.token(&target.name))];
+        Ok(self.node("Variable", fields))
+    }
+
+    fn visit_assign(&mut self, target: Rc<Assign>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("name", self.token(&target.name)),
+            ("value", self.expr(&target.value)),
+        ];
+        Ok(self.node("Assign", fields))
+    }
+
+    fn visit_assign_tuple(&mut self, target: Rc<AssignTuple>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("equals", self.token(&target.equals)),
+            ("names", self.tokens(&target.names())),
+            ("value", self.expr(&target.value)),
+        ];
+        Ok(self.node("AssignTuple", fields))
+    }
+
+    fn visit_call(&mut self, target: Rc<Call>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("callee", self.expr(&target.callee)),
+            ("paren", self.token(&target.paren)),
+            ("arguments", self.exprs(&target.arguments)),
+        ];
+        Ok(self.node("Call", fields))
+    }
+
+    fn visit_get(&mut self, target: Rc<Get>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("object", self.expr(&target.object)),
+            ("name", self.token(&target.name)),
+        ];
+        Ok(self.node("Get", fields))
+    }
+
+    fn visit_lambda(&mut self, target: Rc<Lambda>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("name", self.optional_token(&target.name)),
+            ("params", self.tokens(&target.params)),
+            ("param_types", self.optional_tokens(&target.param_types)),
+            ("defaults", self.exprs(&target.defaults)),
+            ("variadic", target.variadic.to_string()),
+            ("body", self.stmts(&target.body)),
+        ];
+        Ok(self.node("Lambda", fields))
+    }
+
+    fn visit_type_check(&mut self, target: Rc<TypeCheck>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("object", self.expr(&target.object)),
+            ("keyword", self.token(&target.keyword)),
+            ("class", self.expr(&target.class)),
+        ];
+        Ok(self.node("TypeCheck", fields))
+    }
+
+    fn visit_loop(&mut self, target: Rc<Loop>) -> Result<String, RatexError> {
+        let fields = vec![("statement", self.stmt(&target.statement))];
+        Ok(self.node("Loop", fields))
+    }
+
+    fn visit_match(&mut self, target: Rc<Match>) -> Result<String, RatexError> {
+        // a null pattern is the `_` wildcard
+        let arms: Vec<String> = target
+            .arms
+            .iter()
+            .map(|(pattern, value)| {
+                format!(
+                    "{{\"pattern\":{},\"value\":{}}}",
+                    self.expr(pattern),
+                    self.expr(value)
+                )
+            })
+            .collect();
+
+        let fields = vec![
+            ("keyword", self.token(&target.keyword)),
+            ("scrutinee", self.expr(&target.scrutinee)),
+            ("arms", format!("[{}]", arms.join(","))),
+        ];
+        Ok(self.node("Match", fields))
+    }
+
+    fn visit_invariant(&mut self, target: Rc<Invariant>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("index", target.index.to_string()),
+            ("expr", self.expr(&target.expr)),
+        ];
+        Ok(self.node("Invariant", fields))
+    }
+
+    fn visit_list_literal(&mut self, target: Rc<ListLiteral>) -> Result<String, RatexError> {
+        let fields = vec![("elements", self.exprs(&target.elements))];
+        Ok(self.node("ListLiteral", fields))
+    }
+
+    fn visit_tuple_literal(&mut self, target: Rc<TupleLiteral>) -> Result<String, RatexError> {
+        let fields = vec![("elements", self.exprs(&target.elements))];
+        Ok(self.node("TupleLiteral", fields))
+    }
+
+    fn visit_index(&mut self, target: Rc<Index>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("object", self.expr(&target.object)),
+            ("bracket", self.token(&target.bracket)),
+            ("index", self.expr(&target.index)),
+        ];
+        Ok(self.node("Index", fields))
+    }
+
+    fn visit_index_set(&mut self, target: Rc<IndexSet>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("object", self.expr(&target.object)),
+            ("bracket", self.token(&target.bracket)),
+            ("index", self.expr(&target.index)),
+            ("value", self.expr(&target.value)),
+        ];
+        Ok(self.node("IndexSet", fields))
+    }
+
+    fn visit_slice(&mut self, target: Rc<Slice>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("object", self.expr(&target.object)),
+            ("bracket", self.token(&target.bracket)),
+            ("start", self.expr(&target.start)),
+            ("end", self.expr(&target.end)),
+        ];
+        Ok(self.node("Slice", fields))
+    }
+
+    fn visit_slice_set(&mut self, target: Rc<SliceSet>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("object", self.expr(&target.object)),
+            ("bracket", self.token(&target.bracket)),
+            ("start", self.expr(&target.start)),
+            ("end", self.expr(&target.end)),
+            ("value", self.expr(&target.value)),
+        ];
+        Ok(self.node("SliceSet", fields))
+    }
+}
+
+impl StmtVisitor<String> for JsonSerialiser {
+    fn visit_block(&mut self, target: Rc<Block>) -> Result<String, RatexError> {
+        let fields = vec![("statements", self.stmts(&target.statements))];
+        Ok(self.node("Block", fields))
+    }
+
+    fn visit_class(&mut self, target: Rc<Class>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("name", self.token(&target.name)),
+            ("fields", self.tokens(&target.fields)),
+            ("methods", self.stmts(&target.methods)),
+        ];
+        Ok(self.node("Class", fields))
+    }
+
+    fn visit_variant(&mut self, target: Rc<Variant>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("name", self.token(&target.name)),
+            ("constructors", self.stmts(&target.constructors)),
+        ];
+        Ok(self.node("Variant", fields))
+    }
+
+    fn visit_expression(&mut self, target: Rc<Expression>) -> Result<String, RatexError> {
+        let fields = vec![("expr", self.expr(&target.expr))];
+        Ok(self.node("Expression", fields))
+    }
+
+    fn visit_if(&mut self, target: Rc<If>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("condition", self.expr(&target.condition)),
+            ("then", self.stmt(&target.then_stmt)),
+            ("else", self.stmt(&target.else_stmt)),
+        ];
+        Ok(self.node("If", fields))
+    }
+
+    fn visit_fun(&mut self, target: Rc<Fun>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("name", self.token(&target.name)),
+            ("params", self.tokens(&target.params)),
+            ("param_types", self.optional_tokens(&target.param_types)),
+            ("defaults", self.exprs(&target.defaults)),
+            ("variadic", target.variadic.to_string()),
+            ("return_type", self.optional_token(&target.return_type)),
+            ("body", self.stmts(&target.body)),
+        ];
+        Ok(self.node("Fun", fields))
+    }
+
+    fn visit_while(&mut self, target: Rc<While>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("label", self.optional_token(&target.label)),
+            ("condition", self.expr(&target.condition)),
+            ("body", self.stmt(&target.body)),
+            ("else", self.stmt(&target.else_stmt)),
+        ];
+        Ok(self.node("While", fields))
+    }
+
+    fn visit_for(&mut self, target: Rc<For>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("label", self.optional_token(&target.label)),
+            ("initialiser", self.stmt(&target.initialiser)),
+            ("condition", self.expr(&target.condition)),
+            ("increment", self.expr(&target.increment)),
+            ("body", self.stmt(&target.body)),
+            ("else", self.stmt(&target.else_stmt)),
+        ];
+        Ok(self.node("For", fields))
+    }
+
+    fn visit_yield(&mut self, target: Rc<Yield>) -> Result<String, RatexError> {
+        let fields = vec![("keyword", self.token(&target.keyword))];
+        Ok(self.node("Yield", fields))
+    }
+
+    fn visit_try(&mut self, target: Rc<Try>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("body", self.stmts(&target.body)),
+            ("name", self.token(&target.name)),
+            ("handler", self.stmts(&target.handler)),
+        ];
+        Ok(self.node("Try", fields))
+    }
+
+    fn visit_with(&mut self, target: Rc<With>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("name", self.token(&target.name)),
+            ("resource", self.expr(&target.resource)),
+            ("body", self.stmts(&target.body)),
+        ];
+        Ok(self.node("With", fields))
+    }
+
+    fn visit_throw(&mut self, target: Rc<Throw>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("keyword", self.token(&target.keyword)),
+            ("value", self.expr(&target.value)),
+        ];
+        Ok(self.node("Throw", fields))
+    }
+
+    fn visit_break(&mut self, target: Rc<Break>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("keyword", self.token(&target.keyword)),
+            ("label", self.optional_token(&target.label)),
+            ("value", self.expr(&target.value)),
+        ];
+        Ok(self.node("Break", fields))
+    }
+
+    fn visit_print(&mut self, target: Rc<Print>) -> Result<String, RatexError> {
+        let fields = vec![("expr", self.expr(&target.expr))];
+        Ok(self.node("Print", fields))
+    }
+
+    fn visit_return(&mut self, target: Rc<Return>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("keyword", self.token(&target.keyword)),
+            ("value", self.expr(&target.value)),
+        ];
+        Ok(self.node("Return", fields))
+    }
+
+    fn visit_var(&mut self, target: Rc<Var>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("name", self.token(&target.name)),
+            ("initialiser", self.expr(&target.initialiser)),
+            ("constant", target.constant.to_string()),
+        ];
+        Ok(self.node("Var", fields))
+    }
+
+    fn visit_var_tuple(&mut self, target: Rc<VarTuple>) -> Result<String, RatexError> {
+        let fields = vec![
+            ("paren", self.token(&target.paren)),
+            ("names", self.tokens(&target.names)),
+            ("initialiser", self.expr(&target.initialiser)),
+            ("constant", target.constant.to_string()),
+        ];
+        Ok(self.node("VarTuple", fields))
+    }
+}
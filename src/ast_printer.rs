@@ -0,0 +1,437 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{
+        Assign, AssignTuple, Binary, Block, Break, Call, Chain, Class, Expr, ExprAccept,
+        ExprVisitor, Expression, For, Fun, Get, Grouping, If, Index, IndexSet, Invariant, Lambda,
+        ListLiteral, Literal, Logical, Loop, Match, Object, Print, Return, Set, Slice, SliceSet,
+        Stmt, StmtAccept, StmtVisitor, This, Throw, Try, TupleLiteral, TypeCheck, Unary, Var,
+        VarTuple, Variable, Variant, While, With, Yield,
+    },
+    error::RatexError,
+    token::RatexToken,
+};
+
+pub fn print_stmt(stmt: &Stmt) -> String {
+    AstPrinter {}.stmt(stmt)
+}
+
+struct AstPrinter {}
+
+impl AstPrinter {
+    fn expr(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Empty => "()".to_owned(),
+            _ => expr.accept(self).unwrap_or_default(),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> String {
+        match stmt {
+            Stmt::Empty => "()".to_owned(),
+            _ => stmt.accept(self).unwrap_or_default(),
+        }
+    }
+
+    // A match pattern, where Empty is the `_` wildcard and a Call is a
+    // constructor and the patterns for its fields.
+    fn pattern(&mut self, pattern: &Expr) -> String {
+        match pattern {
+            Expr::Empty => "_".to_owned(),
+            Expr::Call(call) => {
+                let name = self.expr(&call.callee);
+                let parts = call.arguments.iter().map(|p| self.pattern(p)).collect();
+                self.parenthesize(&name, parts)
+            }
+            _ => self.expr(pattern),
+        }
+    }
+
+    fn parenthesize(&self, name: &str, parts: Vec<String>) -> String {
+        let mut out = format!("({name}");
+
+        for part in parts {
+            out.push(' ');
+            out.push_str(&part);
+        }
+
+        out.push(')');
+        out
+    }
+
+    fn labelled(&self, label: &Option<RatexToken>, node: String) -> String {
+        match label {
+            Some(label) => format!("{}: {}", label.lexeme, node),
+            None => node,
+        }
+    }
+
+    fn params(&self, params: &[RatexToken]) -> String {
+        let names: Vec<&str> = params.iter().map(|p| p.lexeme.as_str()).collect();
+        format!("({})", names.join(" "))
+    }
+
+    fn function_params(
+        &mut self,
+        params: &[RatexToken],
+        types: &[Option<RatexToken>],
+        defaults: &[Rc<Expr>],
+        variadic: bool,
+    ) -> String {
+        let mut names: Vec<String> = params
+            .iter()
+            .zip(types)
+            .zip(defaults)
+            .map(|((p, t), d)| {
+                let name = match t {
+                    Some(t) => format!("{}: {}", p.lexeme, t.lexeme),
+                    None => p.lexeme.clone(),
+                };
+
+                match **d {
+                    Expr::Empty => name,
+                    _ => format!("(= {} {})", name, self.expr(d)),
+                }
+            })
+            .collect();
+
+        if let (true, Some(rest)) = (variadic, names.last_mut()) {
+            rest.insert_str(0, "...");
+        }
+
+        format!("({})", names.join(" "))
+    }
+
+    fn body(&mut self, statements: &[Rc<Stmt>]) -> Vec<String> {
+        statements.iter().map(|s| self.stmt(s)).collect()
+    }
+}
+
+impl ExprVisitor<String> for AstPrinter {
+    fn visit_binary(&mut self, target: Rc<Binary>) -> Result<String, RatexError> {
+        let parts = vec![self.expr(&target.left), self.expr(&target.right)];
+        Ok(self.parenthesize(&target.operator.lexeme, parts))
+    }
+
+    fn visit_chain(&mut self, target: Rc<Chain>) -> Result<String, RatexError> {
+        let mut parts = vec![self.expr(&target.operands[0])];
+
+        for (operator, operand) in target.operators.iter().zip(&target.operands[1..]) {
+            parts.push(operator.lexeme.clone());
+            parts.push(self.expr(operand));
+        }
+
+        Ok(self.parenthesize("chain", parts))
+    }
+
+    fn visit_logical(&mut self, target: Rc<Logical>) -> Result<String, RatexError> {
+        let parts = vec![self.expr(&target.left), self.expr(&target.right)];
+        Ok(self.parenthesize(&target.operator.lexeme, parts))
+    }
+
+    fn visit_set(&mut self, target: Rc<Set>) -> Result<String, RatexError> {
+        let parts = vec![
+            self.expr(&target.object),
+            target.name.lexeme.clone(),
+            self.expr(&target.value),
+        ];
+        Ok(self.parenthesize("set", parts))
+    }
+
+    fn visit_this(&mut self, _: Rc<This>) -> Result<String, RatexError> {
+        Ok("this".to_owned())
+    }
+
+    fn visit_unary(&mut self, target: Rc<Unary>) -> Result<String, RatexError> {
+        let parts = vec![self.expr(&target.right)];
+        Ok(self.parenthesize(&target.operator.lexeme, parts))
+    }
+
+    fn visit_literal(&mut self, target: Rc<Literal>) -> Result<String, RatexError> {
+        match &target.value {
+            Object::String(s) => Ok(format!("\"{s}\"")),
+            Object::Nil => Ok("nil".to_owned()),
+            value => Ok(value.to_string()),
+        }
+    }
+
+    fn visit_grouping(&mut self, target: Rc<Grouping>) -> Result<String, RatexError> {
+        let parts = vec![self.expr(&target.expr)];
+        Ok(self.parenthesize("group", parts))
+    }
+
+    fn visit_variable(&mut self, target: Rc<Variable>) -> Result<String, RatexError> {
+        Ok(target.name.lexeme.clone())
+    }
+
+    fn visit_assign(&mut self, target: Rc<Assign>) -> Result<String, RatexError> {
+        let parts = vec![target.name.lexeme.clone(), self.expr(&target.value)];
+        Ok(self.parenthesize("=", parts))
+    }
+
+    fn visit_assign_tuple(&mut self, target: Rc<AssignTuple>) -> Result<String, RatexError> {
+        let parts = vec![self.params(&target.names()), self.expr(&target.value)];
+        Ok(self.parenthesize("=", parts))
+    }
+
+    fn visit_call(&mut self, target: Rc<Call>) -> Result<String, RatexError> {
+        let mut parts = vec![self.expr(&target.callee)];
+
+        for argument in &target.arguments {
+            parts.push(self.expr(argument));
+        }
+
+        Ok(self.parenthesize("call", parts))
+    }
+
+    fn visit_get(&mut self, target: Rc<Get>) -> Result<String, RatexError> {
+        let parts = vec![self.expr(&target.object), target.name.lexeme.clone()];
+        Ok(self.parenthesize(".", parts))
+    }
+
+    fn visit_lambda(&mut self, target: Rc<Lambda>) -> Result<String, RatexError> {
+        let mut parts = Vec::new();
+
+        if let Some(name) = &target.name {
+            parts.push(name.lexeme.clone());
+        }
+
+        parts.push(self.function_params(
+            &target.params,
+            &target.param_types,
+            &target.defaults,
+            target.variadic,
+        ));
+        parts.extend(self.body(&target.body));
+        Ok(self.parenthesize("lambda", parts))
+    }
+
+    fn visit_type_check(&mut self, target: Rc<TypeCheck>) -> Result<String, RatexError> {
+        let parts = vec![self.expr(&target.object), self.expr(&target.class)];
+        Ok(self.parenthesize("is", parts))
+    }
+
+    fn visit_loop(&mut self, target: Rc<Loop>) -> Result<String, RatexError> {
+        Ok(self.stmt(&target.statement))
+    }
+
+    fn visit_match(&mut self, target: Rc<Match>) -> Result<String, RatexError> {
+        let mut parts = vec![self.expr(&target.scrutinee)];
+
+        for (pattern, value) in &target.arms {
+            let pattern = self.pattern(pattern);
+            let value = self.expr(value);
+            parts.push(self.parenthesize(&pattern, vec![value]));
+        }
+
+        Ok(self.parenthesize("match", parts))
+    }
+
+    fn visit_invariant(&mut self, target: Rc<Invariant>) -> Result<String, RatexError> {
+        let parts = vec![self.expr(&target.expr)];
+        Ok(self.parenthesize("invariant", parts))
+    }
+
+    fn visit_list_literal(&mut self, target: Rc<ListLiteral>) -> Result<String, RatexError> {
+        let parts = target.elements.iter().map(|e| self.expr(e)).collect();
+        Ok(self.parenthesize("list", parts))
+    }
+
+    fn visit_tuple_literal(&mut self, target: Rc<TupleLiteral>) -> Result<String, RatexError> {
+        let parts = target.elements.iter().map(|e| self.expr(e)).collect();
+        Ok(self.parenthesize("tuple", parts))
+    }
+
+    fn visit_index(&mut self, target: Rc<Index>) -> Result<String, RatexError> {
+        let parts = vec![self.expr(&target.object), self.expr(&target.index)];
+        Ok(self.parenthesize("[]", parts))
+    }
+
+    fn visit_index_set(&mut self, target: Rc<IndexSet>) -> Result<String, RatexError> {
+        let parts = vec![
+            self.expr(&target.object),
+            self.expr(&target.index),
+            self.expr(&target.value),
+        ];
+        Ok(self.parenthesize("[]=", parts))
+    }
+
+    fn visit_slice(&mut self, target: Rc<Slice>) -> Result<String, RatexError> {
+        let parts = vec![
+            self.expr(&target.object),
+            self.expr(&target.start),
+            self.expr(&target.end),
+        ];
+        Ok(self.parenthesize("[:]", parts))
+    }
+
+    fn visit_slice_set(&mut self, target: Rc<SliceSet>) -> Result<String, RatexError> {
+        let parts = vec![
+            self.expr(&target.object),
+            self.expr(&target.start),
+            self.expr(&target.end),
+            self.expr(&target.value),
+        ];
+        Ok(self.parenthesize("[:]=", parts))
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter {
+    fn visit_block(&mut self, target: Rc<Block>) -> Result<String, RatexError> {
+        let parts = self.body(&target.statements);
+        Ok(self.parenthesize("block", parts))
+    }
+
+    fn visit_class(&mut self, target: Rc<Class>) -> Result<String, RatexError> {
+        let mut parts = vec![target.name.lexeme.clone()];
+
+        if !target.fields.is_empty() {
+            parts.push(self.params(&target.fields));
+        }
+
+        parts.extend(self.body(&target.methods));
+        Ok(self.parenthesize("class", parts))
+    }
+
+    fn visit_variant(&mut self, target: Rc<Variant>) -> Result<String, RatexError> {
+        let mut parts = vec![target.name.lexeme.clone()];
+        parts.extend(self.body(&target.constructors));
+        Ok(self.parenthesize("variant", parts))
+    }
+
+    fn visit_expression(&mut self, target: Rc<Expression>) -> Result<String, RatexError> {
+        let parts = vec![self.expr(&target.expr)];
+        Ok(self.parenthesize(";", parts))
+    }
+
+    fn visit_if(&mut self, target: Rc<If>) -> Result<String, RatexError> {
+        let mut parts = vec![self.expr(&target.condition), self.stmt(&target.then_stmt)];
+
+        if *target.else_stmt != Stmt::Empty {
+            parts.push(self.stmt(&target.else_stmt));
+        }
+
+        Ok(self.parenthesize("if", parts))
+    }
+
+    fn visit_fun(&mut self, target: Rc<Fun>) -> Result<String, RatexError> {
+        let mut parts = vec![
+            target.name.lexeme.clone(),
+            self.function_params(
+                &target.params,
+                &target.param_types,
+                &target.defaults,
+                target.variadic,
+            ),
+        ];
+
+        if let Some(return_type) = &target.return_type {
+            parts.push(format!(": {}", return_type.lexeme));
+        }
+
+        parts.extend(self.body(&target.body));
+        Ok(self.parenthesize("fun", parts))
+    }
+
+    fn visit_while(&mut self, target: Rc<While>) -> Result<String, RatexError> {
+        let mut parts = vec![self.expr(&target.condition), self.stmt(&target.body)];
+
+        if *target.else_stmt != Stmt::Empty {
+            parts.push(self.stmt(&target.else_stmt));
+        }
+
+        Ok(self.labelled(&target.label, self.parenthesize("while", parts)))
+    }
+
+    fn visit_for(&mut self, target: Rc<For>) -> Result<String, RatexError> {
+        let mut parts = vec![
+            self.stmt(&target.initialiser),
+            self.expr(&target.condition),
+            self.expr(&target.increment),
+            self.stmt(&target.body),
+        ];
+
+        if *target.else_stmt != Stmt::Empty {
+            parts.push(self.stmt(&target.else_stmt));
+        }
+
+        Ok(self.labelled(&target.label, self.parenthesize("for", parts)))
+    }
+
+    fn visit_yield(&mut self, _: Rc<Yield>) -> Result<String, RatexError> {
+        Ok("(yield)".to_owned())
+    }
+
+    fn visit_try(&mut self, target: Rc<Try>) -> Result<String, RatexError> {
+        let body = self.body(&target.body);
+        let handler = self.body(&target.handler);
+        let parts = vec![
+            self.parenthesize("block", body),
+            target.name.lexeme.clone(),
+            self.parenthesize("block", handler),
+        ];
+        Ok(self.parenthesize("try", parts))
+    }
+
+    fn visit_with(&mut self, target: Rc<With>) -> Result<String, RatexError> {
+        let body = self.body(&target.body);
+        let parts = vec![
+            target.name.lexeme.clone(),
+            self.expr(&target.resource),
+            self.parenthesize("block", body),
+        ];
+        Ok(self.parenthesize("with", parts))
+    }
+
+    fn visit_throw(&mut self, target: Rc<Throw>) -> Result<String, RatexError> {
+        let parts = vec![self.expr(&target.value)];
+        Ok(self.parenthesize("throw", parts))
+    }
+
+    fn visit_break(&mut self, target: Rc<Break>) -> Result<String, RatexError> {
+        let mut parts = Vec::new();
+
+        if let Some(label) = &target.label {
+            parts.push(label.lexeme.clone());
+        }
+
+        if *target.value != Expr::Empty {
+            parts.push(self.expr(&target.value));
+        }
+
+        Ok(self.parenthesize("break", parts))
+    }
+
+    fn visit_print(&mut self, target: Rc<Print>) -> Result<String, RatexError> {
+        let parts = vec![self.expr(&target.expr)];
+        Ok(self.parenthesize("print", parts))
+    }
+
+    fn visit_return(&mut self, target: Rc<Return>) -> Result<String, RatexError> {
+        let mut parts = vec![];
+
+        if *target.value != Expr::Empty {
+            parts.push(self.expr(&target.value));
+        }
+
+        Ok(self.parenthesize("return", parts))
+    }
+
+    fn visit_var(&mut self, target: Rc<Var>) -> Result<String, RatexError> {
+        let mut parts = vec![target.name.lexeme.clone()];
+
+        if *target.initialiser != Expr::Empty {
+            parts.push(self.expr(&target.initialiser));
+        }
+
+        let keyword = if target.constant { "const" } else { "var" };
+        Ok(self.parenthesize(keyword, parts))
+    }
+
+    fn visit_var_tuple(&mut self, target: Rc<VarTuple>) -> Result<String, RatexError> {
+        let parts = vec![self.params(&target.names), self.expr(&target.initialiser)];
+
+        let keyword = if target.constant { "const" } else { "var" };
+        Ok(self.parenthesize(keyword, parts))
+    }
+}
@@ -7,34 +7,108 @@ use crate::{
     interpreter::RatexInterpreter,
 };
 
-#[derive(Debug, PartialEq, Clone)]
+type MethodCache = RefCell<HashMap<String, Option<Rc<RefCell<RatexFunction>>>>>;
+
+// The variant type a constructor class belongs to, so a match over it can
+// tell which constructors it leaves out.
+#[derive(Debug, PartialEq)]
+pub struct RatexVariant {
+    pub name: String,
+    pub constructors: Vec<String>,
+}
+
+#[derive(Debug)]
 pub struct RatexClass {
     name: String,
+    fields: Vec<String>,
+    variant: Option<Rc<RatexVariant>>,
     methods: HashMap<String, Rc<RefCell<RatexFunction>>>,
+    // lookups are remembered, misses included, so hooks like __getattr__ that
+    // most classes don't define are only searched for once
+    method_cache: MethodCache,
 }
 
 impl RatexClass {
-    pub fn new(name: String, methods: HashMap<String, Rc<RefCell<RatexFunction>>>) -> Self {
-        RatexClass { name, methods }
+    pub fn new(
+        name: String,
+        fields: Vec<String>,
+        methods: HashMap<String, Rc<RefCell<RatexFunction>>>,
+        variant: Option<Rc<RatexVariant>>,
+    ) -> Self {
+        RatexClass {
+            name,
+            fields,
+            variant,
+            methods,
+            method_cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    pub fn variant(&self) -> Option<&Rc<RatexVariant>> {
+        self.variant.as_ref()
     }
 
     fn find_method(&self, name: &String) -> Option<Rc<RefCell<RatexFunction>>> {
-        if let Some(method) = self.methods.get(name) {
-            let func = Rc::clone(method);
-            return Some(func);
+        if let Some(cached) = self.method_cache.borrow().get(name) {
+            return cached.clone();
         }
 
-        None
+        let method = self.methods.get(name).map(Rc::clone);
+
+        self.method_cache
+            .borrow_mut()
+            .insert(name.clone(), method.clone());
+
+        method
     }
 }
 
-impl RatexCallable for RatexClass {
-    fn call(&self, _: &mut RatexInterpreter, _: Vec<Object>) -> Result<Object, RatexError> {
-        Ok(Object::Instance(RatexInstance::new(self.clone())))
+// Implemented on the Rc so that instances can share the class they were
+// made from.
+impl RatexCallable for Rc<RatexClass> {
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        let instance = RatexInstance::new(Rc::clone(self));
+
+        // without an explicit init, the fields listed after the class name
+        // act as the constructor's parameters
+        if let Some(init) = RatexInstance::bound_method(&instance, "init") {
+            interpreter.call_function(init, arguments)?;
+        } else {
+            for (field, value) in self.fields.iter().zip(arguments) {
+                RatexInstance::set(&instance, field.clone(), value, interpreter)?;
+            }
+        }
+
+        Ok(Object::Instance(instance))
     }
 
     fn arity(&self) -> Result<usize, RatexError> {
-        Ok(0)
+        match self.find_method(&"init".to_string()) {
+            Some(init) => init.borrow().arity(),
+            None => Ok(self.fields.len()),
+        }
+    }
+
+    fn min_arity(&self) -> Result<usize, RatexError> {
+        match self.find_method(&"init".to_string()) {
+            Some(init) => init.borrow().min_arity(),
+            None => Ok(self.fields.len()),
+        }
+    }
+
+    fn variadic(&self) -> bool {
+        match self.find_method(&"init".to_string()) {
+            Some(init) => init.borrow().variadic(),
+            None => false,
+        }
     }
 
     fn name(&self) -> String {
@@ -42,17 +116,21 @@ impl RatexCallable for RatexClass {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub struct RatexInstance {
-    klass: RatexClass,
+    klass: Rc<RatexClass>,
     fields: HashMap<String, Object>,
+    in_getattr: bool,
+    in_setattr: bool,
 }
 
 impl RatexInstance {
-    pub fn new(klass: RatexClass) -> Rc<RefCell<Self>> {
+    pub fn new(klass: Rc<RatexClass>) -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(RatexInstance {
             klass,
             fields: HashMap::new(),
+            in_getattr: false,
+            in_setattr: false,
         }))
     }
 
@@ -60,23 +138,109 @@ impl RatexInstance {
         self.klass.name()
     }
 
-    pub fn get(&self, name: String) -> Result<Object, RatexError> {
-        if let Some(value) = self.fields.get(&name) {
+    // Every value of a class shares one allocation, so comparing pointers
+    // tells whether two class values come from the same declaration, even
+    // when another class has the same name and methods.
+    pub fn is_instance_of(&self, klass: &Rc<RatexClass>) -> bool {
+        Rc::ptr_eq(&self.klass, klass)
+    }
+
+    // A field's value without going through __getattr__, for patterns that
+    // look inside an instance.
+    pub fn field(&self, name: &str) -> Option<Object> {
+        self.fields.get(name).cloned()
+    }
+
+    pub fn get(
+        instance: &Rc<RefCell<Self>>,
+        name: String,
+        interpreter: &mut RatexInterpreter,
+    ) -> Result<Object, RatexError> {
+        if let Some(value) = instance.borrow().fields.get(&name) {
             return Ok(value.clone());
         }
 
-        if let Some(method) = self.klass.find_method(&name) {
-            method.as_ref().borrow_mut().bind(self.clone());
+        let method = instance.borrow().klass.find_method(&name);
+
+        if let Some(method) = method {
+            return Ok(Object::Function(method.borrow().bind(Rc::clone(instance))));
+        }
 
-            return Ok(Object::Function(method));
+        // __getattr__ only sees names that aren't fields or methods, and a
+        // missing field read from inside it is an error rather than recursion
+        if !instance.borrow().in_getattr {
+            let hook = instance
+                .borrow()
+                .klass
+                .find_method(&"__getattr__".to_string());
+
+            if let Some(hook) = hook {
+                instance.borrow_mut().in_getattr = true;
+                let result = Self::call_hook(instance, hook, vec![Object::from(name)], interpreter);
+                instance.borrow_mut().in_getattr = false;
+
+                return result;
+            }
         }
 
         Err(RatexError {
             source: RatexErrorType::AccessUnknownField(name),
+            backtrace: None,
         })
     }
 
-    pub fn set(&mut self, name: String, value: Object) {
-        self.fields.insert(name, value);
+    pub fn set(
+        instance: &Rc<RefCell<Self>>,
+        name: String,
+        value: Object,
+        interpreter: &mut RatexInterpreter,
+    ) -> Result<(), RatexError> {
+        let intercept = {
+            let inst = instance.borrow();
+            !inst.in_setattr && !inst.fields.contains_key(&name)
+        };
+
+        // writes made from inside __setattr__ go straight to the fields so
+        // that it can store the value somewhere without calling itself again
+        if intercept {
+            let hook = instance
+                .borrow()
+                .klass
+                .find_method(&"__setattr__".to_string());
+
+            if let Some(hook) = hook {
+                instance.borrow_mut().in_setattr = true;
+                let result =
+                    Self::call_hook(instance, hook, vec![Object::from(name), value], interpreter);
+                instance.borrow_mut().in_setattr = false;
+
+                return result.map(|_| ());
+            }
+        }
+
+        instance.borrow_mut().fields.insert(name, value);
+
+        Ok(())
+    }
+
+    pub fn bound_method(
+        instance: &Rc<RefCell<Self>>,
+        name: &str,
+    ) -> Option<Rc<RefCell<RatexFunction>>> {
+        let method = instance.borrow().klass.find_method(&name.to_string())?;
+        let bound = method.borrow().bind(Rc::clone(instance));
+
+        Some(bound)
+    }
+
+    fn call_hook(
+        instance: &Rc<RefCell<Self>>,
+        hook: Rc<RefCell<RatexFunction>>,
+        arguments: Vec<Object>,
+        interpreter: &mut RatexInterpreter,
+    ) -> Result<Object, RatexError> {
+        let bound = hook.borrow().bind(Rc::clone(instance));
+
+        interpreter.call_function(bound, arguments)
     }
 }
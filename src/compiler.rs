@@ -0,0 +1,510 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{
+        Assign, AssignTuple, Binary, Block, Break, Call, Chain, Class, Expr, ExprAccept,
+        ExprVisitor, Expression, For, Fun, Get, Grouping, If, Index, IndexSet, Invariant, Lambda,
+        ListLiteral, Literal, Logical, Loop, Match, Object, Print, Return, Set, Slice, SliceSet,
+        Stmt, StmtAccept, StmtVisitor, This, Throw, Try, TupleLiteral, TypeCheck, Unary, Var,
+        VarTuple, Variable, Variant, While, With, Yield,
+    },
+    error::{RatexError, RatexErrorType},
+    token::{RatexToken, RatexTokenType as RXTT},
+};
+
+// Jump targets are absolute indexes into the instruction list. JumpIfFalse
+// leaves the condition on the stack, so the code after it has to pop it.
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    Constant(Object),
+    Pop,
+    DefineGlobal(String),
+    GetGlobal(String),
+    SetGlobal(String),
+    GetLocal(usize),
+    SetLocal(usize),
+    Binary(RatexToken),
+    // like Binary, but keeps the right operand under the result for the
+    // next comparison in a chain
+    Compare(RatexToken),
+    Swap,
+    Unary(RatexToken),
+    Print,
+    Jump(usize),
+    JumpIfFalse(usize),
+    Loop(usize),
+    Call(usize, u32),
+}
+
+// Lowers a program to instructions for the VM. Only the procedural core of the
+// language is covered: functions, classes and lists are reported as
+// unsupported and still need the tree-walking interpreter.
+pub fn compile(statements: &[Rc<Stmt>]) -> Result<Vec<Instruction>, RatexError> {
+    let mut compiler = Compiler {
+        code: Vec::new(),
+        locals: Vec::new(),
+        depth: 0,
+        loops: Vec::new(),
+    };
+
+    for statement in statements {
+        compiler.stmt(statement)?;
+    }
+
+    Ok(compiler.code)
+}
+
+struct Local {
+    name: String,
+    depth: usize,
+}
+
+struct LoopState {
+    locals: usize,
+    breaks: Vec<usize>,
+}
+
+struct Compiler {
+    code: Vec<Instruction>,
+    // locals live on the VM stack in declaration order, so a local's index
+    // here is also its stack slot
+    locals: Vec<Local>,
+    depth: usize,
+    loops: Vec<LoopState>,
+}
+
+impl Compiler {
+    fn expr(&mut self, expr: &Expr) -> Result<(), RatexError> {
+        match expr {
+            Expr::Empty => {
+                self.emit(Instruction::Constant(Object::Nil));
+                Ok(())
+            }
+            _ => expr.accept(self),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), RatexError> {
+        match stmt {
+            Stmt::Empty => Ok(()),
+            _ => stmt.accept(self),
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.code.push(instruction);
+        self.code.len() - 1
+    }
+
+    fn patch_jump(&mut self, at: usize) {
+        let target = self.code.len();
+
+        match &mut self.code[at] {
+            Instruction::Jump(to) | Instruction::JumpIfFalse(to) => *to = target,
+            _ => unreachable!("only jumps can be patched"),
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.depth -= 1;
+
+        while self.locals.last().is_some_and(|l| l.depth > self.depth) {
+            self.locals.pop();
+            self.emit(Instruction::Pop);
+        }
+    }
+
+    // Compiles the loop's else branch, which the loop falls into when its
+    // condition is false, then points the loop's breaks past it. The loop is
+    // finished first, so a break in the else leaves the loop around it.
+    fn end_loop(&mut self, else_stmt: &Stmt) -> Result<(), RatexError> {
+        let state = self.loops.pop();
+
+        self.stmt(else_stmt)?;
+
+        if let Some(state) = state {
+            for jump in state.breaks {
+                self.patch_jump(jump);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resolve_local(&self, name: &str) -> Option<usize> {
+        self.locals.iter().rposition(|l| l.name == name)
+    }
+
+    fn unsupported(&self, what: &str) -> Result<(), RatexError> {
+        Err(RatexError {
+            source: RatexErrorType::NotCompilable(what.to_owned()),
+            backtrace: None,
+        })
+    }
+}
+
+impl ExprVisitor<()> for Compiler {
+    fn visit_binary(&mut self, target: Rc<Binary>) -> Result<(), RatexError> {
+        self.expr(&target.left)?;
+        self.expr(&target.right)?;
+        self.emit(Instruction::Binary(target.operator.clone()));
+        Ok(())
+    }
+
+    // Each comparison but the last leaves its right operand on the stack for
+    // the next one. A false comparison jumps to the end, dropping the operand
+    // left under it.
+    fn visit_chain(&mut self, target: Rc<Chain>) -> Result<(), RatexError> {
+        let last = target.operators.len() - 1;
+        let mut failed = Vec::new();
+
+        self.expr(&target.operands[0])?;
+
+        for (i, operator) in target.operators.iter().enumerate() {
+            self.expr(&target.operands[i + 1])?;
+
+            if i == last {
+                self.emit(Instruction::Binary(operator.clone()));
+            } else {
+                self.emit(Instruction::Compare(operator.clone()));
+                failed.push(self.emit(Instruction::JumpIfFalse(0)));
+                self.emit(Instruction::Pop);
+            }
+        }
+
+        let end = self.emit(Instruction::Jump(0));
+
+        for jump in failed {
+            self.patch_jump(jump);
+        }
+
+        self.emit(Instruction::Swap);
+        self.emit(Instruction::Pop);
+        self.patch_jump(end);
+
+        Ok(())
+    }
+
+    fn visit_logical(&mut self, target: Rc<Logical>) -> Result<(), RatexError> {
+        self.expr(&target.left)?;
+
+        match target.operator.token_type {
+            RXTT::And => {
+                let end = self.emit(Instruction::JumpIfFalse(0));
+                self.emit(Instruction::Pop);
+                self.expr(&target.right)?;
+                self.patch_jump(end);
+            }
+            RXTT::Or => {
+                let right = self.emit(Instruction::JumpIfFalse(0));
+                let end = self.emit(Instruction::Jump(0));
+                self.patch_jump(right);
+                self.emit(Instruction::Pop);
+                self.expr(&target.right)?;
+                self.patch_jump(end);
+            }
+            _ => {
+                return Err(RatexError {
+                    source: RatexErrorType::InvalidLogicalOperation(target.operator.line),
+                    backtrace: None,
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    fn visit_set(&mut self, _: Rc<Set>) -> Result<(), RatexError> {
+        self.unsupported("field assignment")
+    }
+
+    fn visit_this(&mut self, _: Rc<This>) -> Result<(), RatexError> {
+        self.unsupported("this")
+    }
+
+    fn visit_unary(&mut self, target: Rc<Unary>) -> Result<(), RatexError> {
+        self.expr(&target.right)?;
+        self.emit(Instruction::Unary(target.operator.clone()));
+        Ok(())
+    }
+
+    fn visit_literal(&mut self, target: Rc<Literal>) -> Result<(), RatexError> {
+        self.emit(Instruction::Constant(target.value.clone()));
+        Ok(())
+    }
+
+    fn visit_grouping(&mut self, target: Rc<Grouping>) -> Result<(), RatexError> {
+        self.expr(&target.expr)
+    }
+
+    fn visit_variable(&mut self, target: Rc<Variable>) -> Result<(), RatexError> {
+        let instruction = match self.resolve_local(&target.name.lexeme) {
+            Some(slot) => Instruction::GetLocal(slot),
+            None => Instruction::GetGlobal(target.name.lexeme.clone()),
+        };
+
+        self.emit(instruction);
+        Ok(())
+    }
+
+    fn visit_assign(&mut self, target: Rc<Assign>) -> Result<(), RatexError> {
+        self.expr(&target.value)?;
+
+        let instruction = match self.resolve_local(&target.name.lexeme) {
+            Some(slot) => Instruction::SetLocal(slot),
+            None => Instruction::SetGlobal(target.name.lexeme.clone()),
+        };
+
+        self.emit(instruction);
+        Ok(())
+    }
+
+    fn visit_assign_tuple(&mut self, _: Rc<AssignTuple>) -> Result<(), RatexError> {
+        self.unsupported("tuples")
+    }
+
+    fn visit_call(&mut self, target: Rc<Call>) -> Result<(), RatexError> {
+        self.expr(&target.callee)?;
+
+        for argument in &target.arguments {
+            self.expr(argument)?;
+        }
+
+        self.emit(Instruction::Call(target.arguments.len(), target.paren.line));
+        Ok(())
+    }
+
+    fn visit_get(&mut self, _: Rc<Get>) -> Result<(), RatexError> {
+        self.unsupported("field access")
+    }
+
+    fn visit_lambda(&mut self, _: Rc<Lambda>) -> Result<(), RatexError> {
+        self.unsupported("lambdas")
+    }
+
+    fn visit_type_check(&mut self, _: Rc<TypeCheck>) -> Result<(), RatexError> {
+        self.unsupported("'is'")
+    }
+
+    fn visit_loop(&mut self, _: Rc<Loop>) -> Result<(), RatexError> {
+        self.unsupported("loops as expressions")
+    }
+
+    fn visit_match(&mut self, _: Rc<Match>) -> Result<(), RatexError> {
+        self.unsupported("match")
+    }
+
+    // the VM doesn't cache anything, so it just works the expression out
+    fn visit_invariant(&mut self, target: Rc<Invariant>) -> Result<(), RatexError> {
+        self.expr(&target.expr)
+    }
+
+    fn visit_list_literal(&mut self, _: Rc<ListLiteral>) -> Result<(), RatexError> {
+        self.unsupported("lists")
+    }
+
+    fn visit_tuple_literal(&mut self, _: Rc<TupleLiteral>) -> Result<(), RatexError> {
+        self.unsupported("tuples")
+    }
+
+    fn visit_index(&mut self, _: Rc<Index>) -> Result<(), RatexError> {
+        self.unsupported("lists")
+    }
+
+    fn visit_index_set(&mut self, _: Rc<IndexSet>) -> Result<(), RatexError> {
+        self.unsupported("lists")
+    }
+
+    fn visit_slice(&mut self, _: Rc<Slice>) -> Result<(), RatexError> {
+        self.unsupported("lists")
+    }
+
+    fn visit_slice_set(&mut self, _: Rc<SliceSet>) -> Result<(), RatexError> {
+        self.unsupported("lists")
+    }
+}
+
+impl StmtVisitor<()> for Compiler {
+    fn visit_block(&mut self, target: Rc<Block>) -> Result<(), RatexError> {
+        self.begin_scope();
+
+        for statement in &target.statements {
+            self.stmt(statement)?;
+        }
+
+        self.end_scope();
+        Ok(())
+    }
+
+    fn visit_class(&mut self, _: Rc<Class>) -> Result<(), RatexError> {
+        self.unsupported("classes")
+    }
+
+    fn visit_variant(&mut self, _: Rc<Variant>) -> Result<(), RatexError> {
+        self.unsupported("variants")
+    }
+
+    fn visit_expression(&mut self, target: Rc<Expression>) -> Result<(), RatexError> {
+        self.expr(&target.expr)?;
+        self.emit(Instruction::Pop);
+        Ok(())
+    }
+
+    fn visit_if(&mut self, target: Rc<If>) -> Result<(), RatexError> {
+        self.expr(&target.condition)?;
+
+        let else_jump = self.emit(Instruction::JumpIfFalse(0));
+        self.emit(Instruction::Pop);
+        self.stmt(&target.then_stmt)?;
+
+        let end_jump = self.emit(Instruction::Jump(0));
+        self.patch_jump(else_jump);
+        self.emit(Instruction::Pop);
+        self.stmt(&target.else_stmt)?;
+        self.patch_jump(end_jump);
+
+        Ok(())
+    }
+
+    fn visit_fun(&mut self, _: Rc<Fun>) -> Result<(), RatexError> {
+        self.unsupported("function declarations")
+    }
+
+    fn visit_while(&mut self, target: Rc<While>) -> Result<(), RatexError> {
+        let start = self.code.len();
+        self.expr(&target.condition)?;
+
+        let exit = self.emit(Instruction::JumpIfFalse(0));
+        self.emit(Instruction::Pop);
+
+        self.loops.push(LoopState {
+            locals: self.locals.len(),
+            breaks: Vec::new(),
+        });
+        self.stmt(&target.body)?;
+        self.emit(Instruction::Loop(start));
+
+        self.patch_jump(exit);
+        self.emit(Instruction::Pop);
+        self.end_loop(&target.else_stmt)?;
+
+        Ok(())
+    }
+
+    fn visit_for(&mut self, target: Rc<For>) -> Result<(), RatexError> {
+        self.begin_scope();
+        self.stmt(&target.initialiser)?;
+
+        let start = self.code.len();
+
+        match *target.condition {
+            Expr::Empty => {
+                self.emit(Instruction::Constant(Object::Bool(true)));
+            }
+            _ => self.expr(&target.condition)?,
+        }
+
+        let exit = self.emit(Instruction::JumpIfFalse(0));
+        self.emit(Instruction::Pop);
+
+        self.loops.push(LoopState {
+            locals: self.locals.len(),
+            breaks: Vec::new(),
+        });
+        self.stmt(&target.body)?;
+
+        if *target.increment != Expr::Empty {
+            self.expr(&target.increment)?;
+            self.emit(Instruction::Pop);
+        }
+
+        self.emit(Instruction::Loop(start));
+
+        self.patch_jump(exit);
+        self.emit(Instruction::Pop);
+        self.end_loop(&target.else_stmt)?;
+        self.end_scope();
+
+        Ok(())
+    }
+
+    fn visit_break(&mut self, target: Rc<Break>) -> Result<(), RatexError> {
+        if *target.value != Expr::Empty {
+            return self.unsupported("break with a value");
+        }
+
+        if target.label.is_some() {
+            return self.unsupported("labelled breaks");
+        }
+
+        let Some(locals) = self.loops.last().map(|l| l.locals) else {
+            return self.unsupported("break outside of a loop");
+        };
+
+        // drop the locals declared inside the loop body before leaving it
+        for _ in locals..self.locals.len() {
+            self.emit(Instruction::Pop);
+        }
+
+        let jump = self.emit(Instruction::Jump(0));
+
+        if let Some(state) = self.loops.last_mut() {
+            state.breaks.push(jump);
+        }
+
+        Ok(())
+    }
+
+    fn visit_yield(&mut self, _: Rc<Yield>) -> Result<(), RatexError> {
+        self.unsupported("yield")
+    }
+
+    fn visit_try(&mut self, _: Rc<Try>) -> Result<(), RatexError> {
+        self.unsupported("try")
+    }
+
+    fn visit_with(&mut self, _: Rc<With>) -> Result<(), RatexError> {
+        self.unsupported("with")
+    }
+
+    fn visit_throw(&mut self, _: Rc<Throw>) -> Result<(), RatexError> {
+        self.unsupported("throw")
+    }
+
+    fn visit_print(&mut self, target: Rc<Print>) -> Result<(), RatexError> {
+        self.expr(&target.expr)?;
+        self.emit(Instruction::Print);
+        Ok(())
+    }
+
+    fn visit_return(&mut self, _: Rc<Return>) -> Result<(), RatexError> {
+        self.unsupported("return")
+    }
+
+    fn visit_var_tuple(&mut self, _: Rc<VarTuple>) -> Result<(), RatexError> {
+        self.unsupported("tuples")
+    }
+
+    fn visit_var(&mut self, target: Rc<Var>) -> Result<(), RatexError> {
+        if target.constant {
+            return self.unsupported("constants");
+        }
+
+        self.expr(&target.initialiser)?;
+
+        if self.depth == 0 {
+            self.emit(Instruction::DefineGlobal(target.name.lexeme.clone()));
+        } else {
+            self.locals.push(Local {
+                name: target.name.lexeme.clone(),
+                depth: self.depth,
+            });
+        }
+
+        Ok(())
+    }
+}
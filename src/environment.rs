@@ -1,4 +1,9 @@
-use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt,
+    rc::Rc,
+};
 
 use crate::{
     ast::Object,
@@ -7,14 +12,28 @@ use crate::{
 
 #[derive(Clone, PartialEq)]
 pub struct Environment {
-    values: HashMap<String, Object>,
+    // in the order they were defined, which for a local scope is the order
+    // the resolver gave out slots in, so resolved lookups index straight in
+    values: Vec<Object>,
+    // the name defined at each slot, so a resolved lookup can check that the
+    // slot it was given holds the name it's after
+    names: Vec<String>,
+    // where each name's value is, for globals and other lookups by name
+    slots: HashMap<String, usize>,
+    // Names declared with `const` in this scope.
+    constants: HashSet<String>,
     enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
 impl fmt::Debug for Environment {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut builder = f.debug_struct("Environment");
-        builder.field("values", &self.values);
+        let values: HashMap<&String, &Object> = self
+            .slots
+            .iter()
+            .map(|(name, slot)| (name, &self.values[*slot]))
+            .collect();
+        builder.field("values", &values);
 
         if let Some(env) = &self.enclosing {
             let value = env.borrow();
@@ -28,81 +47,333 @@ impl fmt::Debug for Environment {
 impl Environment {
     pub fn new() -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(Environment {
-            values: HashMap::new(),
+            values: Vec::new(),
+            names: Vec::new(),
+            slots: HashMap::new(),
+            constants: HashSet::new(),
             enclosing: None,
         }))
     }
 
     pub fn new_child(parent: Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
         Rc::new(RefCell::new(Environment {
-            values: HashMap::new(),
+            values: Vec::new(),
+            names: Vec::new(),
+            slots: HashMap::new(),
+            constants: HashSet::new(),
             enclosing: Some(parent.clone()),
         }))
     }
 
-    pub fn define(&mut self, name: String, value: Object) {
-        self.values.insert(name, value);
+    // Empties the scope so its frame can be used again for another call,
+    // keeping the memory its map has already grown to.
+    pub fn reset(&mut self, enclosing: Option<Rc<RefCell<Environment>>>) {
+        self.values.clear();
+        self.names.clear();
+        self.slots.clear();
+        self.constants.clear();
+        self.enclosing = enclosing;
     }
 
-    pub fn get(&self, name: String) -> Result<Object, RatexError> {
-        match self.values.get(&name) {
-            Some(value) => Ok(value.clone()),
-            None => match &self.enclosing {
-                Some(parent) => {
-                    let a = parent.borrow().get(name)?.clone();
-                    return Ok(a);
-                }
-                None => Err(RatexError {
-                    source: RatexErrorType::UndefinedIdentifier(name),
-                }),
-            },
+    // Returns the value the name was previously bound to, if any.
+    pub fn define(&mut self, name: String, value: Object) -> Option<Object> {
+        self.constants.remove(&name);
+
+        match self.slots.get(&name) {
+            Some(slot) => Some(std::mem::replace(&mut self.values[*slot], value)),
+            None => {
+                self.slots.insert(name.clone(), self.values.len());
+                self.values.push(value);
+                self.names.push(name);
+                None
+            }
         }
     }
 
-    pub fn get_at(env: Rc<RefCell<Self>>, distance: usize, name: String) -> Object {
-        println!("{:?}", &env);
+    fn lookup(&self, name: &str) -> Option<&Object> {
+        self.slots.get(name).map(|slot| &self.values[*slot])
+    }
 
-        Self::ancestor(env, distance)
-            .borrow()
-            .values
-            .get(&name)
-            .unwrap()
-            .clone()
+    // The slot the resolver gave for the name if that's where the name is,
+    // and otherwise wherever the name is in this scope.
+    fn slot_of(&self, slot: usize, name: &str) -> Option<usize> {
+        match self.names.get(slot) {
+            Some(found) if found == name => Some(slot),
+            _ => self.slots.get(name).copied(),
+        }
     }
 
-    pub fn assign(&mut self, name: String, value: Object) -> Result<(), RatexError> {
-        if self.values.contains_key(&name) {
-            self.values.insert(name, value);
+    // Stops an already defined name from being assigned to again.
+    pub fn make_constant(&mut self, name: String) {
+        self.constants.insert(name);
+    }
+
+    fn check_not_constant(&self, name: &str) -> Result<(), RatexError> {
+        if self.constants.contains(name) {
+            Err(RatexError {
+                source: RatexErrorType::AssignToConst(name.to_string(), None),
+                backtrace: None,
+            })
         } else {
-            match &mut self.enclosing {
-                Some(parent) => {
-                    return parent.borrow_mut().assign(name, value);
-                }
-                None => {
-                    return Err(RatexError {
-                        source: RatexErrorType::UndefinedIdentifier(name),
-                    })
-                }
+            Ok(())
+        }
+    }
+
+    // Walks up the enclosing scopes in a loop rather than recursing, so a
+    // deep chain of scopes can't overflow the stack.
+    pub fn get(&self, name: String) -> Result<Object, RatexError> {
+        if let Some(value) = self.lookup(&name) {
+            return Ok(value.clone());
+        }
+
+        let mut current = self.enclosing.clone();
+
+        while let Some(env) = current {
+            let env = env.borrow();
+
+            if let Some(value) = env.lookup(&name) {
+                return Ok(value.clone());
             }
+
+            current = env.enclosing.clone();
         }
 
+        Err(RatexError {
+            source: RatexErrorType::UndefinedIdentifier(name, None),
+            backtrace: None,
+        })
+    }
+
+    // `slot` is where the resolver worked out the name would be. The name is
+    // looked up instead if the slot holds a different one, and a name that
+    // isn't in the scope at all is reported.
+    pub fn get_at(
+        env: &Rc<RefCell<Self>>,
+        distance: usize,
+        slot: usize,
+        name: &str,
+    ) -> Result<Object, RatexError> {
+        let ancestor = Self::ancestor(env, distance, name)?;
+        let ancestor = ancestor.borrow();
+
+        match ancestor.slot_of(slot, name) {
+            Some(slot) => Ok(ancestor.values[slot].clone()),
+            None => Err(resolution_error(name.to_owned())),
+        }
+    }
+
+    // Returns the value that was overwritten. Like get, it walks up the
+    // enclosing scopes in a loop.
+    pub fn assign(&mut self, name: String, value: Object) -> Result<Object, RatexError> {
+        if self.slots.contains_key(&name) {
+            return self.replace(name, value);
+        }
+
+        let mut current = self.enclosing.clone();
+
+        while let Some(env) = current {
+            let mut env = env.borrow_mut();
+
+            if env.slots.contains_key(&name) {
+                return env.replace(name, value);
+            }
+
+            current = env.enclosing.clone();
+        }
+
+        Err(RatexError {
+            source: RatexErrorType::UndefinedIdentifier(name, None),
+            backtrace: None,
+        })
+    }
+
+    fn replace(&mut self, name: String, value: Object) -> Result<Object, RatexError> {
+        self.check_not_constant(&name)?;
+        let slot = self.slots[&name];
+        Ok(std::mem::replace(&mut self.values[slot], value))
+    }
+
+    pub fn assign_at(
+        env: &Rc<RefCell<Self>>,
+        distance: usize,
+        slot: usize,
+        name: &str,
+        value: Object,
+    ) -> Result<(), RatexError> {
+        let ancestor = Self::ancestor(env, distance, name)?;
+        let mut ancestor = ancestor.borrow_mut();
+
+        let Some(slot) = ancestor.slot_of(slot, name) else {
+            return Err(resolution_error(name.to_owned()));
+        };
+
+        ancestor.check_not_constant(name)?;
+        ancestor.values[slot] = value;
         Ok(())
     }
 
-    pub fn assign_at(env: Rc<RefCell<Self>>, distance: usize, name: String, value: Object) {
-        Self::ancestor(env, distance)
-            .borrow_mut()
-            .values
-            .insert(name, value);
+    // The names and values defined in the scope `distance` levels up, in the
+    // order they were defined, or None if the chain isn't that long. Meant
+    // for showing a scope's variables, not for running code.
+    pub fn locals_at(env: &Rc<RefCell<Self>>, distance: usize) -> Option<Vec<(String, Object)>> {
+        let ancestor = Self::ancestor(env, distance, "").ok()?;
+        let bindings = ancestor.borrow().bindings();
+        Some(bindings)
     }
 
-    fn ancestor(env: Rc<RefCell<Self>>, distance: usize) -> Rc<RefCell<Self>> {
-        let mut env_ref = Rc::clone(&env);
+    // Every name that can be seen from this scope with its value, innermost
+    // scope first. A name shadowed by an inner scope is only listed once.
+    pub fn all_visible(&self) -> Vec<(String, Object)> {
+        let mut visible = self.bindings();
+        let mut seen: HashSet<String> = visible.iter().map(|(name, _)| name.clone()).collect();
+        let mut current = self.enclosing.clone();
+
+        while let Some(env) = current {
+            let env = env.borrow();
+
+            for (name, value) in env.bindings() {
+                if seen.insert(name.clone()) {
+                    visible.push((name, value));
+                }
+            }
+
+            current = env.enclosing.clone();
+        }
+
+        visible
+    }
+
+    fn bindings(&self) -> Vec<(String, Object)> {
+        self.names
+            .iter()
+            .cloned()
+            .zip(self.values.iter().cloned())
+            .collect()
+    }
+
+    // A distance the chain is too short for means the resolver and the
+    // interpreter disagree about the scopes, which is reported rather than
+    // panicking.
+    fn ancestor(
+        env: &Rc<RefCell<Self>>,
+        distance: usize,
+        name: &str,
+    ) -> Result<Rc<RefCell<Self>>, RatexError> {
+        let mut env_ref = Rc::clone(env);
 
         for _ in 0..distance {
-            env_ref = Rc::clone(Rc::clone(&env_ref).borrow_mut().enclosing.as_ref().unwrap());
+            let enclosing = env_ref.borrow().enclosing.clone();
+
+            env_ref = match enclosing {
+                Some(parent) => parent,
+                None => return Err(resolution_error(name.to_owned())),
+            };
+        }
+
+        Ok(env_ref)
+    }
+}
+
+fn resolution_error(name: String) -> RatexError {
+    RatexError {
+        source: RatexErrorType::ResolutionError(name),
+        backtrace: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a global scope with a and b, and a child scope with c and its own b
+    fn two_levels() -> Rc<RefCell<Environment>> {
+        let globals = Environment::new();
+        {
+            let mut scope = globals.borrow_mut();
+            scope.define("a".to_owned(), Object::from(1.0));
+            scope.define("b".to_owned(), Object::from(2.0));
+        }
+
+        let child = Environment::new_child(globals);
+        {
+            let mut scope = child.borrow_mut();
+            scope.define("c".to_owned(), Object::from(3.0));
+            scope.define("b".to_owned(), Object::from("inner"));
         }
 
-        return env_ref;
+        child
+    }
+
+    fn is_resolution_error<T>(result: Result<T, RatexError>, name: &str) -> bool {
+        matches!(
+            result,
+            Err(RatexError {
+                source: RatexErrorType::ResolutionError(n),
+                ..
+            }) if n == name
+        )
+    }
+
+    #[test]
+    fn bad_distances_and_slots_are_errors() {
+        let env = two_levels();
+
+        assert!(is_resolution_error(
+            Environment::get_at(&env, 5, 0, "a"),
+            "a"
+        ));
+        assert!(is_resolution_error(
+            Environment::assign_at(&env, 5, 0, "a", Object::Nil),
+            "a"
+        ));
+        assert!(is_resolution_error(
+            Environment::get_at(&env, 0, 7, "missing"),
+            "missing"
+        ));
+        assert!(is_resolution_error(
+            Environment::assign_at(&env, 1, 7, "missing", Object::Nil),
+            "missing"
+        ));
+    }
+
+    #[test]
+    fn locals_at_each_distance() {
+        let env = two_levels();
+
+        assert_eq!(
+            Environment::locals_at(&env, 0),
+            Some(vec![
+                ("c".to_owned(), Object::from(3.0)),
+                ("b".to_owned(), Object::from("inner")),
+            ])
+        );
+        assert_eq!(
+            Environment::locals_at(&env, 1),
+            Some(vec![
+                ("a".to_owned(), Object::from(1.0)),
+                ("b".to_owned(), Object::from(2.0)),
+            ])
+        );
+    }
+
+    #[test]
+    fn locals_past_the_end_of_the_chain() {
+        let env = two_levels();
+
+        assert_eq!(Environment::locals_at(&env, 2), None);
+    }
+
+    #[test]
+    fn all_visible_lists_shadowed_names_once() {
+        let env = two_levels();
+
+        assert_eq!(
+            env.borrow().all_visible(),
+            vec![
+                ("c".to_owned(), Object::from(3.0)),
+                ("b".to_owned(), Object::from("inner")),
+                ("a".to_owned(), Object::from(1.0)),
+            ]
+        );
     }
 }
@@ -1,6 +1,8 @@
 use std::{
     error::Error,
     fmt::{Debug, Display, Formatter, Result},
+    rc::Rc,
+    time::Duration,
 };
 
 use crate::ast::Object;
@@ -8,11 +10,23 @@ use crate::ast::Object;
 #[derive(Debug)]
 pub struct RatexError {
     pub source: RatexErrorType,
+    // the calls the error passed through on its way out, innermost first,
+    // as the name of each function and the line it was called on. It's
+    // boxed, and None until the error leaves a call, to keep every Result
+    // in the interpreter small.
+    #[allow(clippy::box_collection)]
+    pub backtrace: Option<Box<Vec<(String, u32)>>>,
 }
 
 impl Display for RatexError {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        write!(f, "{}", self.source)
+        write!(f, "{}", self.source)?;
+
+        for (name, line) in self.backtrace.iter().flat_map(|b| b.iter()) {
+            write!(f, "\n    in {}, called on line {}", name, line)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -25,25 +39,109 @@ impl Error for RatexError {
 #[derive(Debug)]
 pub enum RatexErrorType {
     // Interrupts
-    Break,
+    // the label of the loop to leave, or None for the innermost one. It's
+    // behind an Rc to keep every Result in the interpreter from growing.
+    Break(Option<Rc<String>>, Object),
     Return(Object),
+    UserThrow(Object),
 
     // Errors
     UnknownToken(u32, String),
     UnterminatedString(u32, String),
     UnterminatedBlockComment(u32, String),
+    MisplacedDigitSeparator(u32, String),
     UnexpectedToken(u32, String),
     ExpectedToken(u32, String),
-    UndefinedIdentifier(String),
+    // the environment doesn't know where a name was used, so the line is
+    // filled in by whoever looked it up
+    UndefinedIdentifier(String, Option<u32>),
+    // same as above
+    AssignToConst(String, Option<u32>),
+    UnusedVariable(u32, String),
     InvalidAssignment(u32),
     InvalidLogicalOperation(u32),
     InvalidFunctionCall,
-    IncompatibleArity,
-    VarInInitialiser,
+    // a call whose callee is neither a function nor a class
+    NotCallable(u32),
+    // calls made by the interpreter itself, such as to __getattr__, have no
+    // line to point at
+    IncompatibleArity {
+        name: String,
+        expected: usize,
+        // None when there's no upper limit
+        maximum: Option<usize>,
+        got: usize,
+        line: Option<u32>,
+    },
+    VarInInitialiser(u32),
     RedeclareLocalVariable(u32),
     InvalidReturnLocation,
+    FailedReturn(u32, Box<RatexError>),
     AccessUnknownField(String),
     NonInstanceSet,
+    InvalidIndex(u32),
+    IndexOutOfBounds(u32),
+    NonListSliceAssignment(u32),
+    StepLimitExceeded(u64),
+    Timeout(Duration),
+    InvalidArgument(String, String),
+    NotCompilable(String),
+    YieldOutsideTask(u32),
+    TooManyArguments(u32),
+    TooManyParameters(u32),
+    RequiredAfterDefault(u32),
+    RestNotLast(u32),
+    AssertionFailed(Option<String>),
+    DivisionByZero(u32),
+    NoMatchingArm(u32),
+    // a constructor pattern whose field count isn't the class's: the class
+    // and how many fields it has
+    InvalidPattern(u32, String, usize),
+    // a constructor pattern whose callee isn't a class
+    PatternNotAClass(u32),
+    // the variant being matched over, and its constructors no arm covers
+    NonExhaustiveMatch(u32, String, String),
+    CantUnpack(u32, usize),
+    // found by the type checker: the operation and the types of its operands
+    InvalidOperands(u32, &'static str, String, String),
+    // an argument or return value wasn't the type it was declared as. `what`
+    // says which one it was.
+    TypeMismatch {
+        what: String,
+        expected: String,
+        got: String,
+        line: u32,
+    },
+    NotAClass(u32),
+    ConversionFailed(&'static str, Box<Object>),
+    EmptyNode(&'static str),
+    // a local wasn't in the scope the resolver said it would be
+    ResolutionError(String),
+    Io(String),
+    OutputFailed(String),
+    LabelWithoutLoop(u32),
+    UndefinedLabel(u32, String),
+}
+
+impl RatexErrorType {
+    // Breaks, returns and throws on their way out to whatever handles them.
+    pub fn is_interrupt(&self) -> bool {
+        matches!(
+            self,
+            RatexErrorType::Break(..) | RatexErrorType::Return(_) | RatexErrorType::UserThrow(_)
+        )
+    }
+
+    // Errors that stop the script wherever it is, which nothing in it is
+    // allowed to catch.
+    pub fn stops_script(&self) -> bool {
+        matches!(
+            self,
+            RatexErrorType::StepLimitExceeded(_)
+                | RatexErrorType::Timeout(_)
+                | RatexErrorType::OutputFailed(_)
+        )
+    }
 }
 
 impl Display for RatexErrorType {
@@ -58,6 +156,13 @@ impl Display for RatexErrorType {
             RatexErrorType::UnterminatedBlockComment(line, index) => {
                 write!(f, "line {}, unterminated block comment: {}", line, index)
             }
+            RatexErrorType::MisplacedDigitSeparator(line, number) => {
+                write!(
+                    f,
+                    "line {}, underscores in {} must be between digits",
+                    line, number
+                )
+            }
             RatexErrorType::UnexpectedToken(line, token) => {
                 write!(f, "line {}, unexpected token '{}'", line, token)
             }
@@ -68,8 +173,20 @@ impl Display for RatexErrorType {
                     line, string
                 )
             }
-            RatexErrorType::UndefinedIdentifier(identifier) => {
-                write!(f, "tried to read undefined variable '{}'", identifier)
+            RatexErrorType::UndefinedIdentifier(identifier, Some(line)) => {
+                write!(f, "line {}: undefined variable '{}'", line, identifier)
+            }
+            RatexErrorType::UndefinedIdentifier(identifier, None) => {
+                write!(f, "undefined variable '{}'", identifier)
+            }
+            RatexErrorType::AssignToConst(name, Some(line)) => {
+                write!(f, "line {}: can't assign to constant '{}'", line, name)
+            }
+            RatexErrorType::AssignToConst(name, None) => {
+                write!(f, "can't assign to constant '{}'", name)
+            }
+            RatexErrorType::UnusedVariable(line, name) => {
+                write!(f, "line {}, local variable '{}' is never read", line, name)
             }
             RatexErrorType::InvalidAssignment(line) => {
                 write!(f, "line {}, invalid assignment", line)
@@ -80,18 +197,56 @@ impl Display for RatexErrorType {
             RatexErrorType::InvalidFunctionCall => {
                 write!(f, "invalid function call")
             }
-            RatexErrorType::IncompatibleArity => {
-                write!(f, "too many or too few arguments")
+            RatexErrorType::NotCallable(line) => {
+                write!(f, "line {}, can only call functions and classes", line)
             }
-            RatexErrorType::VarInInitialiser => {
-                write!(f, "can't read local variable in its own initialiser")
+            RatexErrorType::IncompatibleArity {
+                name,
+                expected,
+                maximum,
+                got,
+                line,
+            } => {
+                if let Some(line) = line {
+                    write!(f, "line {}, ", line)?;
+                }
+
+                let noun = if *expected == 1 {
+                    "argument"
+                } else {
+                    "arguments"
+                };
+
+                match maximum {
+                    Some(maximum) if maximum != expected => write!(
+                        f,
+                        "{} expected {} to {} arguments but got {}",
+                        name, expected, maximum, got
+                    ),
+                    Some(_) => write!(f, "{} expected {} {} but got {}", name, expected, noun, got),
+                    None => write!(
+                        f,
+                        "{} expected at least {} {} but got {}",
+                        name, expected, noun, got
+                    ),
+                }
             }
-            RatexErrorType::Break => {
+            RatexErrorType::VarInInitialiser(line) => {
+                write!(
+                    f,
+                    "line {}, can't read local variable in its own initialiser",
+                    line
+                )
+            }
+            RatexErrorType::Break(..) => {
                 write!(f, "break statement reached")
             }
             RatexErrorType::Return(_) => {
                 write!(f, "returned")
             }
+            RatexErrorType::UserThrow(value) => {
+                write!(f, "uncaught exception: {}", value)
+            }
             RatexErrorType::RedeclareLocalVariable(line) => {
                 write!(
                     f,
@@ -100,10 +255,142 @@ impl Display for RatexErrorType {
                 )
             }
             RatexErrorType::InvalidReturnLocation => write!(f, "return called outside a function"),
+            RatexErrorType::FailedReturn(line, e) => {
+                write!(f, "line {}, could not evaluate return value: {}", line, e)
+            }
             RatexErrorType::AccessUnknownField(s) => {
                 write!(f, "tried to access unknown field \"{s}\"")
             }
             RatexErrorType::NonInstanceSet => write!(f, "only class instance have fields"),
+            RatexErrorType::InvalidIndex(line) => {
+                write!(
+                    f,
+                    "line {}, only lists, tuples and strings can be indexed by whole numbers",
+                    line
+                )
+            }
+            RatexErrorType::IndexOutOfBounds(line) => {
+                write!(f, "line {}, index out of bounds", line)
+            }
+            RatexErrorType::StepLimitExceeded(max) => {
+                write!(f, "execution stopped after the limit of {} steps", max)
+            }
+            RatexErrorType::Timeout(limit) => {
+                write!(f, "execution stopped after the time limit of {:?}", limit)
+            }
+            RatexErrorType::InvalidArgument(function, reason) => {
+                write!(f, "invalid argument to {}: {}", function, reason)
+            }
+            RatexErrorType::YieldOutsideTask(line) => {
+                write!(
+                    f,
+                    "line {}, yield can only be used directly in the body of a spawned task",
+                    line
+                )
+            }
+            RatexErrorType::TooManyArguments(line) => {
+                write!(f, "line {}, can't have more than 255 arguments", line)
+            }
+            RatexErrorType::TooManyParameters(line) => {
+                write!(f, "line {}, can't have more than 255 parameters", line)
+            }
+            RatexErrorType::RequiredAfterDefault(line) => {
+                write!(
+                    f,
+                    "line {}, a parameter without a default can't follow one with a default",
+                    line
+                )
+            }
+            RatexErrorType::RestNotLast(line) => {
+                write!(
+                    f,
+                    "line {}, a rest parameter must be the last parameter",
+                    line
+                )
+            }
+            RatexErrorType::AssertionFailed(None) => write!(f, "assertion failed"),
+            RatexErrorType::AssertionFailed(Some(message)) => {
+                write!(f, "assertion failed: {}", message)
+            }
+            RatexErrorType::DivisionByZero(line) => write!(f, "line {}, division by zero", line),
+            RatexErrorType::NoMatchingArm(line) => {
+                write!(f, "line {}, no arm of the match fits the value", line)
+            }
+            RatexErrorType::InvalidPattern(line, class, fields) => {
+                write!(
+                    f,
+                    "line {}, a pattern for {} needs {} fields",
+                    line, class, fields
+                )
+            }
+            RatexErrorType::PatternNotAClass(line) => {
+                write!(f, "line {}, a constructor pattern must name a class", line)
+            }
+            RatexErrorType::NonExhaustiveMatch(line, variant, missing) => {
+                write!(
+                    f,
+                    "line {}, match over {} doesn't cover {}",
+                    line, variant, missing
+                )
+            }
+            RatexErrorType::TypeMismatch {
+                what,
+                expected,
+                got,
+                line,
+            } => {
+                write!(
+                    f,
+                    "line {}, {} is declared as {} but got {}",
+                    line, what, expected, got
+                )
+            }
+            RatexErrorType::InvalidOperands(line, operation, left, right) => {
+                write!(
+                    f,
+                    "line {}, cannot {} {} and {}",
+                    line, operation, left, right
+                )
+            }
+            RatexErrorType::CantUnpack(line, count) => {
+                write!(
+                    f,
+                    "line {}, expected a tuple of {} values to unpack",
+                    line, count
+                )
+            }
+            RatexErrorType::NotAClass(line) => {
+                write!(f, "line {}, the right side of 'is' must be a class", line)
+            }
+            RatexErrorType::ConversionFailed(expected, got) => {
+                write!(f, "expected a {} but got {}", expected, got)
+            }
+            RatexErrorType::ResolutionError(name) => {
+                write!(
+                    f,
+                    "internal error: '{}' isn't in the scope it was resolved to",
+                    name
+                )
+            }
+            RatexErrorType::EmptyNode(kind) => {
+                write!(f, "internal error: tried to visit an empty {} node", kind)
+            }
+            RatexErrorType::Io(reason) => write!(f, "could not read file: {}", reason),
+            RatexErrorType::OutputFailed(reason) => {
+                write!(f, "could not write output: {}", reason)
+            }
+            RatexErrorType::LabelWithoutLoop(line) => {
+                write!(f, "line {}, only loops can be labelled", line)
+            }
+            RatexErrorType::UndefinedLabel(line, label) => {
+                write!(f, "line {}, no enclosing loop is labelled {}", line, label)
+            }
+            RatexErrorType::NotCompilable(what) => {
+                write!(f, "the compiled backend doesn't support {}", what)
+            }
+            RatexErrorType::NonListSliceAssignment(line) => {
+                write!(f, "line {}, only a list can be assigned to a slice", line)
+            }
         }
     }
 }
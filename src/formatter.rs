@@ -0,0 +1,577 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{
+        Assign, AssignTuple, Binary, Block, Break, Call, Chain, Class, Expr, ExprAccept,
+        ExprVisitor, Expression, For, Fun, Get, Grouping, If, Index, IndexSet, Invariant, Lambda,
+        ListLiteral, Literal, Logical, Loop, Match, Object, Print, Return, Set, Slice, SliceSet,
+        Stmt, StmtAccept, StmtVisitor, This, Throw, Try, TupleLiteral, TypeCheck, Unary, Var,
+        VarTuple, Variable, Variant, While, With, Yield,
+    },
+    error::RatexError,
+    token::RatexToken,
+};
+
+const INDENT: &str = "    ";
+
+// Formats a parsed program back into source code. Comments don't survive
+// scanning, so they are lost when a file is formatted.
+pub fn format(statements: &[Rc<Stmt>]) -> String {
+    let mut formatter = Formatter { indent: 0 };
+    let mut out = String::new();
+
+    for statement in statements {
+        out.push_str(&formatter.stmt(statement));
+        out.push('\n');
+    }
+
+    out
+}
+
+struct Formatter {
+    indent: usize,
+}
+
+impl Formatter {
+    fn expr(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Empty => String::new(),
+            _ => expr.accept(self).unwrap_or_default(),
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Expr) -> String {
+        match pattern {
+            Expr::Empty => "_".to_owned(),
+            Expr::Call(call) => {
+                let fields: Vec<String> = call.arguments.iter().map(|p| self.pattern(p)).collect();
+                format!("{}({})", self.expr(&call.callee), fields.join(", "))
+            }
+            _ => self.expr(pattern),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> String {
+        match stmt {
+            Stmt::Empty => String::new(),
+            _ => stmt.accept(self).unwrap_or_default(),
+        }
+    }
+
+    fn block(&mut self, statements: &[Rc<Stmt>]) -> String {
+        if statements.is_empty() {
+            return "{}".to_owned();
+        }
+
+        self.indent += 1;
+
+        let mut out = "{\n".to_owned();
+
+        for statement in statements {
+            out.push_str(&INDENT.repeat(self.indent));
+            out.push_str(&self.stmt(statement));
+            out.push('\n');
+        }
+
+        self.indent -= 1;
+
+        out.push_str(&INDENT.repeat(self.indent));
+        out.push('}');
+        out
+    }
+
+    fn label_prefix(&self, label: &Option<RatexToken>) -> String {
+        match label {
+            Some(label) => format!("{}: ", label.lexeme),
+            None => String::new(),
+        }
+    }
+
+    // A loop's else goes after its body, the way an if's does.
+    fn loop_else(&mut self, mut out: String, else_stmt: &Rc<Stmt>) -> String {
+        if **else_stmt != Stmt::Empty {
+            out.push_str(" else ");
+            out.push_str(&self.stmt(else_stmt));
+        }
+
+        out
+    }
+
+    fn parameters(
+        &mut self,
+        params: &[RatexToken],
+        types: &[Option<RatexToken>],
+        defaults: &[Rc<Expr>],
+        variadic: bool,
+    ) -> String {
+        let mut names: Vec<String> = params
+            .iter()
+            .zip(types)
+            .zip(defaults)
+            .map(|((p, t), d)| {
+                let name = match t {
+                    Some(t) => format!("{}: {}", p.lexeme, t.lexeme),
+                    None => p.lexeme.clone(),
+                };
+
+                match **d {
+                    Expr::Empty => name,
+                    _ => format!("{} = {}", name, self.expr(d)),
+                }
+            })
+            .collect();
+
+        if let (true, Some(rest)) = (variadic, names.last_mut()) {
+            rest.insert_str(0, "...");
+        }
+
+        format!("({})", names.join(", "))
+    }
+
+    // `parameters` is the parameter list, already formatted.
+    fn function(
+        &mut self,
+        name: &str,
+        parameters: String,
+        return_type: &Option<RatexToken>,
+        body: &[Rc<Stmt>],
+    ) -> String {
+        let return_type = match return_type {
+            Some(return_type) => format!(": {}", return_type.lexeme),
+            None => String::new(),
+        };
+
+        format!("{}{}{} {}", name, parameters, return_type, self.block(body))
+    }
+}
+
+impl ExprVisitor<String> for Formatter {
+    fn visit_binary(&mut self, target: Rc<Binary>) -> Result<String, RatexError> {
+        Ok(format!(
+            "{} {} {}",
+            self.expr(&target.left),
+            target.operator.lexeme,
+            self.expr(&target.right)
+        ))
+    }
+
+    fn visit_chain(&mut self, target: Rc<Chain>) -> Result<String, RatexError> {
+        let mut out = self.expr(&target.operands[0]);
+
+        for (operator, operand) in target.operators.iter().zip(&target.operands[1..]) {
+            out.push_str(&format!(" {} {}", operator.lexeme, self.expr(operand)));
+        }
+
+        Ok(out)
+    }
+
+    fn visit_logical(&mut self, target: Rc<Logical>) -> Result<String, RatexError> {
+        Ok(format!(
+            "{} {} {}",
+            self.expr(&target.left),
+            target.operator.lexeme,
+            self.expr(&target.right)
+        ))
+    }
+
+    fn visit_set(&mut self, target: Rc<Set>) -> Result<String, RatexError> {
+        Ok(format!(
+            "{}.{} = {}",
+            self.expr(&target.object),
+            target.name.lexeme,
+            self.expr(&target.value)
+        ))
+    }
+
+    fn visit_this(&mut self, _: Rc<This>) -> Result<String, RatexError> {
+        Ok("this".to_owned())
+    }
+
+    fn visit_unary(&mut self, target: Rc<Unary>) -> Result<String, RatexError> {
+        Ok(format!(
+            "{}{}",
+            target.operator.lexeme,
+            self.expr(&target.right)
+        ))
+    }
+
+    fn visit_literal(&mut self, target: Rc<Literal>) -> Result<String, RatexError> {
+        match &target.value {
+            Object::String(s) => Ok(format!("\"{s}\"")),
+            Object::Nil => Ok("nil".to_owned()),
+            value => Ok(value.to_string()),
+        }
+    }
+
+    fn visit_grouping(&mut self, target: Rc<Grouping>) -> Result<String, RatexError> {
+        Ok(format!("({})", self.expr(&target.expr)))
+    }
+
+    fn visit_variable(&mut self, target: Rc<Variable>) -> Result<String, RatexError> {
+        Ok(target.name.lexeme.clone())
+    }
+
+    fn visit_assign(&mut self, target: Rc<Assign>) -> Result<String, RatexError> {
+        Ok(format!(
+            "{} = {}",
+            target.name.lexeme,
+            self.expr(&target.value)
+        ))
+    }
+
+    fn visit_assign_tuple(&mut self, target: Rc<AssignTuple>) -> Result<String, RatexError> {
+        Ok(format!(
+            "{} = {}",
+            names_tuple(&target.names()),
+            self.expr(&target.value)
+        ))
+    }
+
+    fn visit_call(&mut self, target: Rc<Call>) -> Result<String, RatexError> {
+        let arguments: Vec<String> = target.arguments.iter().map(|a| self.expr(a)).collect();
+
+        Ok(format!(
+            "{}({})",
+            self.expr(&target.callee),
+            arguments.join(", ")
+        ))
+    }
+
+    fn visit_get(&mut self, target: Rc<Get>) -> Result<String, RatexError> {
+        Ok(format!(
+            "{}.{}",
+            self.expr(&target.object),
+            target.name.lexeme
+        ))
+    }
+
+    fn visit_lambda(&mut self, target: Rc<Lambda>) -> Result<String, RatexError> {
+        let parameters = self.parameters(
+            &target.params,
+            &target.param_types,
+            &target.defaults,
+            target.variadic,
+        );
+
+        Ok(format!(
+            "fun {}",
+            self.function(
+                target.name.as_ref().map_or("", |name| name.lexeme.as_str()),
+                parameters,
+                &None,
+                &target.body,
+            )
+        ))
+    }
+
+    fn visit_type_check(&mut self, target: Rc<TypeCheck>) -> Result<String, RatexError> {
+        Ok(format!(
+            "{} is {}",
+            self.expr(&target.object),
+            self.expr(&target.class)
+        ))
+    }
+
+    fn visit_loop(&mut self, target: Rc<Loop>) -> Result<String, RatexError> {
+        Ok(self.stmt(&target.statement))
+    }
+
+    fn visit_match(&mut self, target: Rc<Match>) -> Result<String, RatexError> {
+        let arms: Vec<String> = target
+            .arms
+            .iter()
+            .map(|(pattern, value)| format!("{} => {}", self.pattern(pattern), self.expr(value)))
+            .collect();
+
+        Ok(format!(
+            "match {} {{ {} }}",
+            self.expr(&target.scrutinee),
+            arms.join(", ")
+        ))
+    }
+
+    // only the optimizer makes these, so there's no syntax of their own
+    fn visit_invariant(&mut self, target: Rc<Invariant>) -> Result<String, RatexError> {
+        Ok(self.expr(&target.expr))
+    }
+
+    fn visit_list_literal(&mut self, target: Rc<ListLiteral>) -> Result<String, RatexError> {
+        let elements: Vec<String> = target.elements.iter().map(|e| self.expr(e)).collect();
+        Ok(format!("[{}]", elements.join(", ")))
+    }
+
+    fn visit_tuple_literal(&mut self, target: Rc<TupleLiteral>) -> Result<String, RatexError> {
+        let elements: Vec<String> = target.elements.iter().map(|e| self.expr(e)).collect();
+        Ok(tuple(elements))
+    }
+
+    fn visit_index(&mut self, target: Rc<Index>) -> Result<String, RatexError> {
+        Ok(format!(
+            "{}[{}]",
+            self.expr(&target.object),
+            self.expr(&target.index)
+        ))
+    }
+
+    fn visit_index_set(&mut self, target: Rc<IndexSet>) -> Result<String, RatexError> {
+        Ok(format!(
+            "{}[{}] = {}",
+            self.expr(&target.object),
+            self.expr(&target.index),
+            self.expr(&target.value)
+        ))
+    }
+
+    fn visit_slice(&mut self, target: Rc<Slice>) -> Result<String, RatexError> {
+        Ok(format!(
+            "{}[{}:{}]",
+            self.expr(&target.object),
+            self.expr(&target.start),
+            self.expr(&target.end)
+        ))
+    }
+
+    fn visit_slice_set(&mut self, target: Rc<SliceSet>) -> Result<String, RatexError> {
+        Ok(format!(
+            "{}[{}:{}] = {}",
+            self.expr(&target.object),
+            self.expr(&target.start),
+            self.expr(&target.end),
+            self.expr(&target.value)
+        ))
+    }
+}
+
+impl StmtVisitor<String> for Formatter {
+    fn visit_block(&mut self, target: Rc<Block>) -> Result<String, RatexError> {
+        Ok(self.block(&target.statements))
+    }
+
+    fn visit_class(&mut self, target: Rc<Class>) -> Result<String, RatexError> {
+        let mut header = format!("class {}", target.name.lexeme);
+
+        if !target.fields.is_empty() {
+            let names: Vec<&str> = target.fields.iter().map(|f| f.lexeme.as_str()).collect();
+            header.push_str(&format!("({})", names.join(", ")));
+        }
+
+        if target.methods.is_empty() {
+            return Ok(format!("{} {{}}", header));
+        }
+
+        self.indent += 1;
+
+        let mut out = format!("{} {{\n", header);
+
+        for method in &target.methods {
+            if let Stmt::Fun(fun) = method.as_ref() {
+                let parameters =
+                    self.parameters(&fun.params, &fun.param_types, &fun.defaults, fun.variadic);
+
+                out.push_str(&INDENT.repeat(self.indent));
+                out.push_str(&self.function(
+                    &fun.name.lexeme,
+                    parameters,
+                    &fun.return_type,
+                    &fun.body,
+                ));
+                out.push('\n');
+            }
+        }
+
+        self.indent -= 1;
+
+        out.push_str(&INDENT.repeat(self.indent));
+        out.push('}');
+        Ok(out)
+    }
+
+    fn visit_variant(&mut self, target: Rc<Variant>) -> Result<String, RatexError> {
+        let constructors: Vec<String> = target
+            .constructors
+            .iter()
+            .filter_map(|c| match c.as_ref() {
+                Stmt::Class(class) if class.fields.is_empty() => Some(class.name.lexeme.clone()),
+                Stmt::Class(class) => {
+                    let names: Vec<&str> = class.fields.iter().map(|f| f.lexeme.as_str()).collect();
+                    Some(format!("{}({})", class.name.lexeme, names.join(", ")))
+                }
+                _ => None,
+            })
+            .collect();
+
+        if constructors.is_empty() {
+            return Ok(format!("variant {} {{}}", target.name.lexeme));
+        }
+
+        Ok(format!(
+            "variant {} {{ {} }}",
+            target.name.lexeme,
+            constructors.join(", ")
+        ))
+    }
+
+    fn visit_expression(&mut self, target: Rc<Expression>) -> Result<String, RatexError> {
+        Ok(format!("{};", self.expr(&target.expr)))
+    }
+
+    fn visit_if(&mut self, target: Rc<If>) -> Result<String, RatexError> {
+        let mut out = format!(
+            "if ({}) {}",
+            self.expr(&target.condition),
+            self.stmt(&target.then_stmt)
+        );
+
+        if *target.else_stmt != Stmt::Empty {
+            out.push_str(" else ");
+            out.push_str(&self.stmt(&target.else_stmt));
+        }
+
+        Ok(out)
+    }
+
+    fn visit_fun(&mut self, target: Rc<Fun>) -> Result<String, RatexError> {
+        let parameters = self.parameters(
+            &target.params,
+            &target.param_types,
+            &target.defaults,
+            target.variadic,
+        );
+
+        Ok(format!(
+            "fun {}",
+            self.function(
+                &target.name.lexeme,
+                parameters,
+                &target.return_type,
+                &target.body,
+            )
+        ))
+    }
+
+    fn visit_while(&mut self, target: Rc<While>) -> Result<String, RatexError> {
+        let out = format!(
+            "{}while ({}) {}",
+            self.label_prefix(&target.label),
+            self.expr(&target.condition),
+            self.stmt(&target.body)
+        );
+
+        Ok(self.loop_else(out, &target.else_stmt))
+    }
+
+    fn visit_for(&mut self, target: Rc<For>) -> Result<String, RatexError> {
+        let initialiser = match *target.initialiser {
+            Stmt::Empty => ";".to_owned(),
+            _ => self.stmt(&target.initialiser),
+        };
+
+        let mut clauses = format!("{} {};", initialiser, self.expr(&target.condition));
+
+        if *target.increment != Expr::Empty {
+            clauses.push(' ');
+            clauses.push_str(&self.expr(&target.increment));
+        }
+
+        let out = format!(
+            "{}for ({}) {}",
+            self.label_prefix(&target.label),
+            clauses,
+            self.stmt(&target.body)
+        );
+
+        Ok(self.loop_else(out, &target.else_stmt))
+    }
+
+    fn visit_yield(&mut self, _: Rc<Yield>) -> Result<String, RatexError> {
+        Ok("yield;".to_owned())
+    }
+
+    fn visit_try(&mut self, target: Rc<Try>) -> Result<String, RatexError> {
+        Ok(format!(
+            "try {} catch ({}) {}",
+            self.block(&target.body),
+            target.name.lexeme,
+            self.block(&target.handler)
+        ))
+    }
+
+    fn visit_with(&mut self, target: Rc<With>) -> Result<String, RatexError> {
+        Ok(format!(
+            "with ({} = {}) {}",
+            target.name.lexeme,
+            self.expr(&target.resource),
+            self.block(&target.body)
+        ))
+    }
+
+    fn visit_throw(&mut self, target: Rc<Throw>) -> Result<String, RatexError> {
+        Ok(format!("throw {};", self.expr(&target.value)))
+    }
+
+    fn visit_break(&mut self, target: Rc<Break>) -> Result<String, RatexError> {
+        let mut out = "break".to_owned();
+
+        if let Some(label) = &target.label {
+            out.push(' ');
+            out.push_str(&label.lexeme);
+        }
+
+        if *target.value != Expr::Empty {
+            out.push(' ');
+            out.push_str(&self.expr(&target.value));
+        }
+
+        out.push(';');
+        Ok(out)
+    }
+
+    fn visit_print(&mut self, target: Rc<Print>) -> Result<String, RatexError> {
+        Ok(format!("print {};", self.expr(&target.expr)))
+    }
+
+    fn visit_return(&mut self, target: Rc<Return>) -> Result<String, RatexError> {
+        match *target.value {
+            Expr::Empty => Ok("return;".to_owned()),
+            _ => Ok(format!("return {};", self.expr(&target.value))),
+        }
+    }
+
+    fn visit_var(&mut self, target: Rc<Var>) -> Result<String, RatexError> {
+        let keyword = if target.constant { "const" } else { "var" };
+
+        match *target.initialiser {
+            Expr::Empty => Ok(format!("{} {};", keyword, target.name.lexeme)),
+            _ => Ok(format!(
+                "{} {} = {};",
+                keyword,
+                target.name.lexeme,
+                self.expr(&target.initialiser)
+            )),
+        }
+    }
+
+    fn visit_var_tuple(&mut self, target: Rc<VarTuple>) -> Result<String, RatexError> {
+        let keyword = if target.constant { "const" } else { "var" };
+
+        Ok(format!(
+            "{} {} = {};",
+            keyword,
+            names_tuple(&target.names),
+            self.expr(&target.initialiser)
+        ))
+    }
+}
+
+// A tuple is written with a trailing comma when it has one element, so it
+// isn't read back as a grouping.
+fn tuple(elements: Vec<String>) -> String {
+    match elements.len() {
+        1 => format!("({},)", elements[0]),
+        _ => format!("({})", elements.join(", ")),
+    }
+}
+
+fn names_tuple(names: &[RatexToken]) -> String {
+    tuple(names.iter().map(|n| n.lexeme.clone()).collect())
+}
@@ -1,23 +1,33 @@
-use std::{
-    cell::RefCell,
-    fmt::Debug,
-    rc::Rc,
-    time::{SystemTime, UNIX_EPOCH},
-};
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, fmt::Debug, rc::Rc};
 
 use crate::{
-    ast::{Object, RatexCallable, Stmt},
+    ast::{Expr, Object, RatexCallable, Stmt, TaskParts},
     class::RatexInstance,
     environment::Environment,
     error::{RatexError, RatexErrorType},
-    interpreter::RatexInterpreter,
+    interpreter::{RatexInterpreter, Watcher},
+    tasks::Task,
+    token::RatexToken,
 };
 
-#[derive(PartialEq, Clone, Debug)]
+type SpareFrame = RefCell<Option<Rc<RefCell<Environment>>>>;
+
+#[derive(Clone, Debug)]
 pub struct RatexFunction {
     name: String,
     declaration: Rc<Stmt>,
     closure: Rc<RefCell<Environment>>,
+    // the parameter frame of a finished call that nothing kept hold of, ready
+    // for the next call. Clones and bound copies of a function share it.
+    spare_frame: Rc<SpareFrame>,
+}
+
+impl PartialEq for RatexFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.declaration == other.declaration
+            && self.closure == other.closure
+    }
 }
 
 impl RatexCallable for RatexFunction {
@@ -28,34 +38,100 @@ impl RatexCallable for RatexFunction {
     ) -> Result<Object, RatexError> {
         match &*self.declaration {
             Stmt::Fun(f) => {
-                for i in 0..f.params.len() {
-                    self.closure.borrow_mut().define(
-                        f.params.get(i).unwrap().lexeme.clone(),
-                        arguments.get(i).unwrap().clone(),
-                    );
+                let env = self.frame();
+                let mut arguments = arguments.into_iter();
+                let (params, rest) = match f.params.split_last() {
+                    Some((rest, params)) if f.variadic => (params, Some(rest)),
+                    _ => (&f.params[..], None),
+                };
+
+                // missing trailing arguments take their parameters' defaults,
+                // evaluated in the closure each time the function is called
+                for ((param, default), param_type) in
+                    params.iter().zip(&f.defaults).zip(&f.param_types)
+                {
+                    let value = match arguments.next() {
+                        Some(value) => value,
+                        None => {
+                            interpreter.evaluate_in(Rc::clone(default), Rc::clone(&self.closure))?
+                        }
+                    };
+
+                    self.check_argument(param, param_type, &value)?;
+                    env.borrow_mut().define(param.lexeme.clone(), value);
                 }
 
-                interpreter.execute_block(f.body.clone(), Rc::clone(&self.closure))?;
-                Ok(Object::Nil)
+                if let Some(rest) = rest {
+                    let extra: Vec<Object> = arguments.collect();
+
+                    for value in &extra {
+                        self.check_argument(rest, f.param_types.last().unwrap(), value)?;
+                    }
+
+                    let extra = Object::List(Rc::new(RefCell::new(extra)));
+                    env.borrow_mut().define(rest.lexeme.clone(), extra);
+                }
+
+                // a function declared to return nil doesn't give back the
+                // value of its last expression
+                let result = match &f.return_type {
+                    Some(return_type) if return_type.lexeme == "nil" => interpreter
+                        .execute_block(f.body.clone(), Rc::clone(&env))
+                        .map(|()| Object::Nil),
+                    _ => interpreter.execute_body(f.body.clone(), Rc::clone(&env)),
+                };
+                self.recycle(env);
+
+                match &f.return_type {
+                    Some(return_type) => self.check_return(return_type, result),
+                    None => result,
+                }
             }
             _ => Err(RatexError {
                 source: RatexErrorType::InvalidFunctionCall,
+                backtrace: None,
             }),
         }
     }
 
     fn arity(&self) -> Result<usize, RatexError> {
         match &*self.declaration {
+            Stmt::Fun(f) if f.variadic => Ok(f.params.len() - 1),
             Stmt::Fun(f) => Ok(f.params.len()),
             _ => Err(RatexError {
                 source: RatexErrorType::InvalidFunctionCall,
+                backtrace: None,
+            }),
+        }
+    }
+
+    fn min_arity(&self) -> Result<usize, RatexError> {
+        match &*self.declaration {
+            Stmt::Fun(f) => {
+                let required = f.defaults.iter().filter(|d| ***d == Expr::Empty).count();
+                Ok(if f.variadic { required - 1 } else { required })
+            }
+            _ => Err(RatexError {
+                source: RatexErrorType::InvalidFunctionCall,
+                backtrace: None,
             }),
         }
     }
 
+    fn variadic(&self) -> bool {
+        matches!(&*self.declaration, Stmt::Fun(f) if f.variadic)
+    }
+
     fn name(&self) -> String {
         self.name.clone()
     }
+
+    fn task_parts(&self) -> Option<TaskParts> {
+        match &*self.declaration {
+            Stmt::Fun(f) => Some((f.body.clone(), Rc::clone(&self.closure))),
+            _ => None,
+        }
+    }
 }
 
 impl RatexFunction {
@@ -63,37 +139,135 @@ impl RatexFunction {
         name: String,
         declaration: Rc<Stmt>,
         closure: Rc<RefCell<Environment>>,
+    ) -> Rc<RefCell<RatexFunction>> {
+        Self::with_spare_frame(name, declaration, closure, Rc::new(RefCell::new(None)))
+    }
+
+    fn with_spare_frame(
+        name: String,
+        declaration: Rc<Stmt>,
+        closure: Rc<RefCell<Environment>>,
+        spare_frame: Rc<SpareFrame>,
     ) -> Rc<RefCell<RatexFunction>> {
         Rc::new(RefCell::new(RatexFunction {
             name,
             closure,
             declaration,
+            spare_frame,
         }))
     }
 
-    pub fn bind(&mut self, instance: RatexInstance) {
+    // Takes the spare frame if there is one rather than allocating a new
+    // scope. It's taken out rather than borrowed, so a recursive call finds
+    // nothing spare and gets a frame of its own.
+    fn frame(&self) -> Rc<RefCell<Environment>> {
+        match self.spare_frame.borrow_mut().take() {
+            Some(env) => {
+                env.borrow_mut().reset(Some(Rc::clone(&self.closure)));
+                env
+            }
+            None => Environment::new_child(Rc::clone(&self.closure)),
+        }
+    }
+
+    // A frame is only kept for reuse when this call held the last reference
+    // to it, since a closure made during the call still needs its values.
+    fn recycle(&self, env: Rc<RefCell<Environment>>) {
+        if Rc::strong_count(&env) == 1 && Rc::weak_count(&env) == 0 {
+            env.borrow_mut().reset(None);
+            *self.spare_frame.borrow_mut() = Some(env);
+        }
+    }
+
+    // Checks what the body gave back against the declared return type. A
+    // return statement gives its value as an interrupt, so that's unwrapped
+    // here rather than left to the caller.
+    fn check_return(
+        &self,
+        return_type: &RatexToken,
+        result: Result<Object, RatexError>,
+    ) -> Result<Object, RatexError> {
+        let value = match result {
+            Err(RatexError {
+                source: RatexErrorType::Return(value),
+                ..
+            }) => value,
+            Ok(value) => value,
+            Err(e) => return Err(e),
+        };
+
+        check_type(
+            || format!("the return value of {}", self.name),
+            return_type,
+            &value,
+        )?;
+        Ok(value)
+    }
+
+    fn check_argument(
+        &self,
+        param: &RatexToken,
+        param_type: &Option<RatexToken>,
+        value: &Object,
+    ) -> Result<(), RatexError> {
+        match param_type {
+            Some(param_type) => check_type(
+                || format!("parameter '{}' of {}", param.lexeme, self.name),
+                param_type,
+                value,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    pub fn bind(&self, instance: Rc<RefCell<RatexInstance>>) -> Rc<RefCell<RatexFunction>> {
         let env = Environment::new_child(Rc::clone(&self.closure));
 
-        env.borrow_mut().define(
-            "this".to_owned(),
-            Object::Instance(Rc::new(RefCell::new(instance))),
-        );
+        env.borrow_mut()
+            .define("this".to_owned(), Object::Instance(instance));
+
+        RatexFunction::with_spare_frame(
+            self.name.clone(),
+            Rc::clone(&self.declaration),
+            env,
+            Rc::clone(&self.spare_frame),
+        )
+    }
+}
 
-        self.closure = env;
+// Checks a value against a declared type, where `what` names the value for
+// the error.
+fn check_type(
+    what: impl FnOnce() -> String,
+    expected: &RatexToken,
+    value: &Object,
+) -> Result<(), RatexError> {
+    let got = value.type_name();
+    if got == expected.lexeme {
+        return Ok(());
     }
+
+    Err(RatexError {
+        source: RatexErrorType::TypeMismatch {
+            what: what(),
+            expected: expected.lexeme.clone(),
+            got,
+            line: expected.line,
+        },
+        backtrace: None,
+    })
 }
 
 #[derive(Debug)]
 pub struct ClockFunction {}
 
 impl RatexCallable for ClockFunction {
-    fn call(&self, _: &mut RatexInterpreter, _: Vec<Object>) -> Result<Object, RatexError> {
-        Ok(Object::Number(
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs_f64(),
-        ))
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        _: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        Ok(Object::Number(interpreter.now()))
     }
 
     fn arity(&self) -> Result<usize, RatexError> {
@@ -110,3 +284,1416 @@ impl ClockFunction {
         Rc::new(RefCell::new(ClockFunction {}))
     }
 }
+
+#[derive(Debug)]
+pub struct RandomFunction {}
+
+impl RatexCallable for RandomFunction {
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        _: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        Ok(Object::Number(interpreter.next_random()))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(0)
+    }
+
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+}
+
+impl RandomFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(RandomFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct RandomIntFunction {}
+
+impl RatexCallable for RandomIntFunction {
+    // Both ends of the range can come up.
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        let (lo, hi) = match (&arguments[0], &arguments[1]) {
+            (Object::Number(lo), Object::Number(hi))
+                if lo.fract() == 0.0 && hi.fract() == 0.0 && lo <= hi =>
+            {
+                (*lo, *hi)
+            }
+            _ => {
+                return Err(RatexError {
+                    source: RatexErrorType::InvalidArgument(
+                        self.name(),
+                        "expected whole numbers with lo <= hi".to_owned(),
+                    ),
+                    backtrace: None,
+                })
+            }
+        };
+
+        let offset = (interpreter.next_random() * (hi - lo + 1.0)).floor();
+
+        Ok(Object::Number(lo + offset))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    fn name(&self) -> String {
+        "random_int".to_string()
+    }
+}
+
+impl RandomIntFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(RandomIntFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct StrFunction {}
+
+impl RatexCallable for StrFunction {
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        Ok(Object::from(interpreter.stringify(&arguments[0])?))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "str".to_string()
+    }
+}
+
+impl StrFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(StrFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct ReprFunction {}
+
+impl RatexCallable for ReprFunction {
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        Ok(Object::from(interpreter.repr(&arguments[0])?))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "repr".to_string()
+    }
+}
+
+impl ReprFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(ReprFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct WatchFunction {}
+
+impl RatexCallable for WatchFunction {
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        match (&arguments[0], &arguments[1]) {
+            (Object::String(name), Object::Function(callback)) => {
+                interpreter.watch(name, Watcher::Callable(Rc::clone(callback)));
+                Ok(Object::Nil)
+            }
+            _ => Err(RatexError {
+                source: RatexErrorType::InvalidArgument(
+                    self.name(),
+                    "expected a variable name and a function".to_owned(),
+                ),
+                backtrace: None,
+            }),
+        }
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    fn name(&self) -> String {
+        "watch".to_string()
+    }
+}
+
+impl WatchFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(WatchFunction {}))
+    }
+}
+
+// Widths are counted in characters. A string that is already at least as
+// wide as requested comes back unchanged.
+fn padding_arguments(
+    name: String,
+    arguments: &[Object],
+) -> Result<(String, usize, char), RatexError> {
+    let invalid = |reason: &str| RatexError {
+        source: RatexErrorType::InvalidArgument(name.clone(), reason.to_owned()),
+        backtrace: None,
+    };
+
+    let Object::String(s) = &arguments[0] else {
+        return Err(invalid("expected a string to pad"));
+    };
+
+    let width = match arguments[1] {
+        Object::Number(n) if n >= 0.0 && n.fract() == 0.0 => n as usize,
+        _ => return Err(invalid("expected the width to be a whole number")),
+    };
+
+    let fill = match &arguments[2] {
+        Object::String(f) if f.chars().count() == 1 => f.chars().next().unwrap(),
+        _ => return Err(invalid("expected the fill to be a single character")),
+    };
+
+    let padding = width.saturating_sub(s.chars().count());
+
+    Ok((s.to_string(), padding, fill))
+}
+
+fn fill(c: char, count: usize) -> String {
+    std::iter::repeat_n(c, count).collect()
+}
+
+#[derive(Debug)]
+pub struct PadLeftFunction {}
+
+impl RatexCallable for PadLeftFunction {
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let (s, padding, c) = padding_arguments(self.name(), &arguments)?;
+        Ok(Object::from(fill(c, padding) + &s))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(3)
+    }
+
+    fn name(&self) -> String {
+        "pad_left".to_string()
+    }
+}
+
+impl PadLeftFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(PadLeftFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct PadRightFunction {}
+
+impl RatexCallable for PadRightFunction {
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let (s, padding, c) = padding_arguments(self.name(), &arguments)?;
+        Ok(Object::from(s + &fill(c, padding)))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(3)
+    }
+
+    fn name(&self) -> String {
+        "pad_right".to_string()
+    }
+}
+
+impl PadRightFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(PadRightFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct CenterFunction {}
+
+impl RatexCallable for CenterFunction {
+    // when the padding can't be split evenly the extra character goes on the
+    // right, so the text leans left
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let (s, padding, c) = padding_arguments(self.name(), &arguments)?;
+        let left = padding / 2;
+
+        Ok(Object::from(fill(c, left) + &s + &fill(c, padding - left)))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(3)
+    }
+
+    fn name(&self) -> String {
+        "center".to_string()
+    }
+}
+
+impl CenterFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(CenterFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct UpperFunction {}
+
+impl RatexCallable for UpperFunction {
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let s = string_argument(self.name(), &arguments[0], "expected a string")?;
+        Ok(Object::from(s.to_uppercase()))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "upper".to_string()
+    }
+}
+
+impl UpperFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(UpperFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct LowerFunction {}
+
+impl RatexCallable for LowerFunction {
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let s = string_argument(self.name(), &arguments[0], "expected a string")?;
+        Ok(Object::from(s.to_lowercase()))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "lower".to_string()
+    }
+}
+
+impl LowerFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(LowerFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct TrimFunction {}
+
+impl RatexCallable for TrimFunction {
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let s = string_argument(self.name(), &arguments[0], "expected a string")?;
+        Ok(Object::from(s.trim()))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "trim".to_string()
+    }
+}
+
+impl TrimFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(TrimFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct TrimStartFunction {}
+
+impl RatexCallable for TrimStartFunction {
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let s = string_argument(self.name(), &arguments[0], "expected a string")?;
+        Ok(Object::from(s.trim_start()))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "trim_start".to_string()
+    }
+}
+
+impl TrimStartFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(TrimStartFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct TrimEndFunction {}
+
+impl RatexCallable for TrimEndFunction {
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let s = string_argument(self.name(), &arguments[0], "expected a string")?;
+        Ok(Object::from(s.trim_end()))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "trim_end".to_string()
+    }
+}
+
+impl TrimEndFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(TrimEndFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct StartsWithFunction {}
+
+impl RatexCallable for StartsWithFunction {
+    // Every string starts with the empty string.
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let s = string_argument(self.name(), &arguments[0], "expected a string")?;
+        let prefix = string_argument(
+            self.name(),
+            &arguments[1],
+            "expected the prefix to be a string",
+        )?;
+
+        Ok(Object::Bool(s.starts_with(prefix)))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    fn name(&self) -> String {
+        "starts_with".to_string()
+    }
+}
+
+impl StartsWithFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(StartsWithFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct EndsWithFunction {}
+
+impl RatexCallable for EndsWithFunction {
+    // Every string ends with the empty string.
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let s = string_argument(self.name(), &arguments[0], "expected a string")?;
+        let suffix = string_argument(
+            self.name(),
+            &arguments[1],
+            "expected the suffix to be a string",
+        )?;
+
+        Ok(Object::Bool(s.ends_with(suffix)))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    fn name(&self) -> String {
+        "ends_with".to_string()
+    }
+}
+
+impl EndsWithFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(EndsWithFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct SplitFunction {}
+
+impl RatexCallable for SplitFunction {
+    // Splits on every occurrence of the separator, so neighbouring separators
+    // give empty strings and an empty string gives a list holding just itself.
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let s = string_argument(self.name(), &arguments[0], "expected a string to split")?;
+        let separator = string_argument(
+            self.name(),
+            &arguments[1],
+            "expected the separator to be a string",
+        )?;
+
+        if separator.is_empty() {
+            return Err(RatexError {
+                source: RatexErrorType::InvalidArgument(
+                    self.name(),
+                    "expected a non-empty separator".to_owned(),
+                ),
+                backtrace: None,
+            });
+        }
+
+        let parts = s.split(separator).map(Object::from).collect();
+
+        Ok(Object::List(Rc::new(RefCell::new(parts))))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    fn name(&self) -> String {
+        "split".to_string()
+    }
+}
+
+impl SplitFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(SplitFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct CharsFunction {}
+
+impl RatexCallable for CharsFunction {
+    // One string per Unicode scalar value, the same characters that indexing a
+    // string gives back one at a time.
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let s = string_argument(self.name(), &arguments[0], "expected a string")?;
+        let chars = s.chars().map(|c| Object::from(c.to_string())).collect();
+
+        Ok(Object::List(Rc::new(RefCell::new(chars))))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "chars".to_string()
+    }
+}
+
+impl CharsFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(CharsFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct IndexOfFunction {}
+
+impl RatexCallable for IndexOfFunction {
+    // Counts characters rather than bytes, like substring, and gives -1 when
+    // there's no match. The empty string is found at 0.
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let s = string_argument(self.name(), &arguments[0], "expected a string to search")?;
+        let needle = string_argument(
+            self.name(),
+            &arguments[1],
+            "can only look for a string in a string",
+        )?;
+
+        let index = match s.find(needle) {
+            Some(byte) => s[..byte].chars().count() as f64,
+            None => -1.0,
+        };
+
+        Ok(Object::Number(index))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    fn name(&self) -> String {
+        "index_of".to_string()
+    }
+}
+
+impl IndexOfFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(IndexOfFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct ReplaceFunction {}
+
+impl RatexCallable for ReplaceFunction {
+    // Replaces every match, scanning left to right so matches don't overlap.
+    // An empty pattern matches everywhere, so like split's separator it's
+    // rejected.
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let s = string_argument(self.name(), &arguments[0], "expected a string")?;
+        let from = string_argument(
+            self.name(),
+            &arguments[1],
+            "expected the text to replace to be a string",
+        )?;
+        let to = string_argument(
+            self.name(),
+            &arguments[2],
+            "expected the replacement to be a string",
+        )?;
+
+        if from.is_empty() {
+            return Err(RatexError {
+                source: RatexErrorType::InvalidArgument(
+                    self.name(),
+                    "expected non-empty text to replace".to_owned(),
+                ),
+                backtrace: None,
+            });
+        }
+
+        Ok(Object::from(s.replace(from, to)))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(3)
+    }
+
+    fn name(&self) -> String {
+        "replace".to_string()
+    }
+}
+
+impl ReplaceFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(ReplaceFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct SubstringFunction {}
+
+impl RatexCallable for SubstringFunction {
+    // Positions count characters rather than bytes, and the end is exclusive.
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let s = string_argument(self.name(), &arguments[0], "expected a string")?;
+        let length = s.chars().count();
+
+        let (start, end) = match (&arguments[1], &arguments[2]) {
+            (Object::Number(start), Object::Number(end))
+                if start.fract() == 0.0
+                    && end.fract() == 0.0
+                    && 0.0 <= *start
+                    && start <= end
+                    && *end <= length as f64 =>
+            {
+                (*start as usize, *end as usize)
+            }
+            _ => {
+                return Err(RatexError {
+                    source: RatexErrorType::InvalidArgument(
+                        self.name(),
+                        format!(
+                            "expected whole numbers with 0 <= start <= end <= {}",
+                            length
+                        ),
+                    ),
+                    backtrace: None,
+                })
+            }
+        };
+
+        Ok(Object::from(
+            s.chars().skip(start).take(end - start).collect::<String>(),
+        ))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(3)
+    }
+
+    fn name(&self) -> String {
+        "substring".to_string()
+    }
+}
+
+impl SubstringFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(SubstringFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct OrdFunction {}
+
+impl RatexCallable for OrdFunction {
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let s = string_argument(self.name(), &arguments[0], "expected a string")?;
+
+        match s.chars().next() {
+            Some(c) => Ok(Object::Number(c as u32 as f64)),
+            None => Err(RatexError {
+                source: RatexErrorType::InvalidArgument(
+                    self.name(),
+                    "expected a non-empty string".to_owned(),
+                ),
+                backtrace: None,
+            }),
+        }
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "ord".to_string()
+    }
+}
+
+impl OrdFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(OrdFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct ChrFunction {}
+
+impl RatexCallable for ChrFunction {
+    // Surrogates and anything past 0x10FFFF aren't characters, so they are
+    // rejected along with fractional and negative numbers.
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let c = match arguments[0] {
+            Object::Number(n) if n.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&n) => {
+                char::from_u32(n as u32)
+            }
+            _ => None,
+        };
+
+        match c {
+            Some(c) => Ok(Object::from(c.to_string())),
+            None => Err(RatexError {
+                source: RatexErrorType::InvalidArgument(
+                    self.name(),
+                    "expected a valid Unicode code point".to_owned(),
+                ),
+                backtrace: None,
+            }),
+        }
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "chr".to_string()
+    }
+}
+
+impl ChrFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(ChrFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct PushFunction {}
+
+impl RatexCallable for PushFunction {
+    // Appends in place and returns the list.
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let list = list_argument(self.name(), &arguments[0])?;
+        list.borrow_mut().push(arguments[1].clone());
+
+        Ok(Object::List(Rc::clone(list)))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    fn name(&self) -> String {
+        "push".to_string()
+    }
+}
+
+impl PushFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(PushFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct PopFunction {}
+
+impl RatexCallable for PopFunction {
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let list = list_argument(self.name(), &arguments[0])?;
+        let last = list.borrow_mut().pop();
+
+        last.ok_or_else(|| RatexError {
+            source: RatexErrorType::InvalidArgument(
+                self.name(),
+                "can't pop from an empty list".to_owned(),
+            ),
+            backtrace: None,
+        })
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "pop".to_string()
+    }
+}
+
+impl PopFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(PopFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct MapFunction {}
+
+impl RatexCallable for MapFunction {
+    // Builds a new list. The elements are copied out first, so the callable
+    // is free to change the original list while map runs.
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        let items = list_argument(self.name(), &arguments[0])?.borrow().clone();
+        let fun = callable_argument(self.name(), &arguments[1])?;
+
+        let mut mapped = Vec::with_capacity(items.len());
+
+        for item in items {
+            mapped.push(interpreter.call_function(Rc::clone(&fun), vec![item])?);
+        }
+
+        Ok(Object::List(Rc::new(RefCell::new(mapped))))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    fn name(&self) -> String {
+        "map".to_string()
+    }
+}
+
+impl MapFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(MapFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct FilterFunction {}
+
+impl RatexCallable for FilterFunction {
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        let items = list_argument(self.name(), &arguments[0])?.borrow().clone();
+        let fun = callable_argument(self.name(), &arguments[1])?;
+
+        let mut kept = Vec::new();
+
+        for item in items {
+            if interpreter
+                .call_function(Rc::clone(&fun), vec![item.clone()])?
+                .is_truthy()
+            {
+                kept.push(item);
+            }
+        }
+
+        Ok(Object::List(Rc::new(RefCell::new(kept))))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    fn name(&self) -> String {
+        "filter".to_string()
+    }
+}
+
+impl FilterFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(FilterFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct ContainsFunction {}
+
+impl RatexCallable for ContainsFunction {
+    // There's no map type yet, so only strings and lists are collections.
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let found = match &arguments[0] {
+            Object::String(s) => {
+                let part = string_argument(
+                    self.name(),
+                    &arguments[1],
+                    "can only look for a string in a string",
+                )?;
+                s.contains(part)
+            }
+            Object::List(list) => list.borrow().contains(&arguments[1]),
+            _ => {
+                return Err(RatexError {
+                    source: RatexErrorType::InvalidArgument(
+                        self.name(),
+                        "expected a string or a list".to_owned(),
+                    ),
+                    backtrace: None,
+                })
+            }
+        };
+
+        Ok(Object::Bool(found))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    fn name(&self) -> String {
+        "contains".to_string()
+    }
+}
+
+impl ContainsFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(ContainsFunction {}))
+    }
+}
+
+fn list_argument(name: String, argument: &Object) -> Result<&Rc<RefCell<Vec<Object>>>, RatexError> {
+    match argument {
+        Object::List(list) => Ok(list),
+        _ => Err(RatexError {
+            source: RatexErrorType::InvalidArgument(name, "expected a list".to_owned()),
+            backtrace: None,
+        }),
+    }
+}
+
+fn callable_argument(
+    name: String,
+    argument: &Object,
+) -> Result<Rc<RefCell<dyn RatexCallable>>, RatexError> {
+    match argument {
+        Object::Function(fun) => Ok(Rc::clone(fun)),
+        _ => Err(RatexError {
+            source: RatexErrorType::InvalidArgument(name, "expected a function".to_owned()),
+            backtrace: None,
+        }),
+    }
+}
+
+fn string_argument<'a>(
+    name: String,
+    argument: &'a Object,
+    reason: &str,
+) -> Result<&'a str, RatexError> {
+    match argument {
+        Object::String(s) => Ok(s),
+        _ => Err(RatexError {
+            source: RatexErrorType::InvalidArgument(name, reason.to_owned()),
+            backtrace: None,
+        }),
+    }
+}
+
+#[derive(Debug)]
+pub struct SortFunction {}
+
+impl RatexCallable for SortFunction {
+    // Returns a sorted copy of the list, leaving the original alone. Without
+    // a comparator, elements are ordered by interpreter.compare; with one,
+    // cmp(a, b) returns a negative number, zero or a positive number.
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        let items = list_argument(self.name(), &arguments[0])?.borrow().clone();
+        let comparator = match arguments.get(1) {
+            Some(cmp) => Some(callable_argument(self.name(), cmp)?),
+            None => None,
+        };
+
+        let sorted = merge_sort(items, &comparator, interpreter)?;
+
+        Ok(Object::List(Rc::new(RefCell::new(sorted))))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    // the comparator is optional
+    fn min_arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "sort".to_string()
+    }
+}
+
+impl SortFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(SortFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct AssertFunction {}
+
+impl RatexCallable for AssertFunction {
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        if arguments[0].is_truthy() {
+            return Ok(Object::Nil);
+        }
+
+        let message = match arguments.get(1) {
+            Some(message) => Some(interpreter.stringify(message)?),
+            None => None,
+        };
+
+        Err(RatexError {
+            source: RatexErrorType::AssertionFailed(message),
+            backtrace: None,
+        })
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    // the message is optional
+    fn min_arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "assert".to_string()
+    }
+}
+
+impl AssertFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(AssertFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct AssertThrowsFunction {}
+
+impl RatexCallable for AssertThrowsFunction {
+    // Calls the function and fails unless it raises an error. What was thrown
+    // is handed back, or the message for an error from the interpreter itself.
+    // Returns and breaks pass through, and so do the step and time limits, so
+    // that a script can't use this to get around them, and failed output.
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        let fun = match &arguments[0] {
+            Object::Function(fun) if fun.borrow().min_arity()? == 0 => Rc::clone(fun),
+            _ => {
+                return Err(RatexError {
+                    source: RatexErrorType::InvalidArgument(
+                        self.name(),
+                        "expected a function that takes no arguments".to_owned(),
+                    ),
+                    backtrace: None,
+                })
+            }
+        };
+
+        if let Err(e) = interpreter.call_function(fun, vec![]) {
+            return match &e.source {
+                RatexErrorType::Break(..) | RatexErrorType::Return(_) => Err(e),
+                source if source.stops_script() => Err(e),
+                RatexErrorType::UserThrow(value) => Ok(value.clone()),
+                source => Ok(Object::from(source.to_string())),
+            };
+        }
+
+        let message = match arguments.get(1) {
+            Some(message) => interpreter.stringify(message)?,
+            None => "nothing was thrown".to_owned(),
+        };
+
+        Err(RatexError {
+            source: RatexErrorType::AssertionFailed(Some(message)),
+            backtrace: None,
+        })
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    // the message is optional
+    fn min_arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "assert_throws".to_string()
+    }
+}
+
+impl AssertThrowsFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(AssertThrowsFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct SpawnFunction {}
+
+impl RatexCallable for SpawnFunction {
+    // Queues the function up as a task. Nothing runs until run_tasks is called.
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        let parts = match &arguments[0] {
+            Object::Function(fun) if fun.borrow().arity()? == 0 && !fun.borrow().variadic() => {
+                fun.borrow().task_parts()
+            }
+            _ => None,
+        };
+
+        let Some((body, closure)) = parts else {
+            return Err(RatexError {
+                source: RatexErrorType::InvalidArgument(
+                    self.name(),
+                    "expected a function that takes no arguments".to_owned(),
+                ),
+                backtrace: None,
+            });
+        };
+
+        interpreter.spawn_task(Task::new(body, closure));
+
+        Ok(Object::Nil)
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "spawn".to_string()
+    }
+}
+
+impl SpawnFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(SpawnFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct RunTasksFunction {}
+
+impl RatexCallable for RunTasksFunction {
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        _: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        interpreter.run_tasks()?;
+
+        Ok(Object::Nil)
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(0)
+    }
+
+    fn name(&self) -> String {
+        "run_tasks".to_string()
+    }
+}
+
+impl RunTasksFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(RunTasksFunction {}))
+    }
+}
+
+#[derive(Debug)]
+pub struct PartialFunction {}
+
+impl RatexCallable for PartialFunction {
+    // partial(fn, a, b) gives back a callable that calls fn with a and b
+    // ahead of whatever it's called with.
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let mut arguments = arguments.into_iter();
+        let function = callable_argument(self.name(), &arguments.next().unwrap())?;
+        let bound: Vec<Object> = arguments.collect();
+
+        let too_many = {
+            let function = function.borrow();
+            !function.variadic() && bound.len() > function.arity()?
+        };
+
+        if too_many {
+            return Err(RatexError {
+                source: RatexErrorType::InvalidArgument(
+                    self.name(),
+                    "more arguments than the function takes".to_owned(),
+                ),
+                backtrace: None,
+            });
+        }
+
+        Ok(Object::Function(Rc::new(RefCell::new(PartiallyApplied {
+            function,
+            bound,
+        }))))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(2)
+    }
+
+    fn variadic(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> String {
+        "partial".to_string()
+    }
+}
+
+impl PartialFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(PartialFunction {}))
+    }
+}
+
+// What partial gives back: a callable with some of its leading arguments
+// already supplied.
+#[derive(Debug)]
+pub struct PartiallyApplied {
+    function: Rc<RefCell<dyn RatexCallable>>,
+    bound: Vec<Object>,
+}
+
+impl RatexCallable for PartiallyApplied {
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        let mut all = self.bound.clone();
+        all.extend(arguments);
+
+        interpreter.call_function(Rc::clone(&self.function), all)
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(self
+            .function
+            .borrow()
+            .arity()?
+            .saturating_sub(self.bound.len()))
+    }
+
+    fn min_arity(&self) -> Result<usize, RatexError> {
+        Ok(self
+            .function
+            .borrow()
+            .min_arity()?
+            .saturating_sub(self.bound.len()))
+    }
+
+    fn variadic(&self) -> bool {
+        self.function.borrow().variadic()
+    }
+
+    fn name(&self) -> String {
+        self.function.borrow().name()
+    }
+}
+
+#[derive(Debug)]
+pub struct MemoizeFunction {}
+
+impl RatexCallable for MemoizeFunction {
+    fn call(&self, _: &mut RatexInterpreter, arguments: Vec<Object>) -> Result<Object, RatexError> {
+        let function = callable_argument(self.name(), &arguments[0])?;
+
+        Ok(Object::Function(Rc::new(RefCell::new(Memoized {
+            function,
+            cache: RefCell::new(HashMap::new()),
+        }))))
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        Ok(1)
+    }
+
+    fn name(&self) -> String {
+        "memoize".to_string()
+    }
+}
+
+impl MemoizeFunction {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(MemoizeFunction {}))
+    }
+}
+
+// What memoize gives back: a callable that remembers the result for each
+// list of arguments it has been called with. Arguments are compared the way
+// == compares them, so a list argument is looked up by its contents when
+// the call is made.
+#[derive(Debug)]
+pub struct Memoized {
+    function: Rc<RefCell<dyn RatexCallable>>,
+    cache: RefCell<HashMap<Vec<Object>, Object>>,
+}
+
+impl RatexCallable for Memoized {
+    fn call(
+        &self,
+        interpreter: &mut RatexInterpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        if let Some(result) = self.cache.borrow().get(&arguments) {
+            return Ok(result.clone());
+        }
+
+        // the cache isn't borrowed during the call, since a recursive
+        // function calls back into it
+        let result = interpreter.call_function(Rc::clone(&self.function), arguments.clone())?;
+        self.cache.borrow_mut().insert(arguments, result.clone());
+
+        Ok(result)
+    }
+
+    fn arity(&self) -> Result<usize, RatexError> {
+        self.function.borrow().arity()
+    }
+
+    fn min_arity(&self) -> Result<usize, RatexError> {
+        self.function.borrow().min_arity()
+    }
+
+    fn variadic(&self) -> bool {
+        self.function.borrow().variadic()
+    }
+
+    fn name(&self) -> String {
+        self.function.borrow().name()
+    }
+}
+
+// A stable merge sort, used instead of sort_by because comparing can fail and
+// a user's compareTo isn't guaranteed to be a consistent ordering.
+fn merge_sort(
+    mut items: Vec<Object>,
+    comparator: &Option<Rc<RefCell<dyn RatexCallable>>>,
+    interpreter: &mut RatexInterpreter,
+) -> Result<Vec<Object>, RatexError> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, comparator, interpreter)?;
+    let right = merge_sort(right, comparator, interpreter)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        let ordering = match comparator {
+            Some(cmp) => {
+                match interpreter.call_function(Rc::clone(cmp), vec![l.clone(), r.clone()])? {
+                    Object::Number(n) => n.partial_cmp(&0.0).unwrap_or(Ordering::Equal),
+                    _ => {
+                        return Err(RatexError {
+                            source: RatexErrorType::InvalidArgument(
+                                "sort".to_owned(),
+                                "the comparator must return a number".to_owned(),
+                            ),
+                            backtrace: None,
+                        })
+                    }
+                }
+            }
+            None => interpreter.compare(l, r)?,
+        };
+
+        if ordering == Ordering::Greater {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+
+    merged.extend(left);
+    merged.extend(right);
+
+    Ok(merged)
+}
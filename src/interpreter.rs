@@ -1,36 +1,257 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, BufWriter, Write};
 use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crate::ast::{
-    Assign, Binary, Block, Break, Call, Class, Expr, ExprAccept, ExprVisitor, Expression, Fun, Get,
-    Grouping, If, Lambda, Literal, Logical, Object, Print, RatexCallable, Return, Set, Stmt,
-    StmtAccept, StmtVisitor, This, Unary, Var, Variable, While,
+    Assign, AssignTuple, Binary, Block, Break, Call, Chain, Class, Expr, ExprAccept, ExprVisitor,
+    Expression, For, Fun, Get, Grouping, If, Index, IndexSet, Invariant, Lambda, ListLiteral,
+    Literal, Logical, Loop, Match, Object, Print, RatexCallable, Return, Set, Slice, SliceSet,
+    Stmt, StmtAccept, StmtVisitor, This, Throw, Try, TupleLiteral, TypeCheck, Unary, Var, VarTuple,
+    Variable, Variant, While, With, Yield,
 };
-use crate::class::RatexClass;
+use crate::class::{RatexClass, RatexInstance, RatexVariant};
+use crate::compiler;
 use crate::environment::Environment;
 use crate::error::{RatexError, RatexErrorType};
-use crate::functions::{ClockFunction, RatexFunction};
+use crate::functions::{
+    AssertFunction, AssertThrowsFunction, CenterFunction, CharsFunction, ChrFunction,
+    ClockFunction, ContainsFunction, EndsWithFunction, FilterFunction, IndexOfFunction,
+    LowerFunction, MapFunction, MemoizeFunction, OrdFunction, PadLeftFunction, PadRightFunction,
+    PartialFunction, PopFunction, PushFunction, RandomFunction, RandomIntFunction, RatexFunction,
+    ReplaceFunction, ReprFunction, RunTasksFunction, SortFunction, SpawnFunction, SplitFunction,
+    StartsWithFunction, StrFunction, SubstringFunction, TrimEndFunction, TrimFunction,
+    TrimStartFunction, UpperFunction, WatchFunction,
+};
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::tasks::Task;
 use crate::token::{RatexToken, RatexTokenType as RXTT};
+use crate::vm::Vm;
+
+pub type WatchCallback = Box<dyn FnMut(&Object, &Object)>;
+
+// Something to notify when a watched global is written. Both kinds receive
+// the old value followed by the new one.
+pub enum Watcher {
+    Native(WatchCallback),
+    Callable(Rc<RefCell<dyn RatexCallable>>),
+}
+
+impl fmt::Debug for Watcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watcher::Native(_) => write!(f, "Native"),
+            Watcher::Callable(callable) => write!(f, "Callable({})", callable.borrow().name()),
+        }
+    }
+}
+
+// Where clock() gets the time from, in seconds. The interpreter uses the
+// system clock unless it's given another one with `with_clock`.
+pub trait Clock: fmt::Debug {
+    fn now(&self) -> f64;
+}
+
+#[derive(Debug)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64()
+    }
+}
+
+// A clock that's stuck at one time, for runs that need the same output
+// every time.
+#[derive(Debug)]
+pub struct FixedClock(pub f64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> f64 {
+        self.0
+    }
+}
+
+// Where print writes to. The interpreter writes to stdout unless it's given
+// something else with `with_output`.
+pub trait Output: Write + fmt::Debug {}
+
+impl<T: Write + fmt::Debug> Output for T {}
 
 #[derive(Debug)]
 pub struct RatexInterpreter {
     environment: Rc<RefCell<Environment>>,
-    locals: HashMap<Rc<Expr>, usize>,
+    // the scope distance and slot the resolver found for each local, keyed
+    // by the node rather than by what's in it, since two reads of the same
+    // name on one line are otherwise equal. The node is held on to so that
+    // its address can't be reused by another one.
+    locals: HashMap<usize, (Rc<Expr>, usize, usize)>,
     globals: Rc<RefCell<Environment>>,
+    steps: u64,
+    max_steps: Option<u64>,
+    float_epsilon: Option<f64>,
+    timeout: Option<Duration>,
+    // when the current run has to stop by, set from the timeout as each run
+    // starts
+    deadline: Option<Instant>,
+    watchers: HashMap<String, Vec<Watcher>>,
+    tasks: VecDeque<Task>,
+    running_tasks: bool,
+    // the values of the Invariant expressions of the loops running now, worked
+    // out the first time each is needed in a run of its loop
+    loop_caches: Vec<Vec<Option<Object>>>,
+    rng_state: u64,
+    clock: Box<dyn Clock>,
+    // where print writes to. It's buffered, and flushed whenever `interpret`,
+    // `run_compiled` or `evaluate_expr` returns.
+    out: BufWriter<Box<dyn Output>>,
+    // the arguments for the script's main function, if it should be called
+    // once the script has run. Taken by the first run, so it's called once.
+    main_args: Option<Vec<String>>,
 }
 
 impl RatexInterpreter {
     pub fn evaluate(&mut self, expr: Rc<Expr>) -> Result<Object, RatexError> {
+        self.step()?;
         expr.accept(self)
     }
 
+    pub fn evaluate_in(
+        &mut self,
+        expr: Rc<Expr>,
+        env: Rc<RefCell<Environment>>,
+    ) -> Result<Object, RatexError> {
+        let old_environment = std::mem::replace(&mut self.environment, env);
+
+        let result = self.evaluate(expr);
+
+        self.environment = old_environment;
+
+        result
+    }
+
     pub fn execute(&mut self, statement: Rc<Stmt>) -> Result<(), RatexError> {
+        self.step()?;
         statement.accept(self)
     }
 
-    pub fn resolve(&mut self, expr: Rc<Expr>, depth: usize) {
-        self.locals.insert(expr, depth);
+    pub fn step(&mut self) -> Result<(), RatexError> {
+        self.steps += 1;
+
+        match self.max_steps {
+            Some(max) if self.steps > max => Err(RatexError {
+                source: RatexErrorType::StepLimitExceeded(max),
+                backtrace: None,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // Each run gets the whole timeout, counted from when it starts.
+    fn start_deadline(&mut self) {
+        self.deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    // The deadline is only looked at on loop iterations, calls and block
+    // entries, so a single long-running native can overrun it, but any script
+    // that keeps running has to pass through one of those checks.
+    pub fn check_deadline(&self) -> Result<(), RatexError> {
+        match (self.deadline, self.timeout) {
+            (Some(deadline), Some(limit)) if Instant::now() >= deadline => Err(RatexError {
+                source: RatexErrorType::Timeout(limit),
+                backtrace: None,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn watch(&mut self, name: &str, watcher: Watcher) {
+        self.watchers
+            .entry(name.to_owned())
+            .or_default()
+            .push(watcher);
+    }
+
+    // Only globals can be watched: locals are resolved to a slot and written
+    // with assign_at, so they never reach these two helpers.
+    pub fn define_variable(&mut self, name: String, value: Object) -> Result<(), RatexError> {
+        if !self.watchers.contains_key(&name) || !Rc::ptr_eq(&self.environment, &self.globals) {
+            self.environment.borrow_mut().define(name, value);
+            return Ok(());
+        }
+
+        let old = self
+            .environment
+            .borrow_mut()
+            .define(name.clone(), value.clone());
+
+        self.notify_watchers(&name, old.unwrap_or(Object::Nil), value)
+    }
+
+    pub fn assign_variable(&mut self, name: String, value: Object) -> Result<(), RatexError> {
+        if !self.watchers.contains_key(&name) {
+            self.environment.borrow_mut().assign(name, value)?;
+            return Ok(());
+        }
+
+        let old = self
+            .environment
+            .borrow_mut()
+            .assign(name.clone(), value.clone())?;
+
+        self.notify_watchers(&name, old, value)
+    }
+
+    fn notify_watchers(&mut self, name: &str, old: Object, new: Object) -> Result<(), RatexError> {
+        // the watchers are taken out while they run, so a watcher that writes
+        // the variable it is watching doesn't trigger itself again
+        let Some(mut watchers) = self.watchers.remove(name) else {
+            return Ok(());
+        };
+
+        let mut result = Ok(());
+
+        for watcher in watchers.iter_mut() {
+            result = match watcher {
+                // a native watcher may write to stdout itself
+                Watcher::Native(callback) => self.flush().map(|()| callback(&old, &new)),
+                Watcher::Callable(callable) => self
+                    .call_function(Rc::clone(callable), vec![old.clone(), new.clone()])
+                    .map(|_| ()),
+            };
+
+            if result.is_err() {
+                break;
+            }
+        }
+
+        // keep any watchers that were registered while these were running
+        if let Some(added) = self.watchers.remove(name) {
+            watchers.extend(added);
+        }
+        self.watchers.insert(name.to_owned(), watchers);
+
+        result
+    }
+
+    pub fn resolve(&mut self, expr: Rc<Expr>, depth: usize, slot: usize) {
+        self.locals.insert(expr.node_id(), (expr, depth, slot));
+    }
+
+    // The depth and slot an expression will be looked up with, or None if
+    // it's a global.
+    pub fn resolved(&self, expr: &Expr) -> Option<(usize, usize)> {
+        self.locals
+            .get(&expr.node_id())
+            .map(|&(_, depth, slot)| (depth, slot))
     }
 
     pub fn execute_block(
@@ -38,78 +259,1010 @@ impl RatexInterpreter {
         statements: Vec<Rc<Stmt>>,
         env: Rc<RefCell<Environment>>,
     ) -> Result<(), RatexError> {
+        self.run_block(statements, env, false).map(|_| ())
+    }
+
+    // Runs a function body. When its last statement is an expression
+    // statement, that expression's value is what the body evaluates to.
+    pub fn execute_body(
+        &mut self,
+        statements: Vec<Rc<Stmt>>,
+        env: Rc<RefCell<Environment>>,
+    ) -> Result<Object, RatexError> {
+        self.run_block(statements, env, true)
+    }
+
+    fn run_block(
+        &mut self,
+        statements: Vec<Rc<Stmt>>,
+        env: Rc<RefCell<Environment>>,
+        keep_last: bool,
+    ) -> Result<Object, RatexError> {
+        self.check_deadline()?;
+
         let old_environment = Rc::clone(&self.environment);
         self.environment = env;
 
-        for statement in statements {
-            match self.execute(statement) {
-                Err(e) => {
-                    return Err(e);
+        let last = statements.len().saturating_sub(1);
+        let mut result = Ok(Object::Nil);
+
+        for (i, statement) in statements.into_iter().enumerate() {
+            result = match statement.as_ref() {
+                Stmt::Expression(expression) if keep_last && i == last => {
+                    self.evaluate(Rc::clone(&expression.expr))
                 }
-                Ok(()) => {}
+                _ => self.execute(statement).map(|_| Object::Nil),
+            };
+
+            if result.is_err() {
+                break;
             }
         }
 
         self.environment = old_environment;
 
-        Ok(())
+        result
     }
 
     pub fn interpret(&mut self, statements: Vec<Rc<Stmt>>) -> Result<(), RatexError> {
+        self.start_deadline();
+
+        let result = self
+            .interpret_statements(statements)
+            .and_then(|()| self.call_main());
+
+        // the output comes before any error the caller goes on to report
+        self.flush().and(result)
+    }
+
+    // Calls a top-level `main` once everything it might use is declared. A
+    // main with parameters gets the arguments as a list of strings. Scripts
+    // without a main function just run as they are.
+    fn call_main(&mut self) -> Result<(), RatexError> {
+        let Some(args) = self.main_args.take() else {
+            return Ok(());
+        };
+
+        let Ok(Object::Function(main)) = self.get_global("main".to_owned()) else {
+            return Ok(());
+        };
+
+        let arguments = if main.borrow().arity()? == 0 {
+            vec![]
+        } else {
+            let args = args.into_iter().map(Object::from).collect();
+            vec![Object::List(Rc::new(RefCell::new(args)))]
+        };
+
+        self.call_function(main, arguments).map(|_| ())
+    }
+
+    fn interpret_statements(&mut self, statements: Vec<Rc<Stmt>>) -> Result<(), RatexError> {
         for statement in statements {
-            match self.execute(statement) {
-                Err(e) => match e.source {
-                    RatexErrorType::Break => {}
+            if let Err(e) = self.execute(statement) {
+                match e.source {
+                    RatexErrorType::Break(..) => {}
                     _ => {
                         return Err(e);
                     }
-                },
-                _ => {}
+                }
             };
         }
 
         Ok(())
     }
 
+    // Works out the value of a single expression given as source, for a host
+    // that wants a result rather than a program's output. Anything after the
+    // expression is an error.
+    pub fn evaluate_expr(&mut self, src: &str) -> Result<Object, RatexError> {
+        let tokens = Scanner::new(src).scan_until_error()?;
+        let expr = Parser::new(tokens).parse_expression()?;
+
+        // resolved as a statement, so that a lambda in it gets its locals
+        Resolver::new(self).resolve_program(&vec![Expression::new(Rc::clone(&expr))])?;
+
+        self.start_deadline();
+        let result = self.evaluate(expr);
+
+        self.flush().and(result)
+    }
+
+    // Runs the program on the bytecode VM instead of walking the tree. The
+    // program is compiled up front, so code the compiler doesn't support is
+    // rejected before anything runs.
+    pub fn run_compiled(&mut self, statements: &[Rc<Stmt>]) -> Result<(), RatexError> {
+        let code = compiler::compile(statements)?;
+
+        self.start_deadline();
+        let old_environment = Rc::clone(&self.environment);
+        self.environment = Rc::clone(&self.globals);
+
+        let result = Vm::new(code).run(self);
+
+        self.environment = old_environment;
+
+        self.flush().and(result)
+    }
+
+    pub fn current_environment(&self) -> Rc<RefCell<Environment>> {
+        Rc::clone(&self.environment)
+    }
+
+    // Swaps in the environment a task was running in, returning the one it
+    // replaced.
+    pub fn replace_environment(
+        &mut self,
+        environment: Rc<RefCell<Environment>>,
+    ) -> Rc<RefCell<Environment>> {
+        std::mem::replace(&mut self.environment, environment)
+    }
+
+    // A number in [0, 1) from a xorshift64* generator, built from the top 53
+    // bits so that every value is an exact f64.
+    pub fn next_random(&mut self) -> f64 {
+        let mut x = self.rng_state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.rng_state = x;
+
+        (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn spawn_task(&mut self, task: Task) {
+        self.tasks.push_back(task);
+    }
+
+    // Runs the spawned tasks in turn, each one until its next yield, until
+    // they have all finished. Tasks spawned along the way join the end of the
+    // queue. If one fails, the rest are dropped and the error is returned.
+    pub fn run_tasks(&mut self) -> Result<(), RatexError> {
+        if self.running_tasks {
+            return Err(RatexError {
+                source: RatexErrorType::InvalidArgument(
+                    "run_tasks".to_owned(),
+                    "tasks are already running".to_owned(),
+                ),
+                backtrace: None,
+            });
+        }
+
+        self.running_tasks = true;
+
+        let mut result = Ok(());
+
+        while let Some(mut task) = self.tasks.pop_front() {
+            match task.resume(self) {
+                Ok(true) => {}
+                Ok(false) => self.tasks.push_back(task),
+                Err(e) => {
+                    self.tasks.clear();
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        self.running_tasks = false;
+
+        result
+    }
+
+    pub fn get_global(&self, name: String) -> Result<Object, RatexError> {
+        self.globals.borrow().get(name)
+    }
+
+    pub fn define_global(&mut self, name: String, value: Object) {
+        self.globals.borrow_mut().define(name, value);
+    }
+
+    pub fn call_function(
+        &mut self,
+        fun: Rc<RefCell<dyn RatexCallable>>,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RatexError> {
+        self.invoke(fun, arguments, None)
+    }
+
+    // Calls whatever a call expression's callee evaluated to. Arity errors
+    // point at `line`, the line of the call's closing paren.
+    pub fn call_value(
+        &mut self,
+        callee: Object,
+        arguments: Vec<Object>,
+        line: u32,
+    ) -> Result<Object, RatexError> {
+        match callee {
+            Object::Function(fun) => self.invoke(fun, arguments, Some(line)),
+            Object::Class(klass) => {
+                Self::check_arity(
+                    &klass.name(),
+                    (klass.min_arity()?, Self::max_arity(&klass)?),
+                    arguments.len(),
+                    Some(line),
+                )?;
+                klass
+                    .call(self, arguments)
+                    .map_err(|e| with_frame(e, || klass.name(), line))
+            }
+            _ => Err(RatexError {
+                source: RatexErrorType::NotCallable(line),
+                backtrace: None,
+            }),
+        }
+    }
+
+    fn max_arity(fun: &dyn RatexCallable) -> Result<Option<usize>, RatexError> {
+        if fun.variadic() {
+            return Ok(None);
+        }
+
+        fun.arity().map(Some)
+    }
+
+    fn check_arity(
+        name: &str,
+        (expected, maximum): (usize, Option<usize>),
+        got: usize,
+        line: Option<u32>,
+    ) -> Result<(), RatexError> {
+        if got >= expected && maximum.is_none_or(|maximum| got <= maximum) {
+            return Ok(());
+        }
+
+        Err(RatexError {
+            source: RatexErrorType::IncompatibleArity {
+                name: name.to_owned(),
+                expected,
+                maximum,
+                got,
+                line,
+            },
+            backtrace: None,
+        })
+    }
+
+    fn invoke(
+        &mut self,
+        fun: Rc<RefCell<dyn RatexCallable>>,
+        arguments: Vec<Object>,
+        line: Option<u32>,
+    ) -> Result<Object, RatexError> {
+        let (name, expected) = {
+            let fun = fun.borrow();
+            (fun.name(), (fun.min_arity()?, Self::max_arity(&*fun)?))
+        };
+        Self::check_arity(&name, expected, arguments.len(), line)?;
+
+        let result = fun.borrow().call(self, arguments);
+
+        match (result, line) {
+            (
+                Err(RatexError {
+                    source: RatexErrorType::Return(obj),
+                    ..
+                }),
+                _,
+            ) => Ok(obj),
+            // natives don't show up in backtraces, only functions written in
+            // ratex do
+            (Err(e), Some(line)) if fun.borrow().task_parts().is_some() => {
+                Err(with_frame(e, || name, line))
+            }
+            (result, _) => result,
+        }
+    }
+
+    // Gives a loop with Invariant expressions an empty cache for this run of
+    // it, returning whether it did. Scripts with watchers go without, since a
+    // watched assignment in the loop can run code that changes what the
+    // expressions depend on.
+    fn enter_loop(&mut self, hoisted: usize) -> bool {
+        if hoisted == 0 || !self.watchers.is_empty() {
+            return false;
+        }
+
+        self.loop_caches.push(vec![None; hoisted]);
+        true
+    }
+
+    fn leave_loop(&mut self, cached: bool) {
+        if cached {
+            self.loop_caches.pop();
+        }
+    }
+
+    // Loops give back the value they break with, or nil if they run until
+    // their condition is false.
+    fn run_while(&mut self, target: &While) -> Result<Object, RatexError> {
+        let cached = self.enter_loop(target.hoisted);
+
+        let result = self.run_while_iterations(target);
+
+        self.leave_loop(cached);
+
+        self.finish_loop(result, &target.else_stmt)
+    }
+
+    // Returns the value of the break that left the loop, or None if its
+    // condition became false.
+    fn run_while_iterations(&mut self, target: &While) -> Result<Option<Object>, RatexError> {
+        while self.evaluate(Rc::clone(&target.condition))?.is_truthy() {
+            self.check_deadline()?;
+
+            match self.execute(Rc::clone(&target.body)) {
+                Err(RatexError {
+                    source: RatexErrorType::Break(label, value),
+                    ..
+                }) if breaks_out_of(&label, &target.label) => return Ok(Some(value)),
+                result => result?,
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Runs a loop's else branch if the loop wasn't left with a break. It's
+    // run once the loop's cache is gone, since the optimizer treats it as
+    // code after the loop.
+    fn finish_loop(
+        &mut self,
+        broken: Result<Option<Object>, RatexError>,
+        else_stmt: &Rc<Stmt>,
+    ) -> Result<Object, RatexError> {
+        if let Some(value) = broken? {
+            return Ok(value);
+        }
+
+        if **else_stmt != Stmt::Empty {
+            self.execute(Rc::clone(else_stmt))?;
+        }
+
+        Ok(Object::Nil)
+    }
+
+    fn run_for(&mut self, target: &For) -> Result<Object, RatexError> {
+        let old_environment = Rc::clone(&self.environment);
+        self.environment = Environment::new_child(Rc::clone(&old_environment));
+
+        let cached = self.enter_loop(target.hoisted);
+
+        let result = self.run_for_iterations(target);
+
+        self.leave_loop(cached);
+
+        // the else branch can still see the loop variables
+        let result = self.finish_loop(result, &target.else_stmt);
+        self.environment = old_environment;
+
+        result
+    }
+
+    fn run_for_iterations(&mut self, target: &For) -> Result<Option<Object>, RatexError> {
+        if *target.initialiser != Stmt::Empty {
+            self.execute(Rc::clone(&target.initialiser))?;
+        }
+
+        while self.evaluate(Rc::clone(&target.condition))?.is_truthy() {
+            self.check_deadline()?;
+
+            match self.execute(Rc::clone(&target.body)) {
+                Err(RatexError {
+                    source: RatexErrorType::Break(label, value),
+                    ..
+                }) if breaks_out_of(&label, &target.label) => return Ok(Some(value)),
+                result => result?,
+            }
+
+            // every iteration gets its own copy of the loop variables, so
+            // closures made in the body keep the values they were created with
+            let next = self.environment.borrow().clone();
+            self.environment = Rc::new(RefCell::new(next));
+
+            if *target.increment != Expr::Empty {
+                self.evaluate(Rc::clone(&target.increment))?;
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn evaluate_list(
+        &mut self,
+        expr: &Rc<Expr>,
+        bracket: &RatexToken,
+    ) -> Result<Rc<RefCell<Vec<Object>>>, RatexError> {
+        match self.evaluate(Rc::clone(expr))? {
+            Object::List(list) => Ok(list),
+            _ => Err(RatexError {
+                source: RatexErrorType::InvalidIndex(bracket.line),
+                backtrace: None,
+            }),
+        }
+    }
+
+    // Converts an index into a position below `limit`, which is the list
+    // length for element access and one past it for slice bounds
+    fn list_index(index: Object, limit: usize, bracket: &RatexToken) -> Result<usize, RatexError> {
+        match index {
+            Object::Number(n) if n.fract() == 0.0 => {
+                if n < 0.0 || n >= limit as f64 {
+                    Err(RatexError {
+                        source: RatexErrorType::IndexOutOfBounds(bracket.line),
+                        backtrace: None,
+                    })
+                } else {
+                    Ok(n as usize)
+                }
+            }
+            _ => Err(RatexError {
+                source: RatexErrorType::InvalidIndex(bracket.line),
+                backtrace: None,
+            }),
+        }
+    }
+
+    fn slice_bounds(
+        &mut self,
+        list: &Rc<RefCell<Vec<Object>>>,
+        start: &Rc<Expr>,
+        end: &Rc<Expr>,
+        bracket: &RatexToken,
+    ) -> Result<(usize, usize), RatexError> {
+        let start = self.evaluate_bound(start)?;
+        let end = self.evaluate_bound(end)?;
+
+        // read after the bounds are evaluated, since they can change the list
+        let len = list.borrow().len();
+
+        let start = match start {
+            None => 0,
+            Some(start) => Self::list_index(start, len + 1, bracket)?,
+        };
+
+        let end = match end {
+            None => len,
+            Some(end) => Self::list_index(end, len + 1, bracket)?,
+        };
+
+        if start > end {
+            return Err(RatexError {
+                source: RatexErrorType::IndexOutOfBounds(bracket.line),
+                backtrace: None,
+            });
+        }
+
+        Ok((start, end))
+    }
+
+    // A bound left out of a slice is None.
+    fn evaluate_bound(&mut self, bound: &Rc<Expr>) -> Result<Option<Object>, RatexError> {
+        match **bound {
+            Expr::Empty => Ok(None),
+            _ => self.evaluate(Rc::clone(bound)).map(Some),
+        }
+    }
+
+    // Calls the close() method of an instance bound by `with`. Anything
+    // without one needs no cleanup.
+    fn close_resource(&mut self, resource: &Object) -> Result<(), RatexError> {
+        let Object::Instance(instance) = resource else {
+            return Ok(());
+        };
+
+        match RatexInstance::bound_method(instance, "close") {
+            Some(close) => self.call_function(close, Vec::new()).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn stringify(&mut self, value: &Object) -> Result<String, RatexError> {
+        self.format_object(value, &["__str__", "__repr__"])
+    }
+
+    pub fn repr(&mut self, value: &Object) -> Result<String, RatexError> {
+        match value {
+            Object::String(s) => Ok(format!("\"{s}\"")),
+            _ => self.format_object(value, &["__repr__", "__str__"]),
+        }
+    }
+
+    // Orders two values for sorting. Numbers and strings compare with their
+    // own kind, and an instance is compared by calling its compareTo(other),
+    // which should return a negative, zero or positive number.
+    pub fn compare(&mut self, left: &Object, right: &Object) -> Result<Ordering, RatexError> {
+        let invalid = |reason: &str| RatexError {
+            source: RatexErrorType::InvalidArgument("sort".to_owned(), reason.to_owned()),
+            backtrace: None,
+        };
+
+        match (left, right) {
+            (Object::Number(n1), Object::Number(n2)) => Ok(n1.total_cmp(n2)),
+            (Object::String(s1), Object::String(s2)) => Ok(s1.cmp(s2)),
+            (Object::Instance(instance), _) => {
+                let Some(method) = RatexInstance::bound_method(instance, "compareTo") else {
+                    return Err(invalid("instances need a compareTo method to be sorted"));
+                };
+
+                match self.call_function(method, vec![right.clone()])? {
+                    Object::Number(n) => Ok(n.partial_cmp(&0.0).unwrap_or(Ordering::Equal)),
+                    _ => Err(invalid("compareTo must return a number")),
+                }
+            }
+            (Object::Number(_) | Object::String(_), Object::Number(_) | Object::String(_)) => Err(
+                invalid("can't compare a number with a string without a comparator"),
+            ),
+            _ => Err(invalid(
+                "only numbers, strings or instances with compareTo can be sorted",
+            )),
+        }
+    }
+
+    // Lists and tuples show their elements as repr would, so instances in
+    // them get to format themselves too.
+    fn format_object(&mut self, value: &Object, hooks: &[&str]) -> Result<String, RatexError> {
+        match value {
+            Object::Instance(instance) => {
+                for hook in hooks {
+                    if let Some(method) = RatexInstance::bound_method(instance, hook) {
+                        return match self.call_function(method, vec![])? {
+                            Object::String(s) => Ok(s.to_string()),
+                            other => Ok(other.to_string()),
+                        };
+                    }
+                }
+            }
+            Object::List(list) => {
+                // a copy, since a hook could change the list
+                let elements = list.borrow().clone();
+                return Ok(format!("[{}]", self.repr_all(&elements)?.join(", ")));
+            }
+            Object::Tuple(tuple) if tuple.len() == 1 => {
+                return Ok(format!("({},)", self.repr(&tuple[0])?));
+            }
+            Object::Tuple(tuple) => {
+                return Ok(format!("({})", self.repr_all(tuple)?.join(", ")));
+            }
+            _ => {}
+        }
+
+        Ok(value.to_string())
+    }
+
+    fn repr_all(&mut self, values: &[Object]) -> Result<Vec<String>, RatexError> {
+        values.iter().map(|value| self.repr(value)).collect()
+    }
+
     pub fn new() -> Rc<RefCell<Self>> {
         let globals = Environment::new();
 
         globals
             .borrow_mut()
             .define("clock".to_string(), Object::Function(ClockFunction::new()));
+        globals
+            .borrow_mut()
+            .define("str".to_string(), Object::Function(StrFunction::new()));
+        globals
+            .borrow_mut()
+            .define("repr".to_string(), Object::Function(ReprFunction::new()));
+        globals
+            .borrow_mut()
+            .define("watch".to_string(), Object::Function(WatchFunction::new()));
+        globals.borrow_mut().define(
+            "pad_left".to_string(),
+            Object::Function(PadLeftFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "pad_right".to_string(),
+            Object::Function(PadRightFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "center".to_string(),
+            Object::Function(CenterFunction::new()),
+        );
+        globals
+            .borrow_mut()
+            .define("sort".to_string(), Object::Function(SortFunction::new()));
+        globals
+            .borrow_mut()
+            .define("upper".to_string(), Object::Function(UpperFunction::new()));
+        globals
+            .borrow_mut()
+            .define("lower".to_string(), Object::Function(LowerFunction::new()));
+        globals
+            .borrow_mut()
+            .define("trim".to_string(), Object::Function(TrimFunction::new()));
+        globals.borrow_mut().define(
+            "trim_start".to_string(),
+            Object::Function(TrimStartFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "trim_end".to_string(),
+            Object::Function(TrimEndFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "starts_with".to_string(),
+            Object::Function(StartsWithFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "ends_with".to_string(),
+            Object::Function(EndsWithFunction::new()),
+        );
+        globals
+            .borrow_mut()
+            .define("split".to_string(), Object::Function(SplitFunction::new()));
+        globals
+            .borrow_mut()
+            .define("chars".to_string(), Object::Function(CharsFunction::new()));
+        globals.borrow_mut().define(
+            "index_of".to_string(),
+            Object::Function(IndexOfFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "replace".to_string(),
+            Object::Function(ReplaceFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "substring".to_string(),
+            Object::Function(SubstringFunction::new()),
+        );
+        globals
+            .borrow_mut()
+            .define("ord".to_string(), Object::Function(OrdFunction::new()));
+        globals
+            .borrow_mut()
+            .define("chr".to_string(), Object::Function(ChrFunction::new()));
+        globals
+            .borrow_mut()
+            .define("push".to_string(), Object::Function(PushFunction::new()));
+        globals
+            .borrow_mut()
+            .define("pop".to_string(), Object::Function(PopFunction::new()));
+        globals
+            .borrow_mut()
+            .define("map".to_string(), Object::Function(MapFunction::new()));
+        globals.borrow_mut().define(
+            "filter".to_string(),
+            Object::Function(FilterFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "contains".to_string(),
+            Object::Function(ContainsFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "random".to_string(),
+            Object::Function(RandomFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "random_int".to_string(),
+            Object::Function(RandomIntFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "assert".to_string(),
+            Object::Function(AssertFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "assert_throws".to_string(),
+            Object::Function(AssertThrowsFunction::new()),
+        );
+        globals
+            .borrow_mut()
+            .define("spawn".to_string(), Object::Function(SpawnFunction::new()));
+        globals.borrow_mut().define(
+            "run_tasks".to_string(),
+            Object::Function(RunTasksFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "partial".to_string(),
+            Object::Function(PartialFunction::new()),
+        );
+        globals.borrow_mut().define(
+            "memoize".to_string(),
+            Object::Function(MemoizeFunction::new()),
+        );
+
+        let environment = Rc::clone(&globals);
+
+        Rc::new(RefCell::new(RatexInterpreter {
+            environment,
+            locals: HashMap::new(),
+            globals,
+            steps: 0,
+            max_steps: None,
+            float_epsilon: None,
+            timeout: None,
+            deadline: None,
+            watchers: HashMap::new(),
+            tasks: VecDeque::new(),
+            running_tasks: false,
+            loop_caches: Vec::new(),
+            clock: Box::new(SystemClock),
+            rng_state: seed_state(clock_seed()),
+            out: BufWriter::new(Box::new(io::stdout())),
+            main_args: None,
+        }))
+    }
+
+    // The options below are set on a new interpreter before it runs
+    // anything, and can be chained:
+    // `interpreter.with_step_limit(1000).with_timeout(limit)`.
+    pub fn with_step_limit(&mut self, max_steps: u64) -> &mut Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    // The timeout runs from the start of each run, not from when it's set.
+    pub fn with_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // Seeds random() and random_int() so that a script sees the same numbers
+    // on every run. Otherwise the seed comes from the clock.
+    pub fn with_seed(&mut self, seed: u64) -> &mut Self {
+        self.rng_state = seed_state(seed);
+        self
+    }
+
+    // Calls the script's main function, if it has one, after the script has
+    // run, passing it `args`.
+    pub fn with_main_entry(&mut self, args: Vec<String>) -> &mut Self {
+        self.main_args = Some(args);
+        self
+    }
+
+    pub fn with_clock(&mut self, clock: Box<dyn Clock>) -> &mut Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_output(&mut self, out: Box<dyn Output>) -> &mut Self {
+        self.out = BufWriter::new(out);
+        self
+    }
+
+    pub fn print_line(&mut self, text: &str) -> Result<(), RatexError> {
+        writeln!(self.out, "{}", text).map_err(output_failed)
+    }
+
+    // Writes out whatever print has buffered. Anything else that writes to
+    // stdout, like the REPL echoing a value, needs to flush first to keep the
+    // output in order.
+    pub fn flush(&mut self) -> Result<(), RatexError> {
+        self.out.flush().map_err(output_failed)
+    }
+
+    pub fn now(&self) -> f64 {
+        self.clock.now()
+    }
+
+    // Makes == and != treat numbers within `epsilon` of each other (scaled by
+    // their magnitude once it exceeds 1) as equal. This hides rounding noise
+    // like 0.1 + 0.2 != 0.3, at the cost of equality no longer being exact or
+    // transitive, so it is off by default. Panics if `epsilon` is negative or
+    // not finite, which would make even equal numbers unequal.
+    pub fn with_float_epsilon(&mut self, epsilon: f64) -> &mut Self {
+        assert!(
+            epsilon.is_finite() && epsilon >= 0.0,
+            "float epsilon must be finite and not negative, got {}",
+            epsilon
+        );
+        self.float_epsilon = Some(epsilon);
+        self
+    }
+
+    // NaN is never equal to anything, itself included. Infinities are only
+    // equal to themselves, which the epsilon check alone would get wrong
+    // since inf - inf is NaN.
+    fn numbers_equal(&self, n1: f64, n2: f64) -> bool {
+        match self.float_epsilon {
+            Some(_) if n1.is_infinite() || n2.is_infinite() => n1 == n2,
+            Some(epsilon) => (n1 - n2).abs() <= epsilon * n1.abs().max(n2.abs()).max(1.0),
+            None => n1 == n2,
+        }
+    }
+
+    // Assigns to the variable the resolver found for the target, or to a
+    // global when it found none.
+    fn assign_to(&mut self, target: &Rc<Assign>, value: Object) -> Result<(), RatexError> {
+        let local = self.resolved(&Expr::Assign(Rc::clone(target)));
+
+        if let Some((distance, slot)) = local {
+            Environment::assign_at(
+                &self.environment,
+                distance,
+                slot,
+                &target.name.lexeme,
+                value,
+            )
+            .map_err(|e| at_line(e, target.name.line))
+        } else {
+            self.assign_variable(target.name.lexeme.clone(), value)
+                .map_err(|e| at_line(e, target.name.line))
+        }
+    }
+
+    // The values of a tuple being destructured into `count` names.
+    fn unpack(
+        value: &Object,
+        count: usize,
+        token: &RatexToken,
+    ) -> Result<Rc<Vec<Object>>, RatexError> {
+        match value {
+            Object::Tuple(tuple) if tuple.len() == count => Ok(Rc::clone(tuple)),
+            _ => Err(RatexError {
+                source: RatexErrorType::CantUnpack(token.line, count),
+                backtrace: None,
+            }),
+        }
+    }
+
+    // Numbers are compared the way == compares them.
+    fn matches_literal(&self, value: &Object, literal: &Object) -> bool {
+        match (value, literal) {
+            (Object::Number(n1), Object::Number(n2)) => self.numbers_equal(*n1, *n2),
+            _ => value == literal,
+        }
+    }
+
+    fn declare_class(
+        &mut self,
+        target: &Class,
+        variant: Option<Rc<RatexVariant>>,
+    ) -> Result<(), RatexError> {
+        self.environment
+            .borrow_mut()
+            .define(target.name.lexeme.clone(), Object::Nil);
+
+        let mut methods = HashMap::new();
+
+        for declaration in &target.methods {
+            if let Stmt::Fun(fun) = declaration.as_ref() {
+                let function = RatexFunction::new(
+                    fun.name.lexeme.clone(),
+                    Rc::clone(declaration),
+                    Rc::clone(&self.environment),
+                );
+                methods.insert(fun.name.lexeme.clone(), function);
+            }
+        }
+
+        let fields = target.fields.iter().map(|f| f.lexeme.clone()).collect();
+        let klass = RatexClass::new(target.name.lexeme.clone(), fields, methods, variant);
+
+        self.assign_variable(target.name.lexeme.clone(), Object::Class(Rc::new(klass)))
+    }
+
+    // Whether the value fits the pattern, pushing the values of the names it
+    // binds in the order Match::bindings gives them.
+    fn match_pattern(
+        &mut self,
+        pattern: &Expr,
+        value: &Object,
+        line: u32,
+        bound: &mut Vec<Object>,
+    ) -> Result<bool, RatexError> {
+        match pattern {
+            Expr::Empty => Ok(true),
+            Expr::Variable(_) => {
+                bound.push(value.clone());
+                Ok(true)
+            }
+            Expr::Literal(literal) => Ok(self.matches_literal(value, &literal.value)),
+            Expr::Call(call) => {
+                let klass = self.pattern_class(call, line)?;
+
+                let Object::Instance(instance) = value else {
+                    return Ok(false);
+                };
+
+                if !instance.borrow().is_instance_of(&klass) {
+                    return Ok(false);
+                }
+
+                for (field, sub_pattern) in klass.fields().iter().zip(&call.arguments) {
+                    let field_value = instance.borrow().field(field).unwrap_or(Object::Nil);
+
+                    if !self.match_pattern(sub_pattern, &field_value, line, bound)? {
+                        return Ok(false);
+                    }
+                }
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
 
-        let environment = Rc::clone(&globals);
+    // The class a constructor pattern names, which needs a pattern for each
+    // of its fields.
+    fn pattern_class(&mut self, call: &Call, line: u32) -> Result<Rc<RatexClass>, RatexError> {
+        let Object::Class(klass) = self.evaluate(Rc::clone(&call.callee))? else {
+            return Err(RatexError {
+                source: RatexErrorType::PatternNotAClass(line),
+                backtrace: None,
+            });
+        };
+
+        if klass.fields().len() != call.arguments.len() {
+            return Err(RatexError {
+                source: RatexErrorType::InvalidPattern(line, klass.name(), klass.fields().len()),
+                backtrace: None,
+            });
+        }
 
-        Rc::new(RefCell::new(RatexInterpreter {
-            environment,
-            locals: HashMap::new(),
-            globals,
-        }))
+        Ok(klass)
     }
 
-    fn look_up_variable(&self, name: RatexToken, expr: Expr) -> Result<Object, RatexError> {
-        let res = self.locals.get(&expr);
+    // A match over a variant's constructors has to cover all of them, unless
+    // it has an arm that matches anything. A constructor only counts as
+    // covered by an arm that doesn't look inside its fields.
+    fn check_exhaustive(&mut self, target: &Match) -> Result<(), RatexError> {
+        let line = target.keyword.line;
+        let mut variant = None;
+        let mut covered = Vec::new();
+
+        for (pattern, _) in &target.arms {
+            match pattern.as_ref() {
+                pattern if Match::is_irrefutable(pattern) => return Ok(()),
+                Expr::Call(call) => {
+                    let klass = self.pattern_class(call, line)?;
+
+                    if let Some(of) = klass.variant() {
+                        variant.get_or_insert_with(|| Rc::clone(of));
+
+                        if call
+                            .arguments
+                            .iter()
+                            .all(|field| Match::is_irrefutable(field))
+                        {
+                            covered.push(klass.name());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let Some(variant) = variant else {
+            return Ok(());
+        };
 
-        if let Some(distance) = res {
-            Ok(Environment::get_at(
-                Rc::clone(&self.environment),
-                *distance,
-                name.lexeme,
-            ))
-        } else {
-            Ok(self.globals.borrow().get(name.lexeme)?)
+        let missing: Vec<&str> = variant
+            .constructors
+            .iter()
+            .filter(|constructor| !covered.contains(constructor))
+            .map(|constructor| constructor.as_str())
+            .collect();
+
+        if missing.is_empty() {
+            return Ok(());
         }
-    }
-}
 
-impl ExprVisitor<Object> for RatexInterpreter {
-    fn visit_binary(&mut self, target: Rc<Binary>) -> Result<Object, RatexError> {
-        let left: Object = self.evaluate(Rc::clone(&target.left))?;
-        let right: Object = self.evaluate(Rc::clone(&target.right))?;
+        Err(RatexError {
+            source: RatexErrorType::NonExhaustiveMatch(
+                line,
+                variant.name.clone(),
+                missing.join(", "),
+            ),
+            backtrace: None,
+        })
+    }
 
+    pub fn binary_op(
+        &self,
+        operator: &RatexToken,
+        left: Object,
+        right: Object,
+    ) -> Result<Object, RatexError> {
         match (left, right) {
-            (Object::Number(n1), Object::Number(n2)) => match target.operator.token_type {
+            (Object::Number(n1), Object::Number(n2)) => match operator.token_type {
                 RXTT::Minus => Ok(Object::Number(n1 - n2)),
+                RXTT::Slash if n2 == 0.0 => Err(RatexError {
+                    source: RatexErrorType::DivisionByZero(operator.line),
+                    backtrace: None,
+                }),
                 RXTT::Slash => Ok(Object::Number(n1 / n2)),
                 RXTT::Star => Ok(Object::Number(n1 * n2)),
                 RXTT::Plus => Ok(Object::Number(n1 + n2)),
@@ -117,20 +1270,25 @@ impl ExprVisitor<Object> for RatexInterpreter {
                 RXTT::GreaterEqual => Ok(Object::Bool(n1 >= n2)),
                 RXTT::Less => Ok(Object::Bool(n1 < n2)),
                 RXTT::LessEqual => Ok(Object::Bool(n1 <= n2)),
-                RXTT::BangEqual => Ok(Object::Bool(n1 != n2)),
-                RXTT::EqualEqual => Ok(Object::Bool(n1 == n2)),
+                RXTT::BangEqual => Ok(Object::Bool(!self.numbers_equal(n1, n2))),
+                RXTT::EqualEqual => Ok(Object::Bool(self.numbers_equal(n1, n2))),
                 _ => Ok(Object::Nil),
             },
-            (Object::String(s1), Object::String(s2)) => match target.operator.token_type {
-                RXTT::Plus => Ok(Object::String(s1 + &s2)),
+            (Object::String(s1), Object::String(s2)) => match operator.token_type {
+                RXTT::Plus => Ok(Object::from(s1.to_string() + &s2)),
+                // lexicographic by code point
+                RXTT::Greater => Ok(Object::Bool(s1 > s2)),
+                RXTT::GreaterEqual => Ok(Object::Bool(s1 >= s2)),
+                RXTT::Less => Ok(Object::Bool(s1 < s2)),
+                RXTT::LessEqual => Ok(Object::Bool(s1 <= s2)),
                 RXTT::BangEqual => Ok(Object::Bool(s1 != s2)),
                 RXTT::EqualEqual => Ok(Object::Bool(s1 == s2)),
                 _ => Ok(Object::Nil),
             },
-            (Object::Bool(b1), Object::Bool(b2)) => match target.operator.token_type {
-                RXTT::Greater => Ok(Object::Bool(b1 > b2)),
+            (Object::Bool(b1), Object::Bool(b2)) => match operator.token_type {
+                RXTT::Greater => Ok(Object::Bool(b1 & !b2)),
                 RXTT::GreaterEqual => Ok(Object::Bool(b1 >= b2)),
-                RXTT::Less => Ok(Object::Bool(b1 < b2)),
+                RXTT::Less => Ok(Object::Bool(!b1 & b2)),
                 RXTT::LessEqual => Ok(Object::Bool(b1 <= b2)),
                 RXTT::BangEqual => Ok(Object::Bool(b1 != b2)),
                 RXTT::EqualEqual => Ok(Object::Bool(b1 == b2)),
@@ -140,10 +1298,8 @@ impl ExprVisitor<Object> for RatexInterpreter {
         }
     }
 
-    fn visit_unary(&mut self, target: Rc<Unary>) -> Result<Object, RatexError> {
-        let right: Object = self.evaluate(target.right.clone())?;
-
-        match target.operator.token_type {
+    pub fn unary_op(operator: &RatexToken, right: Object) -> Result<Object, RatexError> {
+        match operator.token_type {
             RXTT::Minus => match right {
                 Object::Bool(b) => Ok(Object::Bool(!b)),
                 Object::Number(n) => Ok(Object::Number(-n)),
@@ -158,26 +1314,76 @@ impl ExprVisitor<Object> for RatexInterpreter {
         }
     }
 
+    fn look_up_variable(&self, name: &RatexToken, expr: Expr) -> Result<Object, RatexError> {
+        let res = self.resolved(&expr);
+
+        if let Some((distance, slot)) = res {
+            Environment::get_at(&self.environment, distance, slot, &name.lexeme)
+        } else {
+            Ok(self
+                .globals
+                .borrow()
+                .get(name.lexeme.clone())
+                .map_err(|e| at_line(e, name.line))?)
+        }
+    }
+}
+
+impl ExprVisitor<Object> for RatexInterpreter {
+    fn visit_binary(&mut self, target: Rc<Binary>) -> Result<Object, RatexError> {
+        let left: Object = self.evaluate(Rc::clone(&target.left))?;
+        let right: Object = self.evaluate(Rc::clone(&target.right))?;
+
+        self.binary_op(&target.operator, left, right)
+    }
+
+    fn visit_unary(&mut self, target: Rc<Unary>) -> Result<Object, RatexError> {
+        let right: Object = self.evaluate(target.right.clone())?;
+
+        Self::unary_op(&target.operator, right)
+    }
+
+    // Gives the first comparison that's false, like the ands it stands for,
+    // or the last one if they're all true.
+    fn visit_chain(&mut self, target: Rc<Chain>) -> Result<Object, RatexError> {
+        let mut left = self.evaluate(Rc::clone(&target.operands[0]))?;
+        let mut result = Object::Nil;
+
+        for (operator, operand) in target.operators.iter().zip(&target.operands[1..]) {
+            let right = self.evaluate(Rc::clone(operand))?;
+            result = self.binary_op(operator, left, right.clone())?;
+
+            if !result.is_truthy() {
+                break;
+            }
+
+            left = right;
+        }
+
+        Ok(result)
+    }
+
     fn visit_logical(&mut self, target: Rc<Logical>) -> Result<Object, RatexError> {
         let left = self.evaluate(target.left.clone())?;
 
         match target.operator.token_type {
             RXTT::Or => {
                 if left.is_truthy() {
-                    return Ok(left);
+                    Ok(left)
                 } else {
-                    return Ok(self.evaluate(target.right.clone())?);
+                    self.evaluate(target.right.clone())
                 }
             }
             RXTT::And => {
                 if !left.is_truthy() {
-                    return Ok(left);
+                    Ok(left)
                 } else {
-                    return Ok(self.evaluate(target.right.clone())?);
+                    self.evaluate(target.right.clone())
                 }
             }
             _ => Err(RatexError {
                 source: RatexErrorType::InvalidLogicalOperation(target.operator.line),
+                backtrace: None,
             }),
         }
     }
@@ -191,29 +1397,32 @@ impl ExprVisitor<Object> for RatexInterpreter {
     }
 
     fn visit_variable(&mut self, target: Rc<Variable>) -> Result<Object, RatexError> {
-        return self.look_up_variable(target.name.clone(), Expr::Variable(target.clone()));
+        self.look_up_variable(&target.name, Expr::Variable(Rc::clone(&target)))
     }
 
     fn visit_assign(&mut self, target: Rc<Assign>) -> Result<Object, RatexError> {
         let value = self.evaluate(target.value.clone())?;
-        let distance = self.locals.get(&Expr::Assign(target.clone()));
+        self.assign_to(&target, value.clone())?;
 
-        if let Some(d) = distance {
-            Environment::assign_at(
-                Rc::clone(&self.environment),
-                *d,
-                target.name.lexeme.clone(),
-                value.clone(),
-            );
-        } else {
-            self.environment
-                .borrow_mut()
-                .assign(target.name.lexeme.clone(), value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_assign_tuple(&mut self, target: Rc<AssignTuple>) -> Result<Object, RatexError> {
+        let value = self.evaluate(Rc::clone(&target.value))?;
+        let values = Self::unpack(&value, target.targets.len(), &target.equals)?;
+
+        for (assign, value) in target.targets.iter().zip(values.iter()) {
+            if let Expr::Assign(assign) = assign.as_ref() {
+                self.assign_to(assign, value.clone())?;
+            }
         }
+
         Ok(value)
     }
 
     fn visit_call(&mut self, target: Rc<Call>) -> Result<Object, RatexError> {
+        self.check_deadline()?;
+
         let callee = self.evaluate(target.callee.clone())?;
 
         let mut arguments = Vec::new();
@@ -222,58 +1431,138 @@ impl ExprVisitor<Object> for RatexInterpreter {
             arguments.push(self.evaluate(Rc::clone(argument))?);
         }
 
-        match callee {
-            Object::Function(fun) => {
-                if arguments.len() == fun.borrow().arity()? {
-                    match fun.borrow().call(self, arguments) {
-                        Ok(obj) => return Ok(obj),
-                        Err(e) => {
-                            if let RatexErrorType::Return(obj) = e.source {
-                                return Ok(obj);
-                            }
-                            return Err(e);
-                        }
-                    }
-                } else {
-                    return Err(RatexError {
-                        source: RatexErrorType::IncompatibleArity,
-                    });
-                }
-            }
-            Object::Class(klass) => return Ok(klass.call(self, arguments)?),
-            Object::Instance(instance) => {}
-            _ => {}
-        }
-
-        Err(RatexError {
-            source: RatexErrorType::InvalidFunctionCall,
-        })
+        self.call_value(callee, arguments, target.paren.line)
     }
 
     fn visit_lambda(&mut self, target: Rc<Lambda>) -> Result<Object, RatexError> {
         let declaration = Fun::new(
             RatexToken::default(),
             target.params.clone(),
+            target.param_types.clone(),
+            target.defaults.clone(),
+            target.variadic,
+            None,
             target.body.clone(),
         );
 
+        let Some(name) = &target.name else {
+            return Ok(Object::Function(RatexFunction::new(
+                "anonymous".to_string(),
+                declaration,
+                Rc::clone(&self.environment),
+            )));
+        };
+
+        // the name lives in a scope of its own between the lambda and where
+        // it was made, so it doesn't leak out
+        let closure = Environment::new_child(Rc::clone(&self.environment));
         let function = Object::Function(RatexFunction::new(
-            "anonymous".to_string(),
+            name.lexeme.clone(),
             declaration,
-            Rc::clone(&self.environment),
+            Rc::clone(&closure),
         ));
 
+        closure
+            .borrow_mut()
+            .define(name.lexeme.clone(), function.clone());
+
         Ok(function)
     }
 
+    // Classes don't have superclasses yet, so this only checks the instance's
+    // own class. Anything that isn't an instance is never a member.
+    fn visit_type_check(&mut self, target: Rc<TypeCheck>) -> Result<Object, RatexError> {
+        let object = self.evaluate(Rc::clone(&target.object))?;
+
+        let Object::Class(klass) = self.evaluate(Rc::clone(&target.class))? else {
+            return Err(RatexError {
+                source: RatexErrorType::NotAClass(target.keyword.line),
+                backtrace: None,
+            });
+        };
+
+        Ok(Object::Bool(match object {
+            Object::Instance(instance) => instance.borrow().is_instance_of(&klass),
+            _ => false,
+        }))
+    }
+
+    fn visit_loop(&mut self, target: Rc<Loop>) -> Result<Object, RatexError> {
+        match target.statement.as_ref() {
+            Stmt::While(target) => self.run_while(target),
+            Stmt::For(target) => self.run_for(target),
+            _ => unreachable!("only while and for loops are parsed as expressions"),
+        }
+    }
+
+    // The cache on top is the one for the loop the expression is in. The
+    // optimizer only marks loops that can't call out to other code, so no
+    // other loop can start while one of them is running. When there's no
+    // cache, as when a task steps through the loop itself, the expression is
+    // just evaluated.
+    fn visit_invariant(&mut self, target: Rc<Invariant>) -> Result<Object, RatexError> {
+        let cached = self
+            .loop_caches
+            .last()
+            .and_then(|cache| cache.get(target.index).cloned().flatten());
+
+        if let Some(value) = cached {
+            return Ok(value);
+        }
+
+        let value = self.evaluate(Rc::clone(&target.expr))?;
+
+        if let Some(slot) = self
+            .loop_caches
+            .last_mut()
+            .and_then(|cache| cache.get_mut(target.index))
+        {
+            *slot = Some(value.clone());
+        }
+
+        Ok(value)
+    }
+
+    fn visit_match(&mut self, target: Rc<Match>) -> Result<Object, RatexError> {
+        let value = self.evaluate(Rc::clone(&target.scrutinee))?;
+
+        self.check_exhaustive(&target)?;
+
+        for (pattern, result) in &target.arms {
+            let mut bound = Vec::new();
+
+            if !self.match_pattern(pattern, &value, target.keyword.line, &mut bound)? {
+                continue;
+            }
+
+            if bound.is_empty() {
+                return self.evaluate(Rc::clone(result));
+            }
+
+            let arm_env = Environment::new_child(Rc::clone(&self.environment));
+
+            for (name, value) in Match::bindings(pattern).into_iter().zip(bound) {
+                arm_env.borrow_mut().define(name.lexeme, value);
+            }
+
+            return self.evaluate_in(Rc::clone(result), arm_env);
+        }
+
+        Err(RatexError {
+            source: RatexErrorType::NoMatchingArm(target.keyword.line),
+            backtrace: None,
+        })
+    }
+
     fn visit_get(&mut self, target: Rc<Get>) -> Result<Object, RatexError> {
         let obj = self.evaluate(target.object.clone())?;
         if let Object::Instance(instance) = obj {
-            return Ok(instance.borrow().get(target.name.lexeme.clone())?);
+            return RatexInstance::get(&instance, target.name.lexeme.clone(), self);
         }
 
         Err(RatexError {
             source: RatexErrorType::InvalidFunctionCall,
+            backtrace: None,
         })
     }
 
@@ -282,19 +1571,111 @@ impl ExprVisitor<Object> for RatexInterpreter {
 
         if let Object::Instance(instance) = object {
             let value = self.evaluate(target.value.clone())?;
-            instance
-                .borrow_mut()
-                .set(target.name.lexeme.clone(), value.clone());
+            RatexInstance::set(&instance, target.name.lexeme.clone(), value.clone(), self)?;
             Ok(value)
         } else {
             Err(RatexError {
                 source: RatexErrorType::NonInstanceSet,
+                backtrace: None,
             })
         }
     }
 
     fn visit_this(&mut self, target: Rc<This>) -> Result<Object, RatexError> {
-        self.look_up_variable(target.keyword.clone(), Expr::This(target))
+        self.look_up_variable(&target.keyword, Expr::This(Rc::clone(&target)))
+    }
+
+    fn visit_list_literal(&mut self, target: Rc<ListLiteral>) -> Result<Object, RatexError> {
+        let mut elements = Vec::new();
+
+        for element in &target.elements {
+            elements.push(self.evaluate(Rc::clone(element))?);
+        }
+
+        Ok(Object::List(Rc::new(RefCell::new(elements))))
+    }
+
+    fn visit_tuple_literal(&mut self, target: Rc<TupleLiteral>) -> Result<Object, RatexError> {
+        let mut elements = Vec::new();
+
+        for element in &target.elements {
+            elements.push(self.evaluate(Rc::clone(element))?);
+        }
+
+        Ok(Object::Tuple(Rc::new(elements)))
+    }
+
+    fn visit_index(&mut self, target: Rc<Index>) -> Result<Object, RatexError> {
+        let object = self.evaluate(Rc::clone(&target.object))?;
+        let index = self.evaluate(Rc::clone(&target.index))?;
+
+        match object {
+            Object::List(list) => {
+                let list = list.borrow();
+                let i = Self::list_index(index, list.len(), &target.bracket)?;
+
+                Ok(list[i].clone())
+            }
+            Object::Tuple(tuple) => {
+                let i = Self::list_index(index, tuple.len(), &target.bracket)?;
+
+                Ok(tuple[i].clone())
+            }
+            // strings are indexed by character, not byte, and give back a
+            // one-character string
+            Object::String(s) => {
+                let i = Self::list_index(index, s.chars().count(), &target.bracket)?;
+
+                Ok(Object::from(s.chars().nth(i).unwrap().to_string()))
+            }
+            _ => Err(RatexError {
+                source: RatexErrorType::InvalidIndex(target.bracket.line),
+                backtrace: None,
+            }),
+        }
+    }
+
+    fn visit_index_set(&mut self, target: Rc<IndexSet>) -> Result<Object, RatexError> {
+        let list = self.evaluate_list(&target.object, &target.bracket)?;
+        let index = self.evaluate(Rc::clone(&target.index))?;
+        let value = self.evaluate(Rc::clone(&target.value))?;
+
+        let mut list = list.borrow_mut();
+        let i = Self::list_index(index, list.len(), &target.bracket)?;
+        list[i] = value.clone();
+
+        Ok(value)
+    }
+
+    fn visit_slice(&mut self, target: Rc<Slice>) -> Result<Object, RatexError> {
+        let list = self.evaluate_list(&target.object, &target.bracket)?;
+        let (start, end) = self.slice_bounds(&list, &target.start, &target.end, &target.bracket)?;
+
+        let elements = list.borrow()[start..end].to_vec();
+
+        Ok(Object::List(Rc::new(RefCell::new(elements))))
+    }
+
+    fn visit_slice_set(&mut self, target: Rc<SliceSet>) -> Result<Object, RatexError> {
+        let list = self.evaluate_list(&target.object, &target.bracket)?;
+        // the new elements can change the list's length, so they're evaluated
+        // before the bounds are checked against it
+        let value = self.evaluate(Rc::clone(&target.value))?;
+        let (start, end) = self.slice_bounds(&list, &target.start, &target.end, &target.bracket)?;
+
+        let replacement = match &value {
+            Object::List(other) => other.borrow().clone(),
+            _ => {
+                return Err(RatexError {
+                    source: RatexErrorType::NonListSliceAssignment(target.bracket.line),
+                    backtrace: None,
+                })
+            }
+        };
+
+        list.borrow_mut().splice(start..end, replacement);
+
+        Ok(value)
     }
 }
 
@@ -330,42 +1711,141 @@ impl StmtVisitor<()> for RatexInterpreter {
         let function = RatexFunction::new(
             name.clone(),
             Rc::new(Stmt::Fun(target)),
-            Environment::new_child(Rc::clone(&self.environment)),
+            Rc::clone(&self.environment),
         );
 
-        self.environment
-            .borrow_mut()
-            .define(name, Object::Function(function));
+        self.define_variable(name, Object::Function(function))
+    }
 
+    fn visit_while(&mut self, target: Rc<While>) -> Result<(), RatexError> {
+        self.run_while(&target)?;
         Ok(())
     }
 
-    fn visit_while(&mut self, target: Rc<While>) -> Result<(), RatexError> {
-        while self.evaluate(Rc::clone(&target.condition))?.is_truthy() {
-            self.execute(Rc::clone(&target.body))?
+    fn visit_for(&mut self, target: Rc<For>) -> Result<(), RatexError> {
+        self.run_for(&target)?;
+        Ok(())
+    }
+
+    fn visit_yield(&mut self, target: Rc<Yield>) -> Result<(), RatexError> {
+        Err(RatexError {
+            source: RatexErrorType::YieldOutsideTask(target.keyword.line),
+            backtrace: None,
+        })
+    }
+
+    // Only values thrown by `throw` are caught. Runtime errors, and the
+    // interrupts used for break and return, carry on unwinding.
+    fn visit_try(&mut self, target: Rc<Try>) -> Result<(), RatexError> {
+        let body_env = Environment::new_child(Rc::clone(&self.environment));
+
+        match self.execute_block(target.body.clone(), body_env) {
+            Err(RatexError {
+                source: RatexErrorType::UserThrow(value),
+                ..
+            }) => {
+                let handler_env = Environment::new_child(Rc::clone(&self.environment));
+                handler_env
+                    .borrow_mut()
+                    .define(target.name.lexeme.clone(), value);
+
+                self.execute_block(target.handler.clone(), handler_env)
+            }
+            result => result,
         }
+    }
 
-        Ok(())
+    fn visit_with(&mut self, target: Rc<With>) -> Result<(), RatexError> {
+        let resource = self.evaluate(Rc::clone(&target.resource))?;
+
+        let body_env = Environment::new_child(Rc::clone(&self.environment));
+        body_env
+            .borrow_mut()
+            .define(target.name.lexeme.clone(), resource.clone());
+
+        let result = self.execute_block(target.body.clone(), body_env);
+
+        // the body's error, throw or return is kept over one from close()
+        let closed = self.close_resource(&resource);
+        result.and(closed)
+    }
+
+    fn visit_throw(&mut self, target: Rc<Throw>) -> Result<(), RatexError> {
+        let value = self.evaluate(Rc::clone(&target.value))?;
+
+        Err(RatexError {
+            source: RatexErrorType::UserThrow(value),
+            backtrace: None,
+        })
     }
 
-    fn visit_break(&mut self, _: Rc<Break>) -> Result<(), RatexError> {
+    fn visit_break(&mut self, target: Rc<Break>) -> Result<(), RatexError> {
+        let value = match *target.value {
+            Expr::Empty => Object::Nil,
+            _ => self.evaluate(Rc::clone(&target.value))?,
+        };
+
         Err(RatexError {
-            source: RatexErrorType::Break,
+            source: RatexErrorType::Break(
+                target
+                    .label
+                    .as_ref()
+                    .map(|label| Rc::new(label.lexeme.clone())),
+                value,
+            ),
+            backtrace: None,
         })
     }
 
     fn visit_print(&mut self, target: Rc<Print>) -> Result<(), RatexError> {
         let value = self.evaluate(target.expr.clone())?;
-        println!("{value}");
-        Ok(())
+        let text = self.stringify(&value)?;
+        self.print_line(&text)
     }
 
     fn visit_return(&mut self, target: Rc<Return>) -> Result<(), RatexError> {
+        let value = match *target.value {
+            Expr::Empty => Object::Nil,
+            // only errors are reported as the return failing, so that a throw
+            // can still be caught and a limit still stops the script
+            _ => self.evaluate(target.value.clone()).map_err(|mut e| {
+                if e.source.is_interrupt() || e.source.stops_script() {
+                    return e;
+                }
+
+                RatexError {
+                    // the calls made while evaluating the value are kept
+                    // on the outer error, so they stay together with the
+                    // rest
+                    backtrace: e.backtrace.take(),
+                    source: RatexErrorType::FailedReturn(target.keyword.line, Box::new(e)),
+                }
+            })?,
+        };
+
         Err(RatexError {
-            source: RatexErrorType::Return(self.evaluate(target.value.clone())?),
+            source: RatexErrorType::Return(value),
+            backtrace: None,
         })
     }
 
+    fn visit_var_tuple(&mut self, target: Rc<VarTuple>) -> Result<(), RatexError> {
+        let value = self.evaluate(Rc::clone(&target.initialiser))?;
+        let values = Self::unpack(&value, target.names.len(), &target.paren)?;
+
+        for (name, value) in target.names.iter().zip(values.iter()) {
+            self.define_variable(name.lexeme.clone(), value.clone())?;
+
+            if target.constant {
+                self.environment
+                    .borrow_mut()
+                    .make_constant(name.lexeme.clone());
+            }
+        }
+
+        Ok(())
+    }
+
     fn visit_var(&mut self, target: Rc<Var>) -> Result<(), RatexError> {
         let mut value = Object::Nil;
 
@@ -377,47 +1857,160 @@ impl StmtVisitor<()> for RatexInterpreter {
         }
 
         match &target.name.token_type {
-            RXTT::Identifier => self
-                .environment
-                .borrow_mut()
-                .define(target.name.lexeme.clone(), value),
-            _ => {
-                return Err(RatexError {
-                    source: RatexErrorType::ExpectedToken(
-                        target.name.line,
-                        "Identifier".to_owned(),
-                    ),
-                });
+            RXTT::Identifier => {
+                self.define_variable(target.name.lexeme.clone(), value)?;
+
+                if target.constant {
+                    self.environment
+                        .borrow_mut()
+                        .make_constant(target.name.lexeme.clone());
+                }
+
+                Ok(())
             }
+            _ => Err(RatexError {
+                source: RatexErrorType::ExpectedToken(target.name.line, "Identifier".to_owned()),
+                backtrace: None,
+            }),
+        }
+    }
+
+    fn visit_variant(&mut self, target: Rc<Variant>) -> Result<(), RatexError> {
+        let constructors: Vec<&Rc<Class>> = target
+            .constructors
+            .iter()
+            .filter_map(|constructor| match constructor.as_ref() {
+                Stmt::Class(class) => Some(class),
+                _ => None,
+            })
+            .collect();
+
+        let variant = Rc::new(RatexVariant {
+            name: target.name.lexeme.clone(),
+            constructors: constructors
+                .iter()
+                .map(|class| class.name.lexeme.clone())
+                .collect(),
+        });
+
+        for class in constructors {
+            self.step()?;
+            self.declare_class(class, Some(Rc::clone(&variant)))?;
         }
 
         Ok(())
     }
 
     fn visit_class(&mut self, target: Rc<Class>) -> Result<(), RatexError> {
-        self.environment
-            .borrow_mut()
-            .define(target.name.lexeme.clone(), Object::Nil);
+        self.declare_class(&target, None)
+    }
+}
 
-        let mut methods = HashMap::new();
+// xorshift gets stuck at zero, so a zero seed is swapped for a fixed one
+fn seed_state(seed: u64) -> u64 {
+    if seed == 0 {
+        0x9e37_79b9_7f4a_7c15
+    } else {
+        seed
+    }
+}
 
-        for declaration in &target.methods {
-            if let Stmt::Fun(fun) = declaration.as_ref() {
-                let function = RatexFunction::new(
-                    fun.name.lexeme.clone(),
-                    Rc::clone(declaration),
-                    Environment::new_child(Rc::clone(&self.environment)),
-                );
-                methods.insert(fun.name.lexeme.clone(), function);
-            }
-        }
+// Whether a break with the given label leaves a loop with `loop_label`. An
+// unlabelled break leaves the innermost loop.
+pub fn breaks_out_of(label: &Option<Rc<String>>, loop_label: &Option<RatexToken>) -> bool {
+    match (label, loop_label) {
+        (None, _) => true,
+        (Some(label), Some(loop_label)) => **label == loop_label.lexeme,
+        (Some(_), None) => false,
+    }
+}
 
-        let klass = RatexClass::new(target.name.lexeme.clone(), methods);
+// Fills in the line of an error raised by the environment.
+fn at_line(error: RatexError, line: u32) -> RatexError {
+    match error.source {
+        RatexErrorType::UndefinedIdentifier(name, None) => RatexError {
+            source: RatexErrorType::UndefinedIdentifier(name, Some(line)),
+            backtrace: None,
+        },
+        RatexErrorType::AssignToConst(name, None) => RatexError {
+            source: RatexErrorType::AssignToConst(name, Some(line)),
+            backtrace: None,
+        },
+        _ => error,
+    }
+}
 
-        self.environment
-            .borrow_mut()
-            .assign(target.name.lexeme.clone(), Object::Class(klass))?;
+// Adds the call an error is leaving to its backtrace. The name is only worked
+// out here, since most calls don't fail, and interrupts pass through as they
+// are.
+fn with_frame(mut error: RatexError, name: impl FnOnce() -> String, line: u32) -> RatexError {
+    if matches!(
+        error.source,
+        RatexErrorType::Break(..) | RatexErrorType::Return(_)
+    ) {
+        return error;
+    }
 
-        Ok(())
+    error
+        .backtrace
+        .get_or_insert_with(Box::default)
+        .push((name(), line));
+    error
+}
+
+fn output_failed(e: io::Error) -> RatexError {
+    RatexError {
+        source: RatexErrorType::OutputFailed(e.to_string()),
+        backtrace: None,
+    }
+}
+
+fn clock_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_an_expression() {
+        let interpreter = RatexInterpreter::new();
+
+        let value = interpreter.borrow_mut().evaluate_expr("1 + 2 * 3").unwrap();
+
+        assert_eq!(value, Object::from(7.0));
+    }
+
+    #[test]
+    fn rejects_trailing_input() {
+        let interpreter = RatexInterpreter::new();
+
+        let result = interpreter.borrow_mut().evaluate_expr("1 + 2; 3");
+
+        assert!(matches!(
+            result,
+            Err(RatexError {
+                source: RatexErrorType::UnexpectedToken(1, ref token),
+                ..
+            }) if token == "3"
+        ));
+    }
+
+    #[test]
+    fn undefined_variable_is_an_error() {
+        let interpreter = RatexInterpreter::new();
+
+        let result = interpreter.borrow_mut().evaluate_expr("missing + 1");
+
+        assert!(matches!(
+            result,
+            Err(RatexError {
+                source: RatexErrorType::UndefinedIdentifier(ref name, _),
+                ..
+            }) if name == "missing"
+        ));
     }
 }
@@ -1,92 +1,447 @@
 use std::{
     borrow::Borrow,
+    cell::RefCell,
     env,
     io::{self, Write},
     rc::Rc,
+    time::{Duration, Instant},
 };
 
 mod ast;
+mod ast_json;
+mod ast_printer;
 mod class;
+mod compiler;
 mod environment;
 mod error;
+mod formatter;
 mod functions;
 mod interpreter;
+mod optimizer;
 mod parser;
 mod resolver;
 mod scanner;
+mod tasks;
 mod token;
+mod typechecker;
+mod vm;
 
-use ast::Stmt;
-use interpreter::RatexInterpreter;
+use ast::{Expr, Object, Stmt};
+use interpreter::{FixedClock, RatexInterpreter, Watcher};
+use optimizer::Optimizer;
 use parser::Parser;
 use resolver::Resolver;
 use scanner::Scanner;
+use token::{RatexToken, RatexTokenType as RXTT};
+use typechecker::TypeChecker;
 
-use crate::error::RatexError;
+use crate::error::{RatexError, RatexErrorType};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() > 2 {
-        println!("Usage: ratex [script]");
-    } else if args.len() == 2 {
-        run_file(
-            env::current_dir()
-                .unwrap()
-                .into_os_string()
-                .into_string()
-                .unwrap()
-                .to_owned()
-                + "/"
-                + &args[1].clone(),
-        );
+    match run_command(&args) {
+        Ok(()) => {}
+        Err(e) => println!("Error: {e}"),
+    }
+}
+
+fn run_command(args: &[String]) -> Result<(), RatexError> {
+    if args.len() == 3 && args[1] == "ast" {
+        print_ast(script_path(&args[2]), false)?;
+    } else if args.len() == 4 && args[1] == "ast" && args[2] == "--optimize" {
+        print_ast(script_path(&args[3]), true)?;
+    } else if args.len() == 3 && args[1] == "--tokens" {
+        print_tokens(script_path(&args[2]))?;
+    } else if args.len() == 3 && args[1] == "--dump-resolved" {
+        dump_resolved(script_path(&args[2]))?;
+    } else if args.len() == 3 && args[1] == "--ast-json" {
+        print_ast_json(script_path(&args[2]))?;
+    } else if args.len() == 3 && args[1] == "fmt" {
+        format_file(script_path(&args[2]))?;
+    } else if args.len() > 1 && OPTIONS.contains(&args[1].as_str()) {
+        run_with_options(&args[1..])?;
+    } else if args.len() == 3 && args[1] == "--compiled" {
+        let file = read_file(&script_path(&args[2]))?;
+        run_compiled(file, RatexInterpreter::new());
+        println!("Done!")
+    } else if args.len() == 3 && args[1] == "--strict" {
+        let file = read_file(&script_path(&args[2]))?;
+        run(file, RatexInterpreter::new(), true, false, false);
+        println!("Done!")
+    } else if args.len() == 3 && args[1] == "--typecheck" {
+        let file = read_file(&script_path(&args[2]))?;
+        run(file, RatexInterpreter::new(), false, true, false);
+        println!("Done!")
+    } else if args.len() == 3 && args[1] == "--optimize" {
+        let file = read_file(&script_path(&args[2]))?;
+        run(file, RatexInterpreter::new(), false, false, true);
         println!("Done!")
+    } else if args.len() == 3 && args[1] == "bench" {
+        bench_file(script_path(&args[2]))?;
+    } else if args.len() == 2 || (args.len() > 2 && !is_mode(&args[1])) {
+        // anything after the script is handed to it in `args`
+        let interpreter = RatexInterpreter::new();
+        define_args(&interpreter, &args[2..]);
+        run_file(script_path(&args[1]), interpreter)?;
+        println!("Done!")
+    } else if args.len() > 2 {
+        print_usage();
     } else {
-        let result = run_prompt();
-        match result {
-            Ok(()) => {
-                println!("Done!")
+        run_prompt()?;
+        println!("Done!")
+    }
+
+    Ok(())
+}
+
+fn print_usage() {
+    println!(
+        "Usage: ratex [ast [--optimize]|fmt|bench|--tokens|--dump-resolved|--ast-json|--compiled|--strict|--typecheck|--optimize] [script] [args...]\n       ratex [--max-steps <n>] [--float-eps <eps>] [--timeout <ms>] [--seed <n>] [--clock <secs>] [--watch <name>] [--main] script [args...]"
+    );
+}
+
+// Options that set up the interpreter a script runs on. Any of them can be
+// given together before the script's name.
+const OPTIONS: [&str; 7] = [
+    "--max-steps",
+    "--float-eps",
+    "--timeout",
+    "--seed",
+    "--clock",
+    "--watch",
+    "--main",
+];
+
+fn run_with_options(args: &[String]) -> Result<(), RatexError> {
+    let interpreter = RatexInterpreter::new();
+    let mut main = false;
+    let mut rest = args;
+
+    loop {
+        match rest {
+            [option, ..] if option == "--main" => {
+                main = true;
+                rest = &rest[1..];
             }
-            Err(e) => {
-                println!("Error: {e}")
+            [option, value, ..] if OPTIONS.contains(&option.as_str()) => {
+                if let Err(message) = set_option(&mut interpreter.borrow_mut(), option, value) {
+                    println!("{}", message);
+                    return Ok(());
+                }
+                rest = &rest[2..];
             }
+            _ => break,
+        }
+    }
+
+    let [script, script_args @ ..] = rest else {
+        print_usage();
+        return Ok(());
+    };
+
+    if main {
+        interpreter
+            .borrow_mut()
+            .with_main_entry(script_args.to_vec());
+    }
+
+    define_args(&interpreter, script_args);
+    run_file(script_path(script), interpreter)?;
+    println!("Done!");
+
+    Ok(())
+}
+
+// Sets one of the OPTIONS that takes a value, or says what it expected.
+fn set_option(
+    interpreter: &mut RatexInterpreter,
+    option: &str,
+    value: &str,
+) -> Result<(), &'static str> {
+    match option {
+        "--max-steps" => {
+            let max_steps = value
+                .parse::<u64>()
+                .map_err(|_| "--max-steps expects a whole number")?;
+            interpreter.with_step_limit(max_steps);
+        }
+        "--float-eps" => {
+            let epsilon = value
+                .parse::<f64>()
+                .ok()
+                .filter(|epsilon| epsilon.is_finite() && *epsilon >= 0.0)
+                .ok_or("--float-eps expects a finite number of at least 0")?;
+            interpreter.with_float_epsilon(epsilon);
+        }
+        "--timeout" => {
+            let millis = value
+                .parse::<u64>()
+                .map_err(|_| "--timeout expects a whole number of milliseconds")?;
+            interpreter.with_timeout(Duration::from_millis(millis));
+        }
+        "--seed" => {
+            let seed = value
+                .parse::<u64>()
+                .map_err(|_| "--seed expects a whole number")?;
+            interpreter.with_seed(seed);
+        }
+        "--clock" => {
+            let seconds = value
+                .parse::<f64>()
+                .map_err(|_| "--clock expects a number of seconds")?;
+            interpreter.with_clock(Box::new(FixedClock(seconds)));
+        }
+        "--watch" => {
+            let name = value.to_owned();
+
+            interpreter.watch(
+                value,
+                Watcher::Native(Box::new(move |old, new| {
+                    println!("[watch] {}: {} -> {}", name, old, new)
+                })),
+            );
+        }
+        _ => unreachable!("{} doesn't take a value", option),
+    }
+
+    Ok(())
+}
+
+// Whether a first argument picks a mode rather than naming a script, so that
+// a mode given the wrong arguments prints the usage instead of being run.
+fn is_mode(arg: &str) -> bool {
+    arg.starts_with("--") || ["ast", "fmt", "bench"].contains(&arg)
+}
+
+// Makes the arguments after the script available to it as a list of strings.
+fn define_args(interpreter: &Rc<RefCell<RatexInterpreter>>, args: &[String]) {
+    let args = args.iter().map(|arg| Object::from(arg.as_str())).collect();
+
+    interpreter
+        .borrow_mut()
+        .define_global("args".to_owned(), Object::List(Rc::new(RefCell::new(args))));
+}
+
+fn script_path(name: &str) -> String {
+    env::current_dir()
+        .unwrap()
+        .into_os_string()
+        .into_string()
+        .unwrap()
+        .to_owned()
+        + "/"
+        + name
+}
+
+fn print_ast(path: String, optimize: bool) -> Result<(), RatexError> {
+    let file = read_file(&path)?;
+    let Some(tokens) = scan(&file) else {
+        return Ok(());
+    };
+
+    let mut parser = Parser::new(tokens);
+    let mut ast = parser.parse();
+
+    if optimize && !parser.has_error() {
+        let interpreter = RatexInterpreter::new();
+        ast = Optimizer::new(&RefCell::borrow(&interpreter)).optimize(&ast)?;
+    }
+
+    for statement in ast {
+        println!("{}", ast_printer::print_stmt(&statement));
+    }
+
+    Ok(())
+}
+
+fn print_tokens(path: String) -> Result<(), RatexError> {
+    let file = read_file(&path)?;
+
+    let Some(tokens) = scan(&file) else {
+        return Ok(());
+    };
+
+    for token in tokens {
+        println!("{:>4} {}", token.line, token);
+    }
+
+    Ok(())
+}
+
+// Lists every variable, assignment and `this` with where the resolver said it
+// lives. Each one is looked up again in the interpreter afterwards, so when
+// two identical expressions were given different places, the one that lost
+// is marked with what the resolver gave it.
+fn dump_resolved(path: String) -> Result<(), RatexError> {
+    let file = read_file(&path)?;
+    let Some(tokens) = scan(&file) else {
+        return Ok(());
+    };
+
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse();
+
+    if parser.has_error() {
+        return Ok(());
+    }
+
+    let interpreter = RatexInterpreter::new();
+    let mut interpreter = interpreter.borrow_mut();
+    let mut resolver = Resolver::new(&mut interpreter);
+
+    if let Err(e) = resolver.resolve_program(&ast) {
+        println!("Error: {}", e);
+    }
+
+    let lookups = resolver.into_lookups();
+
+    let place = |local: Option<(usize, usize)>| match local {
+        Some((depth, slot)) => format!("depth {}, slot {}", depth, slot),
+        None => "global".to_owned(),
+    };
+
+    for (expr, name, resolved) in &lookups {
+        let kind = match expr.as_ref() {
+            Expr::Assign(_) => "assign",
+            Expr::This(_) => "this",
+            _ => "variable",
+        };
+        let used = interpreter.resolved(expr);
+
+        print!(
+            "{:>4} {:<8} {:<12} {}",
+            name.line,
+            kind,
+            name.lexeme,
+            place(used)
+        );
+
+        if used != *resolved {
+            print!(" (resolved as {})", place(*resolved));
         }
+
+        println!();
+    }
+
+    Ok(())
+}
+
+fn print_ast_json(path: String) -> Result<(), RatexError> {
+    let file = read_file(&path)?;
+    let Some(tokens) = scan(&file) else {
+        return Ok(());
+    };
+
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse();
+
+    println!("{}", ast_json::to_json(&ast));
+
+    Ok(())
+}
+
+fn format_file(path: String) -> Result<(), RatexError> {
+    let file = read_file(&path)?;
+    let Some(tokens) = scan(&file) else {
+        println!("Code won't be formatted since it has errors.");
+        return Ok(());
+    };
+
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse();
+
+    if parser.has_error() {
+        println!("Code won't be formatted since it has errors.");
+    } else {
+        print!("{}", formatter::format(&ast));
     }
+
+    Ok(())
+}
+
+fn run_file(path: String, interpreter: Rc<RefCell<RatexInterpreter>>) -> Result<(), RatexError> {
+    let file = read_file(&path)?;
+    run(file, interpreter, false, false, false);
+    Ok(())
 }
 
-fn run_file(path: String) {
-    let file = std::fs::read_to_string(path).unwrap();
-    run(file)
+fn read_file(path: &str) -> Result<String, RatexError> {
+    std::fs::read_to_string(path).map_err(|e| RatexError {
+        source: RatexErrorType::Io(format!("{}: {}", path, e)),
+        backtrace: None,
+    })
 }
 
 fn run_prompt() -> Result<(), RatexError> {
     println!("Prompt mode");
     let interpreter = RatexInterpreter::new();
+    let mut lines = std::iter::from_fn(|| {
+        let mut line = String::new();
+
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    });
 
     loop {
-        let mut prompt = String::new();
         print!("> ");
         let _ = io::stdout().flush();
-        let _ = io::stdin().read_line(&mut prompt);
 
-        if prompt.len() == 2 {
+        let Some(input) = read_input(&mut lines) else {
             break;
         };
 
-        let tokens = Scanner::new(prompt.as_str()).scan_tokens();
+        if let Some(code) = input.trim_start().strip_prefix(":type") {
+            print_type(code, &interpreter);
+            continue;
+        }
+
+        // runs a file in this session, so what it defines can be used here
+        if let Some(path) = input.trim_start().strip_prefix(":load") {
+            match path.trim() {
+                "" => println!(":load expects the path of a file"),
+                path => {
+                    if let Err(e) = run_file(path.to_owned(), Rc::clone(&interpreter)) {
+                        println!("Error: {}", e);
+                    }
+                }
+            }
+            continue;
+        }
+
+        let Some(tokens) = scan(&input) else {
+            continue;
+        };
 
         let mut parser = Parser::new(tokens);
 
         let ast = parser.parse();
 
         if !parser.has_error() {
+            if !resolve(&ast, &interpreter, false) {
+                continue;
+            }
+
             for statement in ast {
                 match statement.borrow() {
                     Stmt::Expression(expr) => {
-                        match Rc::clone(&interpreter)
-                            .borrow_mut()
-                            .evaluate(Rc::clone(&expr.expr))
-                        {
+                        let mut interpreter = interpreter.borrow_mut();
+
+                        // the last value is kept in `_` so the next input can
+                        // build on it
+                        let result =
+                            interpreter
+                                .evaluate(Rc::clone(&expr.expr))
+                                .and_then(|value| {
+                                    interpreter.define_global("_".to_owned(), value.clone());
+                                    interpreter.repr(&value)
+                                });
+
+                        // anything printed while evaluating goes out before
+                        // the value is echoed
+                        match interpreter.flush().and(result) {
                             Ok(value) => println!("{}", value),
                             Err(e) => println!("Error: {}", e),
                         }
@@ -106,19 +461,107 @@ fn run_prompt() -> Result<(), RatexError> {
     Ok(())
 }
 
-fn run(code: String) {
-    let tokens = Scanner::new(code.as_str()).scan_tokens();
+// Runs the REPL's `:type <expr>` command, which evaluates the expression and
+// prints the name of its type rather than its value.
+fn print_type(code: &str, interpreter: &Rc<RefCell<RatexInterpreter>>) {
+    match interpreter.borrow_mut().evaluate_expr(code) {
+        Ok(value) => println!("{}", value.type_name()),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+// Reads lines until every bracket opened in them has been closed, so that a
+// function or class can be typed over several lines. Returns None when the
+// input runs out or the user enters a single character to quit.
+fn read_input(lines: &mut impl Iterator<Item = String>) -> Option<String> {
+    let mut input = lines.next()?;
+
+    if input.trim_end_matches(['\r', '\n']).len() == 1 {
+        return None;
+    }
+
+    // input that doesn't scan is handed back as it is, for the errors to be
+    // reported when it's scanned again
+    while let Ok(tokens) = Scanner::new(input.as_str()).scan_tokens() {
+        if open_brackets(&tokens) <= 0 {
+            break;
+        }
+
+        print!("... ");
+        let _ = io::stdout().flush();
+
+        match lines.next() {
+            Some(line) => input.push_str(&line),
+            // let the parser report whatever is left unclosed
+            None => break,
+        }
+    }
+
+    Some(input)
+}
+
+fn open_brackets(tokens: &[RatexToken]) -> i32 {
+    tokens
+        .iter()
+        .map(|token| match token.token_type {
+            RXTT::LeftParen | RXTT::LeftBrace | RXTT::LeftBracket => 1,
+            RXTT::RightParen | RXTT::RightBrace | RXTT::RightBracket => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+// Prints any scanner errors, returning the tokens only if there were none.
+fn scan(code: &str) -> Option<Vec<RatexToken>> {
+    match Scanner::new(code).scan_tokens() {
+        Ok(tokens) => Some(tokens),
+        Err(errors) => {
+            for e in errors {
+                println!("Error: {}", e);
+            }
+
+            None
+        }
+    }
+}
+
+// Under `strict`, warnings from the resolver stop the script like errors do.
+// `typecheck` runs the type checker before the script.
+fn run(
+    code: String,
+    interpreter: Rc<RefCell<RatexInterpreter>>,
+    strict: bool,
+    typecheck: bool,
+    optimize: bool,
+) {
+    let Some(tokens) = scan(&code) else {
+        println!("Code won't be executed since it has errors.");
+        return;
+    };
 
     let mut parser = Parser::new(tokens);
 
-    let ast = parser.parse();
+    let mut ast = parser.parse();
 
     if parser.has_error() {
         println!("Code won't be executed since it has errors.");
     } else {
-        let interpreter = RatexInterpreter::new();
-        let mut resolver = Resolver::new(Rc::clone(&interpreter));
-        let _ = resolver.resolve_list(&ast.clone());
+        // folding has to come before resolving, since the resolver's results
+        // are keyed by the expressions it saw
+        if optimize {
+            match Optimizer::new(&RefCell::borrow(&interpreter)).optimize(&ast) {
+                Ok(folded) => ast = folded,
+                Err(e) => {
+                    println!("Error: {}", e);
+                    return;
+                }
+            }
+        }
+
+        if !resolve(&ast, &interpreter, strict) || (typecheck && !type_check(&ast)) {
+            println!("Code won't be executed since it has errors.");
+            return;
+        }
 
         match Rc::clone(&interpreter).borrow_mut().interpret(ast) {
             Ok(()) => {}
@@ -126,3 +569,89 @@ fn run(code: String) {
         }
     }
 }
+
+// Reports the resolver's warnings and errors, and returns whether the script
+// can be run.
+fn resolve(ast: &Vec<Rc<Stmt>>, interpreter: &Rc<RefCell<RatexInterpreter>>, strict: bool) -> bool {
+    let mut interpreter = interpreter.borrow_mut();
+    let mut resolver = Resolver::new(&mut interpreter);
+    let result = resolver.resolve_program(ast);
+    let kind = if strict { "Error" } else { "Warning" };
+
+    for warning in resolver.warnings() {
+        println!("{}: {}", kind, warning);
+    }
+
+    if let Err(e) = result {
+        println!("Error: {}", e);
+        return false;
+    }
+
+    !strict || resolver.warnings().is_empty()
+}
+
+// Reports what the type checker finds, and returns whether the script can be
+// run.
+fn type_check(ast: &[Rc<Stmt>]) -> bool {
+    let mut checker = TypeChecker::new();
+
+    if let Err(e) = checker.check_program(ast) {
+        println!("Error: {}", e);
+        return false;
+    }
+
+    for error in checker.errors() {
+        println!("Error: {}", error);
+    }
+
+    checker.errors().is_empty()
+}
+
+fn run_compiled(code: String, interpreter: Rc<RefCell<RatexInterpreter>>) {
+    let Some(tokens) = scan(&code) else {
+        println!("Code won't be executed since it has errors.");
+        return;
+    };
+
+    let mut parser = Parser::new(tokens);
+
+    let ast = parser.parse();
+
+    if parser.has_error() {
+        println!("Code won't be executed since it has errors.");
+    } else {
+        if !resolve(&ast, &interpreter, false) {
+            println!("Code won't be executed since it has errors.");
+            return;
+        }
+
+        match Rc::clone(&interpreter).borrow_mut().run_compiled(&ast) {
+            Ok(()) => {}
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+}
+
+// Runs a script once on each backend and reports how long each took,
+// including scanning and parsing.
+fn bench_file(path: String) -> Result<(), RatexError> {
+    let file = read_file(&path)?;
+
+    let start = Instant::now();
+    run(file.clone(), RatexInterpreter::new(), false, false, false);
+    let walked = start.elapsed();
+
+    let start = Instant::now();
+    run(file.clone(), RatexInterpreter::new(), false, false, true);
+    let optimized = start.elapsed();
+
+    let start = Instant::now();
+    run_compiled(file, RatexInterpreter::new());
+    let compiled = start.elapsed();
+
+    println!("tree-walking: {:?}", walked);
+    println!("optimized:    {:?}", optimized);
+    println!("compiled:     {:?}", compiled);
+
+    Ok(())
+}
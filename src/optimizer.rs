@@ -0,0 +1,614 @@
+use std::{collections::HashSet, rc::Rc};
+
+use crate::{
+    ast::{
+        Assign, AssignTuple, Binary, Block, Break, Call, Chain, Class, Expr, ExprAccept,
+        ExprVisitor, Expression, For, Fun, Get, Grouping, If, Index, IndexSet, Invariant, Lambda,
+        ListLiteral, Literal, Logical, Loop, Match, Object, Print, Return, Set, Slice, SliceSet,
+        Stmt, StmtAccept, StmtVisitor, This, Throw, Try, TupleLiteral, TypeCheck, Unary, Var,
+        VarTuple, Variable, Variant, While, With, Yield,
+    },
+    error::RatexError,
+    interpreter::RatexInterpreter,
+};
+
+// Folds binary and unary operators whose operands are literals into a single
+// literal, so `2 + 3 * 4` runs as `14`. The interpreter's own operators do the
+// arithmetic, so a folded program gives the same results, and an operation
+// that fails, such as `1 / 0`, is left in place for the interpreter to report
+// when it runs.
+//
+// Inside a loop that can't run any code the optimizer can't see, arithmetic
+// over names the loop never changes is wrapped in an Invariant, so it's only
+// worked out once each time the loop runs. The rest of the tree is rebuilt as
+// it was.
+pub struct Optimizer<'a> {
+    interpreter: &'a RatexInterpreter,
+    // the innermost loop being rebuilt, if its invariants can be hoisted
+    hoisting: Option<Hoisting>,
+}
+
+struct Hoisting {
+    // every name the loop assigns or declares
+    changed: HashSet<String>,
+    // how many Invariant expressions the loop has so far
+    count: usize,
+}
+
+impl<'a> Optimizer<'a> {
+    pub fn new(interpreter: &'a RatexInterpreter) -> Self {
+        Optimizer {
+            interpreter,
+            hoisting: None,
+        }
+    }
+
+    pub fn optimize(&mut self, statements: &[Rc<Stmt>]) -> Result<Vec<Rc<Stmt>>, RatexError> {
+        statements.iter().map(|s| self.stmt(s)).collect()
+    }
+
+    fn stmt(&mut self, stmt: &Rc<Stmt>) -> Result<Rc<Stmt>, RatexError> {
+        match **stmt {
+            Stmt::Empty => Ok(Rc::clone(stmt)),
+            _ => stmt.accept(self),
+        }
+    }
+
+    fn expr(&mut self, expr: &Rc<Expr>) -> Result<Rc<Expr>, RatexError> {
+        if let Some(hoisting) = &self.hoisting {
+            if worth_hoisting(expr) && invariant(expr, &hoisting.changed) {
+                return self.hoist(expr);
+            }
+        }
+
+        match **expr {
+            Expr::Empty => Ok(Rc::clone(expr)),
+            _ => expr.accept(self),
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Rc<Expr>]) -> Result<Vec<Rc<Expr>>, RatexError> {
+        exprs.iter().map(|e| self.expr(e)).collect()
+    }
+
+    // Folds an invariant expression and gives it the loop's next cache slot,
+    // unless it folded down to a literal.
+    fn hoist(&mut self, expr: &Rc<Expr>) -> Result<Rc<Expr>, RatexError> {
+        let hoisting = self.hoisting.take();
+        let folded = self.expr(expr);
+        self.hoisting = hoisting;
+
+        let folded = folded?;
+        if let Expr::Literal(_) = *folded {
+            return Ok(folded);
+        }
+
+        let hoisting = self.hoisting.as_mut().unwrap();
+        let index = hoisting.count;
+        hoisting.count += 1;
+
+        Ok(Invariant::new(index, folded))
+    }
+
+    // Starts rebuilding a loop, returning what the loop around it was doing.
+    // The parts are everything that runs on each iteration.
+    fn enter_loop(&mut self, stmts: &[&Rc<Stmt>], exprs: &[&Rc<Expr>]) -> Option<Hoisting> {
+        let mut changed = HashSet::new();
+        let pure = stmts.iter().all(|s| changes_in_stmt(s, &mut changed))
+            && exprs.iter().all(|e| changes_in_expr(e, &mut changed));
+
+        let hoisting = pure.then_some(Hoisting { changed, count: 0 });
+        std::mem::replace(&mut self.hoisting, hoisting)
+    }
+
+    // Returns how many expressions the loop just rebuilt had hoisted.
+    fn leave_loop(&mut self, enclosing: Option<Hoisting>) -> usize {
+        let hoisting = std::mem::replace(&mut self.hoisting, enclosing);
+        hoisting.map_or(0, |h| h.count)
+    }
+}
+
+// Whether hoisting an expression saves any work, which a lone name or
+// literal doesn't.
+fn worth_hoisting(expr: &Expr) -> bool {
+    match expr {
+        Expr::Grouping(grouping) => worth_hoisting(&grouping.expr),
+        Expr::Binary(_) | Expr::Unary(_) => true,
+        _ => false,
+    }
+}
+
+// Whether an expression is arithmetic on literals and names the loop doesn't
+// change, which gives the same value on every iteration. Operators never
+// call out to other code, so nothing else can change the names either.
+fn invariant(expr: &Expr, changed: &HashSet<String>) -> bool {
+    match expr {
+        Expr::Literal(_) => true,
+        Expr::Variable(variable) => !changed.contains(&variable.name.lexeme),
+        Expr::Grouping(grouping) => invariant(&grouping.expr, changed),
+        Expr::Unary(unary) => invariant(&unary.right, changed),
+        Expr::Binary(binary) => {
+            invariant(&binary.left, changed) && invariant(&binary.right, changed)
+        }
+        _ => false,
+    }
+}
+
+// Adds the names a statement assigns or declares to `changed`, returning
+// false if it could run code the optimizer can't see: a call, a property
+// that might have a hook, a print that might call __str__, a yield that lets
+// other tasks run, or a function declared inside the loop.
+fn changes_in_stmt(stmt: &Stmt, changed: &mut HashSet<String>) -> bool {
+    match stmt {
+        Stmt::Empty => true,
+        Stmt::Block(block) => block.statements.iter().all(|s| changes_in_stmt(s, changed)),
+        Stmt::Expression(expression) => changes_in_expr(&expression.expr, changed),
+        Stmt::If(target) => {
+            changes_in_expr(&target.condition, changed)
+                && changes_in_stmt(&target.then_stmt, changed)
+                && changes_in_stmt(&target.else_stmt, changed)
+        }
+        Stmt::While(target) => {
+            changes_in_expr(&target.condition, changed)
+                && changes_in_stmt(&target.body, changed)
+                && changes_in_stmt(&target.else_stmt, changed)
+        }
+        Stmt::For(target) => {
+            changes_in_stmt(&target.initialiser, changed)
+                && changes_in_expr(&target.condition, changed)
+                && changes_in_expr(&target.increment, changed)
+                && changes_in_stmt(&target.body, changed)
+                && changes_in_stmt(&target.else_stmt, changed)
+        }
+        Stmt::Break(target) => changes_in_expr(&target.value, changed),
+        Stmt::Return(target) => changes_in_expr(&target.value, changed),
+        Stmt::Throw(target) => changes_in_expr(&target.value, changed),
+        Stmt::Try(target) => {
+            changed.insert(target.name.lexeme.clone());
+            target.body.iter().all(|s| changes_in_stmt(s, changed))
+                && target.handler.iter().all(|s| changes_in_stmt(s, changed))
+        }
+        Stmt::Var(target) => {
+            changed.insert(target.name.lexeme.clone());
+            changes_in_expr(&target.initialiser, changed)
+        }
+        Stmt::VarTuple(target) => {
+            changed.extend(target.names.iter().map(|name| name.lexeme.clone()));
+            changes_in_expr(&target.initialiser, changed)
+        }
+        Stmt::Class(_)
+        | Stmt::Variant(_)
+        | Stmt::Fun(_)
+        | Stmt::Print(_)
+        | Stmt::Yield(_)
+        | Stmt::With(_) => false,
+    }
+}
+
+fn changes_in_expr(expr: &Expr, changed: &mut HashSet<String>) -> bool {
+    let all = |exprs: &[&Rc<Expr>], changed: &mut HashSet<String>| {
+        exprs.iter().all(|e| changes_in_expr(e, changed))
+    };
+
+    match expr {
+        Expr::Empty | Expr::Literal(_) | Expr::Variable(_) | Expr::This(_) => true,
+        Expr::Binary(target) => {
+            changes_in_expr(&target.left, changed) && changes_in_expr(&target.right, changed)
+        }
+        Expr::Logical(target) => {
+            changes_in_expr(&target.left, changed) && changes_in_expr(&target.right, changed)
+        }
+        Expr::Chain(target) => target.operands.iter().all(|e| changes_in_expr(e, changed)),
+        Expr::Unary(target) => changes_in_expr(&target.right, changed),
+        Expr::Grouping(target) => changes_in_expr(&target.expr, changed),
+        Expr::Invariant(target) => changes_in_expr(&target.expr, changed),
+        Expr::Assign(target) => {
+            changed.insert(target.name.lexeme.clone());
+            changes_in_expr(&target.value, changed)
+        }
+        Expr::AssignTuple(target) => {
+            changed.extend(target.names().into_iter().map(|name| name.lexeme));
+            changes_in_expr(&target.value, changed)
+        }
+        Expr::ListLiteral(target) => target.elements.iter().all(|e| changes_in_expr(e, changed)),
+        Expr::TupleLiteral(target) => target.elements.iter().all(|e| changes_in_expr(e, changed)),
+        Expr::Index(target) => {
+            changes_in_expr(&target.object, changed) && changes_in_expr(&target.index, changed)
+        }
+        Expr::IndexSet(target) => all(&[&target.object, &target.index, &target.value], changed),
+        Expr::Slice(target) => all(&[&target.object, &target.start, &target.end], changed),
+        Expr::SliceSet(target) => all(
+            &[&target.object, &target.start, &target.end, &target.value],
+            changed,
+        ),
+        Expr::TypeCheck(target) => {
+            changes_in_expr(&target.object, changed) && changes_in_expr(&target.class, changed)
+        }
+        Expr::Loop(target) => changes_in_stmt(&target.statement, changed),
+        Expr::Match(target) => {
+            changes_in_expr(&target.scrutinee, changed)
+                && target.arms.iter().all(|(pattern, value)| {
+                    changed.extend(Match::bindings(pattern).into_iter().map(|name| name.lexeme));
+                    changes_in_expr(value, changed)
+                })
+        }
+        Expr::Call(_) | Expr::Lambda(_) | Expr::Get(_) | Expr::Set(_) => false,
+    }
+}
+
+// The value of an expression that is a literal, looking through brackets.
+fn literal(expr: &Expr) -> Option<&Literal> {
+    match expr {
+        Expr::Literal(literal) => Some(literal),
+        Expr::Grouping(grouping) => literal(&grouping.expr),
+        _ => None,
+    }
+}
+
+impl ExprVisitor<Rc<Expr>> for Optimizer<'_> {
+    fn visit_binary(&mut self, target: Rc<Binary>) -> Result<Rc<Expr>, RatexError> {
+        let left = self.expr(&target.left)?;
+        let right = self.expr(&target.right)?;
+
+        if let (Some(l), Some(r)) = (literal(&left), literal(&right)) {
+            let folded =
+                self.interpreter
+                    .binary_op(&target.operator, l.value.clone(), r.value.clone());
+
+            if let Ok(value) = folded {
+                return Ok(Literal::new(value));
+            }
+        }
+
+        Ok(Binary::new(left, target.operator.clone(), right))
+    }
+
+    // A chain of literals folds to the value it would give, as long as none of
+    // its comparisons fail.
+    fn visit_chain(&mut self, target: Rc<Chain>) -> Result<Rc<Expr>, RatexError> {
+        let operands = target
+            .operands
+            .iter()
+            .map(|operand| self.expr(operand))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let values: Option<Vec<Object>> = operands
+            .iter()
+            .map(|operand| literal(operand).map(|l| l.value.clone()))
+            .collect();
+
+        if let Some(values) = values {
+            let mut folded = Ok(Object::Nil);
+
+            for (operator, pair) in target.operators.iter().zip(values.windows(2)) {
+                folded = self
+                    .interpreter
+                    .binary_op(operator, pair[0].clone(), pair[1].clone());
+
+                if !matches!(&folded, Ok(value) if value.is_truthy()) {
+                    break;
+                }
+            }
+
+            if let Ok(value) = folded {
+                return Ok(Literal::new(value));
+            }
+        }
+
+        Ok(Chain::new(operands, target.operators.clone()))
+    }
+
+    fn visit_unary(&mut self, target: Rc<Unary>) -> Result<Rc<Expr>, RatexError> {
+        let right = self.expr(&target.right)?;
+
+        if let Some(r) = literal(&right) {
+            if let Ok(value) = RatexInterpreter::unary_op(&target.operator, r.value.clone()) {
+                return Ok(Literal::new(value));
+            }
+        }
+
+        Ok(Unary::new(target.operator.clone(), right))
+    }
+
+    fn visit_logical(&mut self, target: Rc<Logical>) -> Result<Rc<Expr>, RatexError> {
+        Ok(Logical::new(
+            self.expr(&target.left)?,
+            target.operator.clone(),
+            self.expr(&target.right)?,
+        ))
+    }
+
+    fn visit_literal(&mut self, target: Rc<Literal>) -> Result<Rc<Expr>, RatexError> {
+        Ok(Rc::new(Expr::Literal(target)))
+    }
+
+    // A bracketed literal is just the literal, which lets the operator around
+    // it fold too.
+    fn visit_grouping(&mut self, target: Rc<Grouping>) -> Result<Rc<Expr>, RatexError> {
+        let expr = self.expr(&target.expr)?;
+
+        match *expr {
+            Expr::Literal(_) => Ok(expr),
+            _ => Ok(Grouping::new(expr)),
+        }
+    }
+
+    fn visit_variable(&mut self, target: Rc<Variable>) -> Result<Rc<Expr>, RatexError> {
+        Ok(Rc::new(Expr::Variable(target)))
+    }
+
+    fn visit_assign(&mut self, target: Rc<Assign>) -> Result<Rc<Expr>, RatexError> {
+        Ok(Assign::new(target.name.clone(), self.expr(&target.value)?))
+    }
+
+    fn visit_assign_tuple(&mut self, target: Rc<AssignTuple>) -> Result<Rc<Expr>, RatexError> {
+        Ok(AssignTuple::new(
+            target.equals.clone(),
+            target.targets.clone(),
+            self.expr(&target.value)?,
+        ))
+    }
+
+    fn visit_tuple_literal(&mut self, target: Rc<TupleLiteral>) -> Result<Rc<Expr>, RatexError> {
+        Ok(TupleLiteral::new(self.exprs(&target.elements)?))
+    }
+
+    fn visit_call(&mut self, target: Rc<Call>) -> Result<Rc<Expr>, RatexError> {
+        Ok(Call::new(
+            self.expr(&target.callee)?,
+            target.paren.clone(),
+            self.exprs(&target.arguments)?,
+        ))
+    }
+
+    fn visit_lambda(&mut self, target: Rc<Lambda>) -> Result<Rc<Expr>, RatexError> {
+        Ok(Lambda::new(
+            target.name.clone(),
+            target.params.clone(),
+            target.param_types.clone(),
+            self.exprs(&target.defaults)?,
+            target.variadic,
+            self.optimize(&target.body)?,
+        ))
+    }
+
+    fn visit_type_check(&mut self, target: Rc<TypeCheck>) -> Result<Rc<Expr>, RatexError> {
+        Ok(TypeCheck::new(
+            self.expr(&target.object)?,
+            target.keyword.clone(),
+            self.expr(&target.class)?,
+        ))
+    }
+
+    fn visit_loop(&mut self, target: Rc<Loop>) -> Result<Rc<Expr>, RatexError> {
+        Ok(Loop::new(self.stmt(&target.statement)?))
+    }
+
+    fn visit_invariant(&mut self, target: Rc<Invariant>) -> Result<Rc<Expr>, RatexError> {
+        Ok(Invariant::new(target.index, self.expr(&target.expr)?))
+    }
+
+    fn visit_match(&mut self, target: Rc<Match>) -> Result<Rc<Expr>, RatexError> {
+        let mut arms = Vec::new();
+
+        // patterns aren't expressions to be worked out, so they're kept as
+        // they are
+        for (pattern, value) in &target.arms {
+            arms.push((Rc::clone(pattern), self.expr(value)?));
+        }
+
+        Ok(Match::new(
+            target.keyword.clone(),
+            self.expr(&target.scrutinee)?,
+            arms,
+        ))
+    }
+
+    fn visit_get(&mut self, target: Rc<Get>) -> Result<Rc<Expr>, RatexError> {
+        Ok(Get::new(self.expr(&target.object)?, target.name.clone()))
+    }
+
+    fn visit_set(&mut self, target: Rc<Set>) -> Result<Rc<Expr>, RatexError> {
+        Ok(Set::new(
+            self.expr(&target.object)?,
+            target.name.clone(),
+            self.expr(&target.value)?,
+        ))
+    }
+
+    fn visit_this(&mut self, target: Rc<This>) -> Result<Rc<Expr>, RatexError> {
+        Ok(Rc::new(Expr::This(target)))
+    }
+
+    fn visit_list_literal(&mut self, target: Rc<ListLiteral>) -> Result<Rc<Expr>, RatexError> {
+        Ok(ListLiteral::new(self.exprs(&target.elements)?))
+    }
+
+    fn visit_index(&mut self, target: Rc<Index>) -> Result<Rc<Expr>, RatexError> {
+        Ok(Index::new(
+            self.expr(&target.object)?,
+            target.bracket.clone(),
+            self.expr(&target.index)?,
+        ))
+    }
+
+    fn visit_index_set(&mut self, target: Rc<IndexSet>) -> Result<Rc<Expr>, RatexError> {
+        Ok(IndexSet::new(
+            self.expr(&target.object)?,
+            target.bracket.clone(),
+            self.expr(&target.index)?,
+            self.expr(&target.value)?,
+        ))
+    }
+
+    fn visit_slice(&mut self, target: Rc<Slice>) -> Result<Rc<Expr>, RatexError> {
+        Ok(Slice::new(
+            self.expr(&target.object)?,
+            target.bracket.clone(),
+            self.expr(&target.start)?,
+            self.expr(&target.end)?,
+        ))
+    }
+
+    fn visit_slice_set(&mut self, target: Rc<SliceSet>) -> Result<Rc<Expr>, RatexError> {
+        Ok(SliceSet::new(
+            self.expr(&target.object)?,
+            target.bracket.clone(),
+            self.expr(&target.start)?,
+            self.expr(&target.end)?,
+            self.expr(&target.value)?,
+        ))
+    }
+}
+
+impl StmtVisitor<Rc<Stmt>> for Optimizer<'_> {
+    fn visit_block(&mut self, target: Rc<Block>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(Block::new(self.optimize(&target.statements)?))
+    }
+
+    fn visit_class(&mut self, target: Rc<Class>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(Class::new(
+            target.name.clone(),
+            target.fields.clone(),
+            self.optimize(&target.methods)?,
+        ))
+    }
+
+    fn visit_variant(&mut self, target: Rc<Variant>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(Variant::new(
+            target.name.clone(),
+            self.optimize(&target.constructors)?,
+        ))
+    }
+
+    fn visit_expression(&mut self, target: Rc<Expression>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(Expression::new(self.expr(&target.expr)?))
+    }
+
+    fn visit_if(&mut self, target: Rc<If>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(If::new(
+            self.expr(&target.condition)?,
+            self.stmt(&target.then_stmt)?,
+            self.stmt(&target.else_stmt)?,
+        ))
+    }
+
+    fn visit_fun(&mut self, target: Rc<Fun>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(Fun::new(
+            target.name.clone(),
+            target.params.clone(),
+            target.param_types.clone(),
+            self.exprs(&target.defaults)?,
+            target.variadic,
+            target.return_type.clone(),
+            self.optimize(&target.body)?,
+        ))
+    }
+
+    fn visit_while(&mut self, target: Rc<While>) -> Result<Rc<Stmt>, RatexError> {
+        let enclosing = self.enter_loop(&[&target.body], &[&target.condition]);
+        let condition = self.expr(&target.condition)?;
+        let body = self.stmt(&target.body)?;
+        let hoisted = self.leave_loop(enclosing);
+        let else_stmt = self.stmt(&target.else_stmt)?;
+
+        Ok(While::new(
+            target.label.clone(),
+            condition,
+            body,
+            else_stmt,
+            hoisted,
+        ))
+    }
+
+    // The initialiser only runs once, but the names it declares still change
+    // from one iteration to the next. Like the initialiser, the else branch
+    // is outside the loop's hoisting, since it runs after the loop's cache is
+    // gone.
+    fn visit_for(&mut self, target: Rc<For>) -> Result<Rc<Stmt>, RatexError> {
+        let initialiser = self.stmt(&target.initialiser)?;
+
+        let enclosing = self.enter_loop(
+            &[&target.initialiser, &target.body],
+            &[&target.condition, &target.increment],
+        );
+        let condition = self.expr(&target.condition)?;
+        let increment = self.expr(&target.increment)?;
+        let body = self.stmt(&target.body)?;
+        let hoisted = self.leave_loop(enclosing);
+        let else_stmt = self.stmt(&target.else_stmt)?;
+
+        Ok(For::new(
+            target.label.clone(),
+            initialiser,
+            condition,
+            increment,
+            body,
+            else_stmt,
+            hoisted,
+        ))
+    }
+
+    fn visit_break(&mut self, target: Rc<Break>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(Break::new(
+            target.keyword.clone(),
+            target.label.clone(),
+            self.expr(&target.value)?,
+        ))
+    }
+
+    fn visit_yield(&mut self, target: Rc<Yield>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(Rc::new(Stmt::Yield(target)))
+    }
+
+    fn visit_try(&mut self, target: Rc<Try>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(Try::new(
+            self.optimize(&target.body)?,
+            target.name.clone(),
+            self.optimize(&target.handler)?,
+        ))
+    }
+
+    fn visit_with(&mut self, target: Rc<With>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(With::new(
+            target.name.clone(),
+            self.expr(&target.resource)?,
+            self.optimize(&target.body)?,
+        ))
+    }
+
+    fn visit_throw(&mut self, target: Rc<Throw>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(Throw::new(
+            target.keyword.clone(),
+            self.expr(&target.value)?,
+        ))
+    }
+
+    fn visit_print(&mut self, target: Rc<Print>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(Print::new(self.expr(&target.expr)?))
+    }
+
+    fn visit_return(&mut self, target: Rc<Return>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(Return::new(
+            target.keyword.clone(),
+            self.expr(&target.value)?,
+        ))
+    }
+
+    fn visit_var(&mut self, target: Rc<Var>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(Var::new(
+            target.name.clone(),
+            self.expr(&target.initialiser)?,
+            target.constant,
+        ))
+    }
+
+    fn visit_var_tuple(&mut self, target: Rc<VarTuple>) -> Result<Rc<Stmt>, RatexError> {
+        Ok(VarTuple::new(
+            target.paren.clone(),
+            target.names.clone(),
+            self.expr(&target.initialiser)?,
+            target.constant,
+        ))
+    }
+}
@@ -2,14 +2,28 @@ use std::{borrow::Borrow, rc::Rc};
 
 use crate::{
     ast::{
-        Assign, Binary, Block, Break, Call, Class, Expr, Expression, Fun, Get, Grouping, If,
-        Lambda, Literal, Logical, Object, Print, Return, Set, Stmt, This, Unary, Var, Variable,
-        While,
+        Assign, AssignTuple, Binary, Block, Break, Call, Chain, Class, Expr, Expression, For, Fun,
+        Get, Grouping, If, Index, IndexSet, Lambda, ListLiteral, Literal, Logical, Loop, Match,
+        Object, Print, Return, Set, Slice, SliceSet, Stmt, This, Throw, Try, TupleLiteral,
+        TypeCheck, Unary, Var, VarTuple, Variable, Variant, While, With, Yield,
     },
     error::{RatexError, RatexErrorType},
     token::{RatexToken as RXT, RatexTokenType as RXTT},
 };
 
+// Calls and functions are capped so that argument counts fit in a byte.
+const MAX_ARGUMENTS: usize = 255;
+
+// A parameter list. Each parameter has a type and a default, which are None
+// and Expr::Empty for parameters that don't have one, and when the list is
+// variadic the last parameter is the rest parameter.
+struct Parameters {
+    params: Vec<RXT>,
+    types: Vec<Option<RXT>>,
+    defaults: Vec<Rc<Expr>>,
+    variadic: bool,
+}
+
 pub struct Parser {
     tokens: Vec<RXT>,
     current: usize,
@@ -48,6 +62,26 @@ impl Parser {
         statements
     }
 
+    // Parses input that should hold one expression and nothing else, like the
+    // REPL's :type command takes. A trailing semicolon is allowed. Unlike
+    // parse, the error is handed back rather than printed.
+    pub fn parse_expression(&mut self) -> Result<Rc<Expr>, RatexError> {
+        let expr = self.expression()?;
+        self.match_token(vec![RXTT::Semicolon]);
+
+        if self.is_at_end() {
+            return Ok(expr);
+        }
+
+        Err(RatexError {
+            source: RatexErrorType::UnexpectedToken(
+                self.peek().line,
+                self.peek().lexeme.to_string(),
+            ),
+            backtrace: None,
+        })
+    }
+
     fn expression(&mut self) -> Result<Rc<Expr>, RatexError> {
         self.assignment()
     }
@@ -64,21 +98,49 @@ impl Parser {
         Ok(expr)
     }
 
+    // Comparisons chain the way they read in maths: `a < b <= c` means
+    // `a < b and b <= c`, except that b is only evaluated once. A single
+    // comparison is a plain Binary, and a longer run of them is a Chain.
     fn comparison(&mut self) -> Result<Rc<Expr>, RatexError> {
         let mut expr = self.term()?;
 
-        while self.match_token(vec![
+        loop {
+            if self.check_comparison() {
+                let mut operands = vec![expr];
+                let mut operators = Vec::new();
+
+                while self.check_comparison() {
+                    operators.push(self.advance().clone());
+                    operands.push(self.term()?);
+                }
+
+                expr = if operators.len() == 1 {
+                    let right = operands.pop().unwrap();
+                    Binary::new(operands.pop().unwrap(), operators.pop().unwrap(), right)
+                } else {
+                    Chain::new(operands, operators)
+                };
+            } else if self.match_token(vec![RXTT::Is]) {
+                let keyword = self.previous().clone();
+                let class = self.term()?;
+                expr = TypeCheck::new(Rc::clone(&expr), keyword, class);
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn check_comparison(&self) -> bool {
+        [
             RXTT::Greater,
             RXTT::GreaterEqual,
             RXTT::Less,
             RXTT::LessEqual,
-        ]) {
-            let operator = self.previous().clone();
-            let right = self.term()?;
-            expr = Binary::new(Rc::clone(&expr), operator, Rc::clone(&right));
-        }
-
-        Ok(expr)
+        ]
+        .iter()
+        .any(|token_type| self.check(token_type))
     }
 
     fn term(&mut self) -> Result<Rc<Expr>, RatexError> {
@@ -124,6 +186,8 @@ impl Parser {
             } else if self.match_token(vec![RXTT::Dot]) {
                 let name = self.consume(RXTT::Identifier)?;
                 expr = Get::new(Rc::clone(&expr), name.clone())
+            } else if self.match_token(vec![RXTT::LeftBracket]) {
+                expr = self.finish_index(&expr)?;
             } else {
                 break;
             }
@@ -148,7 +212,7 @@ impl Parser {
             }
             RXTT::Number(n) => {
                 self.current += 1;
-                Ok(Literal::new(Object::Number(n.clone())))
+                Ok(Literal::new(Object::Number(*n)))
             }
             RXTT::String(s) => {
                 self.current += 1;
@@ -158,6 +222,24 @@ impl Parser {
                 self.current += 1;
                 let expr = self.expression()?;
 
+                // a comma after the first expression makes a tuple rather
+                // than a grouping, so `(a,)` is a tuple of one
+                if self.match_token(vec![RXTT::Comma]) {
+                    let mut elements = vec![expr];
+
+                    while !self.check(&RXTT::RightParen) {
+                        elements.push(self.expression()?);
+
+                        if !self.match_token(vec![RXTT::Comma]) {
+                            break;
+                        }
+                    }
+
+                    self.consume(RXTT::RightParen)?;
+
+                    return Ok(TupleLiteral::new(elements));
+                }
+
                 self.consume(RXTT::RightParen)?;
 
                 Ok(Grouping::new(Rc::clone(&expr)))
@@ -174,11 +256,113 @@ impl Parser {
                 self.current += 1;
                 self.anonymous_function()
             }
+            RXTT::While => {
+                self.current += 1;
+                Ok(Loop::new(self.while_statement(None)?))
+            }
+            RXTT::For => {
+                self.current += 1;
+                Ok(Loop::new(self.for_statement(None)?))
+            }
+            RXTT::Label if self.check_next(&RXTT::Colon) => {
+                Ok(Loop::new(self.labelled_statement()?))
+            }
+            RXTT::Match => {
+                self.current += 1;
+                self.match_expression()
+            }
+            RXTT::LeftBracket => {
+                self.current += 1;
+                let mut elements = Vec::new();
+
+                if !self.check(&RXTT::RightBracket) {
+                    elements.push(self.expression()?);
+
+                    while self.match_token(vec![RXTT::Comma]) {
+                        if self.check(&RXTT::RightBracket) {
+                            break;
+                        }
+
+                        elements.push(self.expression()?);
+                    }
+                }
+
+                self.consume(RXTT::RightBracket)?;
+
+                Ok(ListLiteral::new(elements))
+            }
             _ => Err(RatexError {
                 source: RatexErrorType::UnexpectedToken(
                     self.peek().line,
-                    format!("{}", self.peek().lexeme),
+                    self.peek().lexeme.to_string(),
                 ),
+                backtrace: None,
+            }),
+        }
+    }
+
+    fn match_expression(&mut self) -> Result<Rc<Expr>, RatexError> {
+        let keyword = self.previous().clone();
+        let scrutinee = self.expression()?;
+        self.consume(RXTT::LeftBrace)?;
+
+        let mut arms = Vec::new();
+
+        while !self.check(&RXTT::RightBrace) && !self.is_at_end() {
+            let pattern = self.pattern()?;
+            self.consume(RXTT::EqualGreater)?;
+            let value = self.expression()?;
+            arms.push((pattern, value));
+
+            if !self.match_token(vec![RXTT::Comma]) {
+                break;
+            }
+        }
+
+        self.consume(RXTT::RightBrace)?;
+
+        Ok(Match::new(keyword, scrutinee, arms))
+    }
+
+    // Patterns are literals, including negative numbers, `_` to match
+    // anything, a name to bind the value to, or `Name(p, ...)` to match an
+    // instance of the class Name whose fields match the inner patterns.
+    fn pattern(&mut self) -> Result<Rc<Expr>, RatexError> {
+        let token = self.advance().clone();
+
+        match token.token_type {
+            RXTT::Identifier if token.lexeme == "_" => Ok(Rc::new(Expr::Empty)),
+            RXTT::Identifier if self.match_token(vec![RXTT::LeftParen]) => {
+                let paren = self.previous().clone();
+                let mut fields = Vec::new();
+
+                while !self.check(&RXTT::RightParen) {
+                    fields.push(self.pattern()?);
+
+                    if !self.match_token(vec![RXTT::Comma]) {
+                        break;
+                    }
+                }
+
+                self.consume(RXTT::RightParen)?;
+
+                Ok(Call::new(Variable::new(token), paren, fields))
+            }
+            RXTT::Identifier => Ok(Variable::new(token)),
+            RXTT::Number(n) => Ok(Literal::new(Object::Number(n))),
+            RXTT::Minus if matches!(self.peek().token_type, RXTT::Number(_)) => {
+                match self.advance().token_type {
+                    RXTT::Number(n) => Ok(Literal::new(Object::Number(-n))),
+                    _ => unreachable!(),
+                }
+            }
+            RXTT::String(s) => Ok(Literal::new(Object::String(s))),
+            RXTT::True => Ok(Literal::new(Object::Bool(true))),
+            RXTT::False => Ok(Literal::new(Object::Bool(false))),
+            RXTT::Nil => Ok(Literal::new(Object::Nil)),
+            _ => Err(RatexError {
+                source: RatexErrorType::UnexpectedToken(token.line, token.lexeme),
+                backtrace: None,
             }),
         }
     }
@@ -212,6 +396,12 @@ impl Parser {
         self.previous()
     }
 
+    fn check_next(&self, token_type: &RXTT) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|token| token.token_type == *token_type)
+    }
+
     fn peek(&self) -> &RXT {
         self.tokens.get(self.current).unwrap()
     }
@@ -223,6 +413,7 @@ impl Parser {
 
         Err(RatexError {
             source: RatexErrorType::ExpectedToken(self.previous().line, ";".to_owned()),
+            backtrace: None,
         })
     }
 
@@ -231,10 +422,18 @@ impl Parser {
     }
 
     fn statement(&mut self) -> Result<Rc<Stmt>, RatexError> {
+        if self.check(&RXTT::Label) && self.check_next(&RXTT::Colon) {
+            return self.labelled_statement();
+        }
+
         if self.match_token(vec![RXTT::Class]) {
             return self.class_declaration();
         }
 
+        if self.match_token(vec![RXTT::Variant]) {
+            return self.variant_declaration();
+        }
+
         if self.match_token(vec![RXTT::Return]) {
             return self.return_statement();
         }
@@ -244,11 +443,11 @@ impl Parser {
         }
 
         if self.match_token(vec![RXTT::For]) {
-            return self.for_statement();
+            return self.for_statement(None);
         }
 
         if self.match_token(vec![RXTT::While]) {
-            return self.while_statement();
+            return self.while_statement(None);
         }
 
         if self.match_token(vec![RXTT::If]) {
@@ -259,6 +458,26 @@ impl Parser {
             return self.print_statement();
         }
 
+        if self.match_token(vec![RXTT::Break]) {
+            return self.break_statement();
+        }
+
+        if self.match_token(vec![RXTT::Yield]) {
+            return self.yield_statement();
+        }
+
+        if self.match_token(vec![RXTT::Try]) {
+            return self.try_statement();
+        }
+
+        if self.match_token(vec![RXTT::Throw]) {
+            return self.throw_statement();
+        }
+
+        if self.match_token(vec![RXTT::With]) {
+            return self.with_statement();
+        }
+
         if self.match_token(vec![RXTT::LeftBrace]) {
             return Ok(Block::new(self.block()?));
         }
@@ -282,31 +501,90 @@ impl Parser {
         Ok(Expression::new(value))
     }
 
+    fn yield_statement(&mut self) -> Result<Rc<Stmt>, RatexError> {
+        let keyword = self.previous().clone();
+        self.consume(RXTT::Semicolon)?;
+
+        Ok(Yield::new(keyword))
+    }
+
+    fn try_statement(&mut self) -> Result<Rc<Stmt>, RatexError> {
+        self.consume(RXTT::LeftBrace)?;
+        let body = self.block()?;
+
+        self.consume(RXTT::Catch)?;
+        self.consume(RXTT::LeftParen)?;
+        let name = self.consume(RXTT::Identifier)?.clone();
+        self.consume(RXTT::RightParen)?;
+
+        self.consume(RXTT::LeftBrace)?;
+        let handler = self.block()?;
+
+        Ok(Try::new(body, name, handler))
+    }
+
+    fn with_statement(&mut self) -> Result<Rc<Stmt>, RatexError> {
+        self.consume(RXTT::LeftParen)?;
+        let name = self.consume(RXTT::Identifier)?.clone();
+        self.consume(RXTT::Equal)?;
+        let resource = self.expression()?;
+        self.consume(RXTT::RightParen)?;
+
+        self.consume(RXTT::LeftBrace)?;
+        let body = self.block()?;
+
+        Ok(With::new(name, resource, body))
+    }
+
+    fn throw_statement(&mut self) -> Result<Rc<Stmt>, RatexError> {
+        let keyword = self.previous().clone();
+        let value = self.expression()?;
+        self.consume(RXTT::Semicolon)?;
+
+        Ok(Throw::new(keyword, value))
+    }
+
     fn break_statement(&mut self) -> Result<Rc<Stmt>, RatexError> {
-        if self.match_token(vec![RXTT::Break]) {
-            self.consume(RXTT::Semicolon)?;
-            return Ok(Break::new());
+        let keyword = self.previous().clone();
+        let mut label = None;
+        let mut value = Rc::new(Expr::Empty);
+
+        // a label is quoted, so `break 'outer;` leaves the loop labelled
+        // 'outer and `break outer;` breaks with the value of outer. The
+        // resolver checks that some loop around the break has the label.
+        if self.match_token(vec![RXTT::Label]) {
+            label = Some(self.previous().clone());
+        }
+
+        if !self.check(&RXTT::Semicolon) {
+            value = self.expression()?;
         }
 
-        self.declaration()
+        self.consume(RXTT::Semicolon)?;
+
+        Ok(Break::new(keyword, label, value))
     }
 
     fn declaration(&mut self) -> Result<Rc<Stmt>, RatexError> {
         if self.match_token(vec![RXTT::Var]) {
-            Ok(self.var_declaration()?)
+            Ok(self.var_declaration(false)?)
+        } else if self.match_token(vec![RXTT::Const]) {
+            Ok(self.var_declaration(true)?)
         } else {
             Ok(self.statement()?)
         }
     }
 
-    fn var_declaration(&mut self) -> Result<Rc<Stmt>, RatexError> {
+    // A constant is a variable that must be given a value and can't be
+    // assigned to afterwards.
+    fn var_declaration(&mut self, constant: bool) -> Result<Rc<Stmt>, RatexError> {
+        if self.match_token(vec![RXTT::LeftParen]) {
+            return self.var_tuple_declaration(constant);
+        }
+
         let token = &self.peek();
         let name = match token.token_type {
-            RXTT::Identifier => RXT {
-                token_type: RXTT::Identifier,
-                lexeme: token.lexeme.clone(),
-                line: 0,
-            },
+            RXTT::Identifier => (*token).clone(),
             _ => {
                 panic!("Expected variable name.")
             }
@@ -318,31 +596,61 @@ impl Parser {
 
         if self.match_token(vec![RXTT::Equal]) {
             initialiser = self.expression()?;
+        } else if constant {
+            return Err(RatexError {
+                source: RatexErrorType::ExpectedToken(self.peek().line, "=".to_owned()),
+                backtrace: None,
+            });
         }
 
         self.consume(RXTT::Semicolon)?;
 
-        Ok(Var::new(name, Rc::clone(&initialiser)))
+        Ok(Var::new(name, Rc::clone(&initialiser), constant))
+    }
+
+    // `var (a, b) = value;`, which always needs the value to unpack
+    fn var_tuple_declaration(&mut self, constant: bool) -> Result<Rc<Stmt>, RatexError> {
+        let paren = self.previous().clone();
+        let mut names = vec![self.consume(RXTT::Identifier)?.clone()];
+
+        while self.match_token(vec![RXTT::Comma]) {
+            if self.check(&RXTT::RightParen) {
+                break;
+            }
+
+            names.push(self.consume(RXTT::Identifier)?.clone());
+        }
+
+        self.consume(RXTT::RightParen)?;
+        self.consume(RXTT::Equal)?;
+        let initialiser = self.expression()?;
+        self.consume(RXTT::Semicolon)?;
+
+        Ok(VarTuple::new(paren, names, initialiser, constant))
     }
 
     fn synchronise(&mut self) {
         self.advance();
 
         while !self.is_at_end() {
-            match self.previous().token_type {
-                RXTT::Semicolon => return (),
-                _ => {}
+            if self.previous().token_type == RXTT::Semicolon {
+                return;
             }
 
             match self.peek().token_type {
                 RXTT::Class
+                | RXTT::Variant
                 | RXTT::Fun
                 | RXTT::Var
+                | RXTT::Const
                 | RXTT::For
                 | RXTT::If
                 | RXTT::While
                 | RXTT::Print
-                | RXTT::Return => return (),
+                | RXTT::Try
+                | RXTT::With
+                | RXTT::Throw
+                | RXTT::Return => return,
                 _ => {}
             }
 
@@ -351,7 +659,7 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> Result<Rc<Expr>, RatexError> {
-        let expr = self.or()?;
+        let expr = self.pipe()?;
 
         if self.match_token(vec![RXTT::Equal]) {
             let equals = self.previous();
@@ -369,9 +677,50 @@ impl Parser {
                         Rc::clone(&self.assignment()?),
                     ))
                 }
+                Expr::Index(index) => {
+                    return Ok(IndexSet::new(
+                        Rc::clone(&index.object),
+                        index.bracket.clone(),
+                        Rc::clone(&index.index),
+                        self.assignment()?,
+                    ))
+                }
+                Expr::Slice(slice) => {
+                    return Ok(SliceSet::new(
+                        Rc::clone(&slice.object),
+                        slice.bracket.clone(),
+                        Rc::clone(&slice.start),
+                        Rc::clone(&slice.end),
+                        self.assignment()?,
+                    ))
+                }
+                Expr::TupleLiteral(tuple) => {
+                    let mut targets = Vec::new();
+
+                    for element in &tuple.elements {
+                        match element.borrow() {
+                            Expr::Variable(var) => {
+                                targets.push(Assign::new(var.name.clone(), Rc::new(Expr::Empty)))
+                            }
+                            _ => {
+                                return Err(RatexError {
+                                    source: RatexErrorType::InvalidAssignment(equals.line),
+                                    backtrace: None,
+                                })
+                            }
+                        }
+                    }
+
+                    return Ok(AssignTuple::new(
+                        equals.clone(),
+                        targets,
+                        self.assignment()?,
+                    ));
+                }
                 _ => {
                     return Err(RatexError {
                         source: RatexErrorType::InvalidAssignment(equals.line),
+                        backtrace: None,
                     });
                 }
             }
@@ -384,7 +733,7 @@ impl Parser {
         let mut statements = Vec::new();
 
         while !self.check(&RXTT::RightBrace) && !self.is_at_end() {
-            statements.push(self.break_statement()?);
+            statements.push(self.declaration()?);
         }
 
         self.consume(RXTT::RightBrace)?;
@@ -412,6 +761,28 @@ impl Parser {
         ))
     }
 
+    // `x |> f` is sugar for `f(x)`, and when the right side is already a
+    // call like `x |> f(a)` the left side becomes its first argument: `f(x, a)`
+    fn pipe(&mut self) -> Result<Rc<Expr>, RatexError> {
+        let mut expr = self.or()?;
+
+        while self.match_token(vec![RXTT::PipeGreater]) {
+            let operator = self.previous().clone();
+            let target = self.call()?;
+
+            expr = match target.as_ref() {
+                Expr::Call(call) => {
+                    let mut arguments = vec![expr];
+                    arguments.extend(call.arguments.iter().cloned());
+                    Call::new(Rc::clone(&call.callee), call.paren.clone(), arguments)
+                }
+                _ => Call::new(target, operator, vec![expr]),
+            };
+        }
+
+        Ok(expr)
+    }
+
     fn or(&mut self) -> Result<Rc<Expr>, RatexError> {
         let mut expr = self.and()?;
 
@@ -428,7 +799,7 @@ impl Parser {
     fn and(&mut self) -> Result<Rc<Expr>, RatexError> {
         let mut expr = self.equality()?;
 
-        while self.match_token(vec![RXTT::Or]) {
+        while self.match_token(vec![RXTT::And]) {
             let operator = self.previous().clone();
             let right = self.equality()?;
 
@@ -438,23 +809,56 @@ impl Parser {
         Ok(expr)
     }
 
-    fn while_statement(&mut self) -> Result<Rc<Stmt>, RatexError> {
+    fn labelled_statement(&mut self) -> Result<Rc<Stmt>, RatexError> {
+        let label = self.advance().clone();
+        self.advance();
+
+        if self.match_token(vec![RXTT::While]) {
+            self.while_statement(Some(label))
+        } else if self.match_token(vec![RXTT::For]) {
+            self.for_statement(Some(label))
+        } else {
+            Err(RatexError {
+                source: RatexErrorType::LabelWithoutLoop(label.line),
+                backtrace: None,
+            })
+        }
+    }
+
+    // A loop's else runs if the loop ends without a break. It's outside the
+    // loop, so a break in it leaves whatever loop is around.
+    fn loop_else(&mut self) -> Result<Rc<Stmt>, RatexError> {
+        if self.match_token(vec![RXTT::Else]) {
+            self.statement()
+        } else {
+            Ok(Rc::new(Stmt::Empty))
+        }
+    }
+
+    fn while_statement(&mut self, label: Option<RXT>) -> Result<Rc<Stmt>, RatexError> {
         self.consume(RXTT::LeftParen)?;
         let condition = self.expression()?;
         self.consume(RXTT::RightParen)?;
 
         let body = self.statement()?;
+        let else_stmt = self.loop_else()?;
 
-        Ok(While::new(Rc::clone(&condition), Rc::clone(&body)))
+        Ok(While::new(
+            label,
+            Rc::clone(&condition),
+            Rc::clone(&body),
+            else_stmt,
+            0,
+        ))
     }
 
-    fn for_statement(&mut self) -> Result<Rc<Stmt>, RatexError> {
+    fn for_statement(&mut self, label: Option<RXT>) -> Result<Rc<Stmt>, RatexError> {
         self.consume(RXTT::LeftParen)?;
         let mut initialiser = Rc::new(Stmt::Empty);
 
         if !self.match_token(vec![RXTT::Semicolon]) {
             if self.match_token(vec![RXTT::Var]) {
-                initialiser = self.var_declaration()?;
+                initialiser = self.var_declaration(false)?;
             } else {
                 initialiser = self.expression_statement()?;
             }
@@ -470,29 +874,24 @@ impl Parser {
 
         let mut increment = Rc::new(Expr::Empty);
 
-        if !self.match_token(vec![RXTT::RightParen]) {
+        if !self.check(&RXTT::RightParen) {
             increment = self.expression()?;
         }
 
         self.consume(RXTT::RightParen)?;
 
-        let mut body = self.statement()?;
-
-        match increment.borrow() {
-            Expr::Empty => {}
-            _ => {
-                body = Block::new(vec![body, Expression::new(Rc::clone(&increment))]);
-            }
-        }
-
-        body = While::new(Rc::clone(&condition), Rc::clone(&body));
-
-        match initialiser.borrow() {
-            Stmt::Empty => {}
-            _ => body = Block::new(vec![initialiser, body]),
-        }
-
-        Ok(body)
+        let body = self.statement()?;
+        let else_stmt = self.loop_else()?;
+
+        Ok(For::new(
+            label,
+            initialiser,
+            condition,
+            increment,
+            body,
+            else_stmt,
+            0,
+        ))
     }
 
     fn finish_call(&mut self, callee: &Rc<Expr>) -> Result<Rc<Expr>, RatexError> {
@@ -501,7 +900,19 @@ impl Parser {
         if !self.check(&RXTT::RightParen) {
             arguments.push(Rc::clone(&self.expression()?));
 
+            // a trailing comma before the closing paren is allowed
             while self.match_token(vec![RXTT::Comma]) {
+                if self.check(&RXTT::RightParen) {
+                    break;
+                }
+
+                if arguments.len() >= MAX_ARGUMENTS {
+                    return Err(RatexError {
+                        source: RatexErrorType::TooManyArguments(self.peek().line),
+                        backtrace: None,
+                    });
+                }
+
                 arguments.push(Rc::clone(&self.expression()?));
             }
         }
@@ -511,25 +922,70 @@ impl Parser {
         Ok(Call::new(Rc::clone(callee), paren.clone(), arguments))
     }
 
-    fn function_statement(&mut self) -> Result<Rc<Stmt>, RatexError> {
-        let name = self.consume(RXTT::Identifier)?.clone();
+    fn finish_index(&mut self, object: &Rc<Expr>) -> Result<Rc<Expr>, RatexError> {
+        let bracket = self.previous().clone();
+        let mut start = Rc::new(Expr::Empty);
 
-        self.consume(RXTT::LeftParen)?;
-        let mut params = Vec::new();
+        if !self.check(&RXTT::Colon) {
+            start = self.expression()?;
+        }
 
-        if !self.check(&RXTT::RightParen) {
-            params.push(self.consume(RXTT::Identifier)?.clone());
+        if !self.match_token(vec![RXTT::Colon]) {
+            self.consume(RXTT::RightBracket)?;
+            return Ok(Index::new(Rc::clone(object), bracket, start));
+        }
 
-            while self.match_token(vec![RXTT::Comma]) {
-                params.push(self.consume(RXTT::Identifier)?.clone());
-            }
+        let mut end = Rc::new(Expr::Empty);
+
+        if !self.check(&RXTT::RightBracket) {
+            end = self.expression()?;
         }
 
-        self.consume(RXTT::RightParen)?;
+        self.consume(RXTT::RightBracket)?;
+
+        Ok(Slice::new(Rc::clone(object), bracket, start, end))
+    }
+
+    fn function_statement(&mut self) -> Result<Rc<Stmt>, RatexError> {
+        let name = self.consume(RXTT::Identifier)?.clone();
+
+        let Parameters {
+            params,
+            types,
+            defaults,
+            variadic,
+        } = self.parameters()?;
+
+        let return_type = match self.match_token(vec![RXTT::Colon]) {
+            true => Some(self.type_name()?),
+            false => None,
+        };
+
         self.consume(RXTT::LeftBrace)?;
         let body = self.block()?;
 
-        Ok(Fun::new(name, params, body))
+        Ok(Fun::new(
+            name,
+            params,
+            types,
+            defaults,
+            variadic,
+            return_type,
+            body,
+        ))
+    }
+
+    // A type is named by an identifier, such as number or a class name, or
+    // by nil, which is a keyword.
+    fn type_name(&mut self) -> Result<RXT, RatexError> {
+        if self.match_token(vec![RXTT::Identifier, RXTT::Nil]) {
+            return Ok(self.previous().clone());
+        }
+
+        Err(RatexError {
+            source: RatexErrorType::ExpectedToken(self.peek().line, "type".to_owned()),
+            backtrace: None,
+        })
     }
 
     fn return_statement(&mut self) -> Result<Rc<Stmt>, RatexError> {
@@ -539,32 +995,119 @@ impl Parser {
             value = self.expression()?;
         }
 
+        // `return a, b;` gives back the tuple (a, b)
+        if self.match_token(vec![RXTT::Comma]) {
+            let mut elements = vec![value];
+
+            loop {
+                elements.push(self.expression()?);
+
+                if !self.match_token(vec![RXTT::Comma]) {
+                    break;
+                }
+            }
+
+            value = TupleLiteral::new(elements);
+        }
+
         self.consume(RXTT::Semicolon)?;
 
         Ok(Return::new(keyword, Rc::clone(&value)))
     }
 
     fn anonymous_function(&mut self) -> Result<Rc<Expr>, RatexError> {
+        let name = match self.check(&RXTT::Identifier) {
+            true => Some(self.advance().clone()),
+            false => None,
+        };
+
+        let Parameters {
+            params,
+            types,
+            defaults,
+            variadic,
+        } = self.parameters()?;
+
+        // `fun (x) => x * 2` is short for a body that returns the expression
+        if self.match_token(vec![RXTT::EqualGreater]) {
+            let arrow = self.previous().clone();
+            let value = self.expression()?;
+            let body = vec![Return::new(arrow, value)];
+
+            return Ok(Lambda::new(name, params, types, defaults, variadic, body));
+        }
+
+        self.consume(RXTT::LeftBrace)?;
+        let body = self.block()?;
+
+        Ok(Lambda::new(name, params, types, defaults, variadic, body))
+    }
+
+    fn parameters(&mut self) -> Result<Parameters, RatexError> {
         self.consume(RXTT::LeftParen)?;
         let mut params = Vec::new();
+        let mut types = Vec::new();
+        let mut defaults = Vec::new();
+        let mut variadic = false;
 
         if !self.check(&RXTT::RightParen) {
-            params.push(self.consume(RXTT::Identifier)?.clone());
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    return Err(RatexError {
+                        source: RatexErrorType::TooManyParameters(self.peek().line),
+                        backtrace: None,
+                    });
+                }
 
-            while self.match_token(vec![RXTT::Comma]) {
+                if variadic {
+                    return Err(RatexError {
+                        source: RatexErrorType::RestNotLast(self.previous().line),
+                        backtrace: None,
+                    });
+                }
+
+                variadic = self.match_token(vec![RXTT::Ellipsis]);
                 params.push(self.consume(RXTT::Identifier)?.clone());
+
+                // a rest parameter's type is the type of each extra argument
+                types.push(match self.match_token(vec![RXTT::Colon]) {
+                    true => Some(self.type_name()?),
+                    false => None,
+                });
+
+                if variadic {
+                    defaults.push(Rc::new(Expr::Empty));
+                } else if self.match_token(vec![RXTT::Equal]) {
+                    defaults.push(self.expression()?);
+                } else if defaults.iter().any(|d| **d != Expr::Empty) {
+                    return Err(RatexError {
+                        source: RatexErrorType::RequiredAfterDefault(self.previous().line),
+                        backtrace: None,
+                    });
+                } else {
+                    defaults.push(Rc::new(Expr::Empty));
+                }
+
+                if !self.match_token(vec![RXTT::Comma]) || self.check(&RXTT::RightParen) {
+                    break;
+                }
             }
         }
 
         self.consume(RXTT::RightParen)?;
-        self.consume(RXTT::LeftBrace)?;
-        let body = self.block()?;
 
-        Ok(Lambda::new(params, body))
+        Ok(Parameters {
+            params,
+            types,
+            defaults,
+            variadic,
+        })
     }
 
     fn class_declaration(&mut self) -> Result<Rc<Stmt>, RatexError> {
         let name = self.consume(RXTT::Identifier)?.clone();
+        let fields = self.field_list()?;
+
         self.consume(RXTT::LeftBrace)?;
 
         let mut methods = Vec::new();
@@ -575,6 +1118,49 @@ impl Parser {
 
         self.consume(RXTT::RightBrace)?;
 
-        Ok(Class::new(name, methods))
+        Ok(Class::new(name, fields, methods))
+    }
+
+    // `variant Shape { Circle(r), Rect(w, h) }` declares one field-only class
+    // per constructor, so `Circle(2)` builds an instance tagged as a Circle
+    fn variant_declaration(&mut self) -> Result<Rc<Stmt>, RatexError> {
+        let name = self.consume(RXTT::Identifier)?.clone();
+        let mut constructors = Vec::new();
+
+        self.consume(RXTT::LeftBrace)?;
+
+        if !self.check(&RXTT::RightBrace) {
+            loop {
+                let constructor = self.consume(RXTT::Identifier)?.clone();
+                let fields = self.field_list()?;
+                constructors.push(Class::new(constructor, fields, Vec::new()));
+
+                if !self.match_token(vec![RXTT::Comma]) || self.check(&RXTT::RightBrace) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(RXTT::RightBrace)?;
+
+        Ok(Variant::new(name, constructors))
+    }
+
+    fn field_list(&mut self) -> Result<Vec<RXT>, RatexError> {
+        let mut fields = Vec::new();
+
+        if self.match_token(vec![RXTT::LeftParen]) {
+            if !self.check(&RXTT::RightParen) {
+                fields.push(self.consume(RXTT::Identifier)?.clone());
+
+                while self.match_token(vec![RXTT::Comma]) {
+                    fields.push(self.consume(RXTT::Identifier)?.clone());
+                }
+            }
+
+            self.consume(RXTT::RightParen)?;
+        }
+
+        Ok(fields)
     }
 }
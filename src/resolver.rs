@@ -1,85 +1,229 @@
 use std::{
     borrow::Borrow,
     cell::RefCell,
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     rc::Rc,
 };
 
 use crate::{
     ast::{
-        Assign, Binary, Block, Break, Call, Class, Expr, ExprAccept, ExprVisitor, Expression, Fun,
-        Get, Grouping, If, Lambda, Literal, Logical, Print, Return, Set, Stmt, StmtAccept,
-        StmtVisitor, This, Unary, Var, Variable, While,
+        Assign, AssignTuple, Binary, Block, Break, Call, Chain, Class, Expr, ExprAccept,
+        ExprVisitor, Expression, For, Fun, Get, Grouping, If, Index, IndexSet, Invariant, Lambda,
+        ListLiteral, Literal, Logical, Loop, Match, Print, Return, Set, Slice, SliceSet, Stmt,
+        StmtAccept, StmtVisitor, This, Throw, Try, TupleLiteral, TypeCheck, Unary, Var, VarTuple,
+        Variable, Variant, While, With, Yield,
     },
     error::{RatexError, RatexErrorType},
     interpreter::RatexInterpreter,
     token::RatexToken as RXT,
 };
 
-#[derive(Debug, Clone)]
+// a name the resolver looked up, with the depth and slot it was given or None
+// for a global
+pub type Lookup = (Rc<Expr>, RXT, Option<(usize, usize)>);
+
+#[derive(Debug, Clone, Copy)]
 pub enum FunctionType {
     Function,
     None,
     Method,
 }
 
+#[derive(Debug, Default)]
+struct Binding {
+    // where the value will be in the scope's environment, which is the order
+    // the scope's names are declared in, matching the order they're defined
+    // at runtime
+    slot: usize,
+    defined: bool,
+    used: bool,
+    // the line of the `var` that declared it; other bindings, such as
+    // parameters, aren't checked for being used
+    line: Option<u32>,
+}
+
 #[derive(Debug)]
-pub struct Resolver {
-    interpreter: Rc<RefCell<RatexInterpreter>>,
-    scopes: VecDeque<RefCell<HashMap<String, bool>>>,
+pub struct Resolver<'a> {
+    interpreter: &'a mut RatexInterpreter,
+    scopes: VecDeque<RefCell<HashMap<String, Binding>>>,
     current_function: FunctionType,
+    // labels of the loops around the code being resolved, up to the nearest
+    // function, since a break can't leave a function
+    labels: Vec<String>,
+    // the first scope of the function being resolved. Names from scopes
+    // before it are only read once the function is called, so they don't
+    // have to be defined yet.
+    function_scope: usize,
+    // names declared with `const`, kept alongside `scopes`
+    constants: VecDeque<HashSet<String>>,
+    global_constants: HashSet<String>,
+    // names declared at the top level so far
+    globals: HashSet<String>,
+    // assignments inside functions to names that weren't declared yet. The
+    // function may only run after the global is declared, so these are
+    // checked once the whole program has been resolved.
+    later_assignments: Vec<RXT>,
+    // problems that don't stop the script from running, such as unused locals
+    warnings: Vec<RatexError>,
+    // every name looked up, in order. Only --dump-resolved reads it.
+    lookups: Vec<Lookup>,
 }
 
-impl Resolver {
-    pub fn new(interpreter: Rc<RefCell<RatexInterpreter>>) -> Self {
+impl<'a> Resolver<'a> {
+    pub fn new(interpreter: &'a mut RatexInterpreter) -> Self {
         Resolver {
             interpreter,
             scopes: VecDeque::new(),
             current_function: FunctionType::None,
+            labels: Vec::new(),
+            function_scope: 0,
+            constants: VecDeque::new(),
+            global_constants: HashSet::new(),
+            globals: HashSet::new(),
+            later_assignments: Vec::new(),
+            warnings: Vec::new(),
+            lookups: Vec::new(),
+        }
+    }
+
+    pub fn warnings(&self) -> &[RatexError] {
+        &self.warnings
+    }
+
+    // Hands back the lookups, which ends the resolver's hold on the
+    // interpreter so that they can be checked against it.
+    pub fn into_lookups(self) -> Vec<Lookup> {
+        self.lookups
+    }
+
+    // Resolves a whole program. Unlike resolve_list, it also checks the
+    // assignments that could only be checked at the end.
+    pub fn resolve_program(&mut self, statements: &Vec<Rc<Stmt>>) -> Result<(), RatexError> {
+        self.resolve_list(statements)?;
+
+        for name in std::mem::take(&mut self.later_assignments) {
+            if !self.is_global(&name.lexeme) {
+                return Err(undeclared(&name));
+            }
         }
+
+        Ok(())
     }
 
     pub fn resolve_list(&mut self, statements: &Vec<Rc<Stmt>>) -> Result<(), RatexError> {
         for statement in statements {
-            self.resolve_stmt(&statement)?;
+            self.resolve_stmt(statement)?;
         }
         Ok(())
     }
 
     fn resolve_stmt(&mut self, stmt: &Rc<Stmt>) -> Result<(), RatexError> {
-        Ok(stmt.accept(self)?)
+        stmt.accept(self)
     }
 
     fn resolve_expr(&mut self, expr: &Rc<Expr>) -> Result<(), RatexError> {
-        Ok(expr.accept(self)?)
+        expr.accept(self)
+    }
+
+    // Only the class names in a constructor pattern are looked up; the other
+    // names in it are bindings.
+    fn resolve_pattern(&mut self, pattern: &Rc<Expr>) -> Result<(), RatexError> {
+        if let Expr::Call(call) = pattern.as_ref() {
+            self.resolve_expr(&call.callee)?;
+
+            for field in &call.arguments {
+                self.resolve_pattern(field)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resolve_bounds(&mut self, start: &Rc<Expr>, end: &Rc<Expr>) -> Result<(), RatexError> {
+        if **start != Expr::Empty {
+            self.resolve_expr(start)?;
+        }
+
+        if **end != Expr::Empty {
+            self.resolve_expr(end)?;
+        }
+
+        Ok(())
     }
 
     fn begin_scope(&mut self) {
         self.scopes.push_back(RefCell::new(HashMap::new()));
+        self.constants.push_back(HashSet::new());
     }
 
     fn end_scope(&mut self) {
-        self.scopes.pop_back();
+        if let Some(scope) = self.scopes.pop_back() {
+            let mut unused: Vec<(u32, String)> = scope
+                .into_inner()
+                .into_iter()
+                .filter_map(|(name, binding)| match binding.line {
+                    Some(line) if !binding.used => Some((line, name)),
+                    _ => None,
+                })
+                .collect();
+            unused.sort();
+
+            for (line, name) in unused {
+                self.warnings.push(RatexError {
+                    source: RatexErrorType::UnusedVariable(line, name),
+                    backtrace: None,
+                });
+            }
+        }
+
+        self.constants.pop_back();
+    }
+
+    // The constants of the innermost scope, or the globals outside of any.
+    fn current_constants(&mut self) -> &mut HashSet<String> {
+        match self.constants.back_mut() {
+            Some(constants) => constants,
+            None => &mut self.global_constants,
+        }
+    }
+
+    // Whether `name` refers to a constant, looking in the same scope
+    // resolve_local would find it in.
+    fn is_constant(&self, name: &RXT) -> bool {
+        for i in (0..self.scopes.len()).rev() {
+            if self.scopes[i].borrow().contains_key(&name.lexeme) {
+                return self.constants[i].contains(&name.lexeme);
+            }
+        }
+
+        self.global_constants.contains(&name.lexeme)
     }
 
-    fn declare(&self, name: RXT) -> Result<(), RatexError> {
+    fn declare(&mut self, name: &RXT) -> Result<(), RatexError> {
         if self.scopes.is_empty() {
+            self.globals.insert(name.lexeme.clone());
             return Ok(());
         }
 
         let mut map = self.scopes.back().unwrap().borrow_mut();
 
-        if map.contains_key(&name.lexeme) {
+        let slot = map.len();
+
+        if let std::collections::hash_map::Entry::Vacant(e) = map.entry(name.lexeme.clone()) {
+            e.insert(Binding {
+                slot,
+                ..Binding::default()
+            });
+            Ok(())
+        } else {
             Err(RatexError {
                 source: RatexErrorType::RedeclareLocalVariable(name.line),
+                backtrace: None,
             })
-        } else {
-            map.insert(name.lexeme, false);
-            Ok(())
         }
     }
 
-    fn define(&self, name: RXT) {
+    fn define(&self, name: &RXT) {
         if self.scopes.is_empty() {
             return;
         }
@@ -88,57 +232,153 @@ impl Resolver {
             .back()
             .unwrap()
             .borrow_mut()
-            .insert(name.lexeme, true);
+            .entry(name.lexeme.clone())
+            .or_default()
+            .defined = true;
     }
 
-    fn resolve_local(&mut self, target: Rc<Expr>, name: &RXT) {
+    // Returns the index of the scope the name was found in.
+    fn resolve_local(&mut self, target: Rc<Expr>, name: &RXT) -> Option<usize> {
         for i in (0..self.scopes.len()).rev() {
-            if self
-                .scopes
-                .get(i)
-                .unwrap()
-                .borrow()
-                .contains_key(&name.lexeme)
-            {
-                self.interpreter
-                    .borrow_mut()
-                    .resolve(target, self.scopes.len() - 1 - i);
-                return;
+            let slot = self.scopes[i].borrow().get(&name.lexeme).map(|b| b.slot);
+
+            if let Some(slot) = slot {
+                let depth = self.scopes.len() - 1 - i;
+                self.lookups
+                    .push((Rc::clone(&target), name.clone(), Some((depth, slot))));
+                self.interpreter.resolve(target, depth, slot);
+                return Some(i);
+            }
+        }
+
+        self.lookups.push((target, name.clone(), None));
+        None
+    }
+
+    // Defines a name bound by var or const, which is the only kind of
+    // binding that can be warned about going unused.
+    fn define_variable(&mut self, name: &RXT, constant: bool) {
+        self.define(name);
+
+        if let Some(scope) = self.scopes.back() {
+            if let Some(binding) = scope.borrow_mut().get_mut(&name.lexeme) {
+                binding.line = Some(name.line);
+            }
+        }
+
+        let lexeme = name.lexeme.clone();
+        if constant {
+            self.current_constants().insert(lexeme);
+        } else {
+            self.current_constants().remove(&lexeme);
+        }
+    }
+
+    fn resolve_assign_target(&mut self, target: &Rc<Assign>) -> Result<(), RatexError> {
+        if self.is_constant(&target.name) {
+            return Err(RatexError {
+                source: RatexErrorType::AssignToConst(
+                    target.name.lexeme.clone(),
+                    Some(target.name.line),
+                ),
+                backtrace: None,
+            });
+        }
+
+        let scope = self.resolve_local(Rc::new(Expr::Assign(Rc::clone(target))), &target.name);
+
+        if scope.is_none() && !self.is_global(&target.name.lexeme) {
+            match self.current_function {
+                FunctionType::None => return Err(undeclared(&target.name)),
+                _ => self.later_assignments.push(target.name.clone()),
             }
         }
+
+        Ok(())
     }
 
+    // Globals from an earlier run, such as a previous line in the REPL, and
+    // native functions are only known to the interpreter.
+    fn is_global(&self, name: &str) -> bool {
+        self.globals.contains(name) || self.interpreter.get_global(name.to_owned()).is_ok()
+    }
+
+    // Shared by declared functions, methods and lambdas, which only differ
+    // in where their parts live.
     fn resolve_function(
         &mut self,
-        fun: &Rc<Fun>,
+        params: &[RXT],
+        defaults: &[Rc<Expr>],
+        body: &Vec<Rc<Stmt>>,
         func_type: FunctionType,
     ) -> Result<(), RatexError> {
-        let enclosing_function = self.current_function.clone();
+        // defaults are evaluated where the function was declared, not inside it
+        self.resolve_defaults(defaults)?;
+
+        let enclosing_function = self.current_function;
         self.current_function = func_type;
+        let enclosing_labels = std::mem::take(&mut self.labels);
+        let enclosing_scope = std::mem::replace(&mut self.function_scope, self.scopes.len());
 
         self.begin_scope();
 
-        for param in &fun.params {
-            self.declare(param.clone())?;
-            self.define(param.clone());
+        for param in params {
+            self.declare(param)?;
+            self.define(param);
         }
 
-        self.resolve_list(&fun.body)?;
+        self.resolve_list(body)?;
         self.end_scope();
 
         self.current_function = enclosing_function;
+        self.labels = enclosing_labels;
+        self.function_scope = enclosing_scope;
+
+        Ok(())
+    }
+
+    fn resolve_loop_body(
+        &mut self,
+        label: &Option<RXT>,
+        body: &Rc<Stmt>,
+    ) -> Result<(), RatexError> {
+        let Some(label) = label else {
+            return self.resolve_stmt(body);
+        };
+
+        self.labels.push(label.lexeme.clone());
+        let result = self.resolve_stmt(body);
+        self.labels.pop();
+
+        result
+    }
+
+    fn resolve_defaults(&mut self, defaults: &[Rc<Expr>]) -> Result<(), RatexError> {
+        for default in defaults {
+            if **default != Expr::Empty {
+                self.resolve_expr(default)?;
+            }
+        }
 
         Ok(())
     }
 }
 
-impl ExprVisitor<()> for Resolver {
+impl ExprVisitor<()> for Resolver<'_> {
     fn visit_binary(&mut self, target: Rc<Binary>) -> Result<(), RatexError> {
         self.resolve_expr(&target.left)?;
         self.resolve_expr(&target.right)?;
         Ok(())
     }
 
+    fn visit_chain(&mut self, target: Rc<Chain>) -> Result<(), RatexError> {
+        for operand in target.operands.iter() {
+            self.resolve_expr(operand)?;
+        }
+
+        Ok(())
+    }
+
     fn visit_unary(&mut self, target: Rc<Unary>) -> Result<(), RatexError> {
         self.resolve_expr(&target.right)?;
         Ok(())
@@ -160,22 +400,23 @@ impl ExprVisitor<()> for Resolver {
     }
 
     fn visit_variable(&mut self, target: Rc<Variable>) -> Result<(), RatexError> {
-        if self.scopes.len() > 0 {
-            if let Some(b) = self
-                .scopes
-                .back()
-                .unwrap()
-                .borrow()
-                .get(&target.name.lexeme)
-            {
-                if !b {
+        let scope = self.resolve_local(Rc::new(Expr::Variable(Rc::clone(&target))), &target.name);
+
+        // the name is looked up in the scope it resolved to, since the
+        // initialiser can open scopes of its own
+        if let Some(i) = scope {
+            if let Some(binding) = self.scopes[i].borrow_mut().get_mut(&target.name.lexeme) {
+                if !binding.defined && i >= self.function_scope {
                     return Err(RatexError {
-                        source: RatexErrorType::Break,
+                        source: RatexErrorType::VarInInitialiser(target.name.line),
+                        backtrace: None,
                     });
                 }
-            }
 
-            self.resolve_local(Rc::new(Expr::Variable(Rc::clone(&target))), &target.name);
+                // only reads count as a use, so assignments don't mark the
+                // binding
+                binding.used = true;
+            }
         }
 
         Ok(())
@@ -183,7 +424,26 @@ impl ExprVisitor<()> for Resolver {
 
     fn visit_assign(&mut self, target: Rc<Assign>) -> Result<(), RatexError> {
         self.resolve_expr(&target.value)?;
-        self.resolve_local(Rc::new(Expr::Assign(Rc::clone(&target))), &target.name);
+        self.resolve_assign_target(&target)
+    }
+
+    fn visit_assign_tuple(&mut self, target: Rc<AssignTuple>) -> Result<(), RatexError> {
+        self.resolve_expr(&target.value)?;
+
+        for assign in &target.targets {
+            if let Expr::Assign(assign) = assign.borrow() {
+                self.resolve_assign_target(assign)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn visit_tuple_literal(&mut self, target: Rc<TupleLiteral>) -> Result<(), RatexError> {
+        for element in &target.elements {
+            self.resolve_expr(element)?;
+        }
+
         Ok(())
     }
 
@@ -197,13 +457,72 @@ impl ExprVisitor<()> for Resolver {
         Ok(())
     }
 
+    // The body gets its own scope for the parameters, matching the child
+    // environment each call runs in. A named lambda has one more scope
+    // around that, holding just its name.
     fn visit_lambda(&mut self, target: Rc<Lambda>) -> Result<(), RatexError> {
-        for statement in &target.body {
-            self.resolve_stmt(statement)?;
+        if let Some(name) = &target.name {
+            self.begin_scope();
+            self.declare(name)?;
+            self.define(name);
+        }
+
+        self.resolve_function(
+            &target.params,
+            &target.defaults,
+            &target.body,
+            FunctionType::Function,
+        )?;
+
+        if target.name.is_some() {
+            self.end_scope();
         }
+
         Ok(())
     }
 
+    fn visit_type_check(&mut self, target: Rc<TypeCheck>) -> Result<(), RatexError> {
+        self.resolve_expr(&target.object)?;
+        self.resolve_expr(&target.class)?;
+        Ok(())
+    }
+
+    fn visit_loop(&mut self, target: Rc<Loop>) -> Result<(), RatexError> {
+        self.resolve_stmt(&target.statement)
+    }
+
+    fn visit_match(&mut self, target: Rc<Match>) -> Result<(), RatexError> {
+        self.resolve_expr(&target.scrutinee)?;
+
+        for (pattern, value) in &target.arms {
+            self.resolve_pattern(pattern)?;
+
+            // an arm that binds names gets a scope of its own to hold them
+            let bindings = Match::bindings(pattern);
+
+            if bindings.is_empty() {
+                self.resolve_expr(value)?;
+                continue;
+            }
+
+            self.begin_scope();
+
+            for name in &bindings {
+                self.declare(name)?;
+                self.define(name);
+            }
+
+            self.resolve_expr(value)?;
+            self.end_scope();
+        }
+
+        Ok(())
+    }
+
+    fn visit_invariant(&mut self, target: Rc<Invariant>) -> Result<(), RatexError> {
+        self.resolve_expr(&target.expr)
+    }
+
     fn visit_get(&mut self, target: Rc<Get>) -> Result<(), RatexError> {
         self.resolve_expr(&target.object)?;
         Ok(())
@@ -219,9 +538,41 @@ impl ExprVisitor<()> for Resolver {
         self.resolve_local(Rc::new(Expr::This(Rc::clone(&target))), &target.keyword);
         Ok(())
     }
+
+    fn visit_list_literal(&mut self, target: Rc<ListLiteral>) -> Result<(), RatexError> {
+        for element in &target.elements {
+            self.resolve_expr(element)?;
+        }
+
+        Ok(())
+    }
+
+    fn visit_index(&mut self, target: Rc<Index>) -> Result<(), RatexError> {
+        self.resolve_expr(&target.object)?;
+        self.resolve_expr(&target.index)?;
+        Ok(())
+    }
+
+    fn visit_index_set(&mut self, target: Rc<IndexSet>) -> Result<(), RatexError> {
+        self.resolve_expr(&target.value)?;
+        self.resolve_expr(&target.object)?;
+        self.resolve_expr(&target.index)?;
+        Ok(())
+    }
+
+    fn visit_slice(&mut self, target: Rc<Slice>) -> Result<(), RatexError> {
+        self.resolve_expr(&target.object)?;
+        self.resolve_bounds(&target.start, &target.end)
+    }
+
+    fn visit_slice_set(&mut self, target: Rc<SliceSet>) -> Result<(), RatexError> {
+        self.resolve_expr(&target.value)?;
+        self.resolve_expr(&target.object)?;
+        self.resolve_bounds(&target.start, &target.end)
+    }
 }
 
-impl StmtVisitor<()> for Resolver {
+impl StmtVisitor<()> for Resolver<'_> {
     fn visit_block(&mut self, target: Rc<Block>) -> Result<(), RatexError> {
         self.begin_scope();
         self.resolve_list(&target.statements)?;
@@ -246,20 +597,99 @@ impl StmtVisitor<()> for Resolver {
     }
 
     fn visit_fun(&mut self, target: Rc<Fun>) -> Result<(), RatexError> {
-        self.declare(target.name.clone())?;
-        self.define(target.name.clone());
-        self.resolve_function(&target, FunctionType::Function)?;
+        self.declare(&target.name)?;
+        self.define(&target.name);
+        self.resolve_function(
+            &target.params,
+            &target.defaults,
+            &target.body,
+            FunctionType::Function,
+        )?;
         Ok(())
     }
 
     fn visit_while(&mut self, target: Rc<While>) -> Result<(), RatexError> {
         self.resolve_expr(&target.condition)?;
-        self.resolve_stmt(&target.body)?;
+        self.resolve_loop_body(&target.label, &target.body)?;
+
+        if *target.else_stmt != Stmt::Empty {
+            self.resolve_stmt(&target.else_stmt)?;
+        }
 
         Ok(())
     }
 
-    fn visit_break(&mut self, _: Rc<Break>) -> Result<(), RatexError> {
+    fn visit_for(&mut self, target: Rc<For>) -> Result<(), RatexError> {
+        self.begin_scope();
+
+        if *target.initialiser != Stmt::Empty {
+            self.resolve_stmt(&target.initialiser)?;
+        }
+
+        self.resolve_expr(&target.condition)?;
+        self.resolve_loop_body(&target.label, &target.body)?;
+
+        if *target.increment != Expr::Empty {
+            self.resolve_expr(&target.increment)?;
+        }
+
+        if *target.else_stmt != Stmt::Empty {
+            self.resolve_stmt(&target.else_stmt)?;
+        }
+
+        self.end_scope();
+
+        Ok(())
+    }
+
+    fn visit_yield(&mut self, _: Rc<Yield>) -> Result<(), RatexError> {
+        Ok(())
+    }
+
+    fn visit_try(&mut self, target: Rc<Try>) -> Result<(), RatexError> {
+        self.begin_scope();
+        self.resolve_list(&target.body)?;
+        self.end_scope();
+
+        self.begin_scope();
+        self.declare(&target.name)?;
+        self.define(&target.name);
+        self.resolve_list(&target.handler)?;
+        self.end_scope();
+
+        Ok(())
+    }
+
+    fn visit_with(&mut self, target: Rc<With>) -> Result<(), RatexError> {
+        self.resolve_expr(&target.resource)?;
+
+        self.begin_scope();
+        self.declare(&target.name)?;
+        self.define(&target.name);
+        self.resolve_list(&target.body)?;
+        self.end_scope();
+
+        Ok(())
+    }
+
+    fn visit_throw(&mut self, target: Rc<Throw>) -> Result<(), RatexError> {
+        self.resolve_expr(&target.value)?;
+        Ok(())
+    }
+
+    fn visit_break(&mut self, target: Rc<Break>) -> Result<(), RatexError> {
+        if let Some(label) = &target.label {
+            if !self.labels.contains(&label.lexeme) {
+                return Err(RatexError {
+                    source: RatexErrorType::UndefinedLabel(label.line, label.lexeme.clone()),
+                    backtrace: None,
+                });
+            }
+        }
+
+        if *target.value != Expr::Empty {
+            self.resolve_expr(&target.value)?;
+        }
         Ok(())
     }
 
@@ -272,6 +702,7 @@ impl StmtVisitor<()> for Resolver {
         if let FunctionType::None = self.current_function {
             return Err(RatexError {
                 source: RatexErrorType::InvalidReturnLocation,
+                backtrace: None,
             });
         }
 
@@ -283,32 +714,51 @@ impl StmtVisitor<()> for Resolver {
     }
 
     fn visit_var(&mut self, target: Rc<Var>) -> Result<(), RatexError> {
-        self.declare(target.name.clone())?;
+        self.declare(&target.name)?;
 
         if *target.initialiser != Expr::Empty {
             self.resolve_expr(&target.initialiser)?;
         }
 
-        self.define(target.name.clone());
+        self.define_variable(&target.name, target.constant);
+        Ok(())
+    }
+
+    fn visit_var_tuple(&mut self, target: Rc<VarTuple>) -> Result<(), RatexError> {
+        for name in &target.names {
+            self.declare(name)?;
+        }
+
+        self.resolve_expr(&target.initialiser)?;
+
+        for name in &target.names {
+            self.define_variable(name, target.constant);
+        }
 
         Ok(())
     }
 
+    fn visit_variant(&mut self, target: Rc<Variant>) -> Result<(), RatexError> {
+        self.resolve_list(&target.constructors)
+    }
+
     fn visit_class(&mut self, target: Rc<Class>) -> Result<(), RatexError> {
-        self.declare(target.name.clone())?;
-        self.define(target.name.clone());
+        self.declare(&target.name)?;
+        self.define(&target.name);
 
         self.begin_scope();
-        self.scopes
-            .back()
-            .unwrap()
-            .borrow_mut()
-            .insert("this".to_string(), true);
+        self.scopes.back().unwrap().borrow_mut().insert(
+            "this".to_string(),
+            Binding {
+                defined: true,
+                ..Binding::default()
+            },
+        );
 
         for method in &target.methods {
             if let Stmt::Fun(fun) = method.borrow() {
                 let declaration = FunctionType::Method;
-                self.resolve_function(fun, declaration)?;
+                self.resolve_function(&fun.params, &fun.defaults, &fun.body, declaration)?;
             }
         }
 
@@ -316,3 +766,10 @@ impl StmtVisitor<()> for Resolver {
         Ok(())
     }
 }
+
+fn undeclared(name: &RXT) -> RatexError {
+    RatexError {
+        source: RatexErrorType::UndefinedIdentifier(name.lexeme.clone(), Some(name.line)),
+        backtrace: None,
+    }
+}
@@ -1,4 +1,9 @@
-use std::{collections::HashMap, iter::Peekable, str::Chars};
+use std::{
+    collections::{HashMap, HashSet},
+    iter::Peekable,
+    rc::Rc,
+    str::Chars,
+};
 
 use crate::{
     error::{RatexError, RatexErrorType},
@@ -13,6 +18,7 @@ pub struct Scanner<'a> {
     current: usize,
     line: u32,
     hash_map: HashMap<&'a str, RatexTokenType>,
+    strings: HashSet<Rc<str>>,
 }
 
 impl<'a> Scanner<'a> {
@@ -27,37 +33,66 @@ impl<'a> Scanner<'a> {
             line: 1,
             hash_map: HashMap::from([
                 ("and", RXTT::And),
+                ("catch", RXTT::Catch),
                 ("class", RXTT::Class),
                 ("else", RXTT::Else),
                 ("false", RXTT::False),
                 ("for", RXTT::For),
                 ("fun", RXTT::Fun),
                 ("if", RXTT::If),
+                ("is", RXTT::Is),
+                ("match", RXTT::Match),
                 ("nil", RXTT::Nil),
                 ("or", RXTT::Or),
                 ("print", RXTT::Print),
                 ("return", RXTT::Return),
                 ("super", RXTT::Super),
                 ("this", RXTT::This),
+                ("throw", RXTT::Throw),
                 ("true", RXTT::True),
+                ("try", RXTT::Try),
                 ("var", RXTT::Var),
+                ("const", RXTT::Const),
+                ("variant", RXTT::Variant),
                 ("while", RXTT::While),
+                ("with", RXTT::With),
+                ("yield", RXTT::Yield),
                 ("break", RXTT::Break),
             ]),
+            strings: HashSet::new(),
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Vec<RatexToken> {
+    // Scanning carries on past an error so that every error in the source is
+    // reported at once.
+    pub fn scan_tokens(&mut self) -> Result<Vec<RatexToken>, Vec<RatexError>> {
+        let mut errors = Vec::new();
+
         while !self.is_at_end() {
             self.start = self.current;
-            match self.scan_token() {
-                Err(e) => {
-                    println!("{e:?}")
-                }
-                _ => {}
+            if let Err(e) = self.scan_token() {
+                errors.push(e);
             }
         }
 
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(self.finish())
+    }
+
+    // Stops at the first error, for callers that only hand back one.
+    pub fn scan_until_error(&mut self) -> Result<Vec<RatexToken>, RatexError> {
+        while !self.is_at_end() {
+            self.start = self.current;
+            self.scan_token()?;
+        }
+
+        Ok(self.finish())
+    }
+
+    fn finish(&mut self) -> Vec<RatexToken> {
         self.tokens.push(RatexToken {
             token_type: RatexTokenType::EOF,
             lexeme: "EOF".to_string(),
@@ -68,7 +103,7 @@ impl<'a> Scanner<'a> {
     }
 
     fn is_at_end(&self) -> bool {
-        return self.current >= self.source.len();
+        self.current >= self.source.len()
     }
 
     fn scan_token(&mut self) -> Result<(), RatexError> {
@@ -82,7 +117,15 @@ impl<'a> Scanner<'a> {
             ')' => self.add_token(RXTT::RightParen),
             '{' => self.add_token(RXTT::LeftBrace),
             '}' => self.add_token(RXTT::RightBrace),
+            '[' => self.add_token(RXTT::LeftBracket),
+            ']' => self.add_token(RXTT::RightBracket),
+            ':' => self.add_token(RXTT::Colon),
             ',' => self.add_token(RXTT::Comma),
+            '.' if self.source.get(self.current..self.current + 2) == Some("..") => {
+                self.advance();
+                self.advance();
+                self.add_token(RXTT::Ellipsis)
+            }
             '.' => self.add_token(RXTT::Dot),
             '-' => self.add_token(RXTT::Minus),
             '+' => self.add_token(RXTT::Plus),
@@ -98,6 +141,8 @@ impl<'a> Scanner<'a> {
             '=' => {
                 if self.advance_if('=') {
                     self.add_token(RXTT::EqualEqual)
+                } else if self.advance_if('>') {
+                    self.add_token(RXTT::EqualGreater)
                 } else {
                     self.add_token(RXTT::Equal)
                 }
@@ -116,22 +161,27 @@ impl<'a> Scanner<'a> {
                     self.add_token(RXTT::Less)
                 }
             }
+            '|' if self.advance_if('>') => self.add_token(RXTT::PipeGreater),
             '/' => {
                 if self.advance_if('/') {
                     while !self.is_at_end() && *self.chars.peek().unwrap() != '\n' {
                         self.advance().unwrap();
                     }
                 } else if self.advance_if('*') {
-                    let mut terminated = false;
+                    // comments nest, so each /* needs its own */
+                    let mut depth = 1;
 
-                    while !self.is_at_end() {
+                    while !self.is_at_end() && depth > 0 {
                         if self.advance_if('*') {
                             if self.advance_if('/') {
-                                terminated = true;
-                                break;
+                                depth -= 1;
                             }
-                        } else {
-                            self.advance().unwrap();
+                        } else if self.advance_if('/') {
+                            if self.advance_if('*') {
+                                depth += 1;
+                            }
+                        } else if self.advance() == Some('\n') {
+                            self.line += 1;
                         }
                     }
 
@@ -141,28 +191,33 @@ impl<'a> Scanner<'a> {
                         .unwrap()
                         .to_owned();
 
-                    if !terminated {
+                    if depth > 0 {
                         return Err(RatexError {
                             source: RatexErrorType::UnterminatedBlockComment(self.line, value),
+                            backtrace: None,
                         });
                     }
                 } else {
                     self.add_token(RXTT::Slash)
                 }
             }
-            '"' => match self.scan_string() {
-                Err(e) => err = Some(e),
-                _ => {}
-            },
+            '"' => {
+                if let Err(e) = self.scan_string() {
+                    err = Some(e)
+                }
+            }
+            '\'' => self.scan_label()?,
             '0'..='9' => self.scan_number()?,
             ' ' | '\r' | '\t' => {}
             '\n' => self.line += 1,
-            'a'..='z' | 'A'..='Z' | '_' => {
+            // identifiers can use letters from any script, not just ASCII
+            c if c.is_alphabetic() || c == '_' => {
                 self.scan_identifier()?;
             }
             _ => {
                 err = Some(RatexError {
                     source: RatexErrorType::UnknownToken(self.line, c.to_string()),
+                    backtrace: None,
                 })
             }
         }
@@ -173,10 +228,13 @@ impl<'a> Scanner<'a> {
         }
     }
 
+    // `current` is a byte offset into the source, so multibyte characters
+    // move it along by more than one
     fn advance(&mut self) -> Option<char> {
-        self.current += 1;
+        let c = self.chars.next();
+        self.current += c.map_or(1, char::len_utf8);
 
-        self.chars.next()
+        c
     }
 
     fn add_token(&mut self, token: RatexTokenType) {
@@ -195,16 +253,11 @@ impl<'a> Scanner<'a> {
 
     fn advance_if(&mut self, next_char: char) -> bool {
         match self.chars.peek() {
-            Some(char) => {
-                if *char == next_char {
-                    self.chars.next();
-                    self.current += 1;
-                    true
-                } else {
-                    false
-                }
+            Some(char) if *char == next_char => {
+                self.advance();
+                true
             }
-            None => false,
+            _ => false,
         }
     }
 
@@ -227,60 +280,126 @@ impl<'a> Scanner<'a> {
                         .unwrap()
                         .to_owned(),
                 ),
+                backtrace: None,
             });
         }
 
         self.advance();
 
-        let value = self
-            .source
-            .get(self.start + 1..self.current - 1)
-            .unwrap()
-            .to_owned();
+        let value = self.intern(self.start + 1, self.current - 1);
 
         self.add_token(RatexTokenType::String(value));
         Ok(())
     }
 
-    fn scan_number(&mut self) -> Result<(), RatexError> {
-        while !self.is_at_end() && self.chars.peek().unwrap().is_digit(10) {
-            self.advance();
+    // Hands out one shared copy of each distinct literal, so a string that
+    // appears many times in the source is allocated once and compares equal
+    // to itself by pointer. Only string literals are interned. Identifiers
+    // stay as String in tokens and environments, and strings built while
+    // running, by `+` or upper() say, get their own allocation, so comparing
+    // those still goes through the characters.
+    fn intern(&mut self, start: usize, end: usize) -> Rc<str> {
+        let text = &self.source[start..end];
+        if let Some(s) = self.strings.get(text) {
+            return Rc::clone(s);
         }
 
+        let s: Rc<str> = Rc::from(text);
+        self.strings.insert(Rc::clone(&s));
+        s
+    }
+
+    fn scan_number(&mut self) -> Result<(), RatexError> {
+        self.scan_digits();
+
         if !self.is_at_end() && *self.chars.peek().unwrap() == '.' {
             self.advance();
 
             // check if there's a number after the period to make sure it's a decimal point
-            if !self.is_at_end() && self.chars.peek().unwrap().is_digit(10) {
-                while !self.is_at_end() && self.chars.peek().unwrap().is_digit(10) {
-                    self.advance();
-                }
+            if !self.is_at_end() && self.chars.peek().unwrap().is_ascii_digit() {
+                self.scan_digits();
             } else {
-                self.add_token(RatexTokenType::Number(
-                    self.source
-                        .get(self.start..self.current - 1)
-                        .unwrap()
-                        .parse::<f64>()
-                        .unwrap(),
-                ));
+                let value = self.number_value(self.current - 1)?;
+                self.tokens.push(RatexToken {
+                    token_type: RatexTokenType::Number(value),
+                    lexeme: self.source[self.start..self.current - 1].to_owned(),
+                    line: self.line,
+                });
+
+                self.start = self.current - 1;
                 self.add_token(RatexTokenType::Dot);
                 return Ok(());
             }
         }
 
-        self.add_token(RatexTokenType::Number(
-            self.source
-                .get(self.start..self.current)
-                .unwrap()
-                .parse::<f64>()
-                .unwrap(),
-        ));
+        let value = self.number_value(self.current)?;
+        self.add_token(RatexTokenType::Number(value));
+
+        Ok(())
+    }
+
+    // Digits can be grouped with underscores, as in 1_000_000, which are
+    // checked and dropped by number_value.
+    fn scan_digits(&mut self) {
+        while !self.is_at_end()
+            && (self.chars.peek().unwrap().is_ascii_digit() || *self.chars.peek().unwrap() == '_')
+        {
+            self.advance();
+        }
+    }
+
+    // Parses the number from the start of the token up to `end`. Each
+    // underscore in it has to sit between two digits.
+    fn number_value(&self, end: usize) -> Result<f64, RatexError> {
+        let text = &self.source[self.start..end];
+        let bytes = text.as_bytes();
+
+        let misplaced = bytes.iter().enumerate().any(|(i, b)| {
+            *b == b'_'
+                && !(i > 0
+                    && bytes[i - 1].is_ascii_digit()
+                    && bytes.get(i + 1).is_some_and(u8::is_ascii_digit))
+        });
+
+        if misplaced {
+            return Err(RatexError {
+                source: RatexErrorType::MisplacedDigitSeparator(self.line, text.to_owned()),
+                backtrace: None,
+            });
+        }
+
+        Ok(text.replace('_', "").parse::<f64>().unwrap())
+    }
+
+    // The lexeme keeps the quote, so a label reads the same wherever it's
+    // shown.
+    fn scan_label(&mut self) -> Result<(), RatexError> {
+        if !self
+            .chars
+            .peek()
+            .is_some_and(|c| c.is_alphabetic() || *c == '_')
+        {
+            return Err(RatexError {
+                source: RatexErrorType::UnknownToken(self.line, "'".to_owned()),
+                backtrace: None,
+            });
+        }
+
+        while !self.is_at_end()
+            && (self.chars.peek().unwrap().is_alphanumeric() || *self.chars.peek().unwrap() == '_')
+        {
+            self.advance();
+        }
+
+        self.add_token(RatexTokenType::Label);
 
         Ok(())
     }
 
     fn scan_identifier(&mut self) -> Result<(), RatexError> {
-        while !self.is_at_end() && self.chars.peek().unwrap().is_alphanumeric() {
+        while !self.is_at_end()
+            && (self.chars.peek().unwrap().is_alphanumeric() || *self.chars.peek().unwrap() == '_')
+        {
             self.advance();
         }
 
@@ -0,0 +1,269 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    ast::{Expr, For, Stmt, While},
+    environment::Environment,
+    error::{RatexError, RatexErrorType},
+    interpreter::{breaks_out_of, RatexInterpreter},
+};
+
+// A spawned function that can stop at a `yield` and carry on from there later.
+//
+// The interpreter runs statements by recursing on the Rust stack, which can't
+// be paused, so a task keeps its own stack of frames for the statements that
+// can contain a yield: blocks, ifs and loops in the task's body. Everything
+// else, including any function the task calls, runs to completion in one go,
+// which is why a yield inside a called function is an error.
+#[derive(Debug)]
+pub struct Task {
+    frames: Vec<Frame>,
+    environment: Rc<RefCell<Environment>>,
+}
+
+#[derive(Debug)]
+enum Frame {
+    Statements {
+        statements: Vec<Rc<Stmt>>,
+        next: usize,
+    },
+    While(Rc<While>),
+    For {
+        target: Rc<For>,
+        started: bool,
+    },
+    // the environment to go back to once the frames above it are finished
+    Scope(Rc<RefCell<Environment>>),
+}
+
+impl Task {
+    pub fn new(body: Vec<Rc<Stmt>>, closure: Rc<RefCell<Environment>>) -> Self {
+        Task {
+            frames: vec![Frame::Statements {
+                statements: body,
+                next: 0,
+            }],
+            environment: Environment::new_child(closure),
+        }
+    }
+
+    // Runs the task until it yields or finishes, returning true once it has
+    // finished.
+    pub fn resume(&mut self, interpreter: &mut RatexInterpreter) -> Result<bool, RatexError> {
+        let old_environment = interpreter.replace_environment(Rc::clone(&self.environment));
+
+        let result = self.run(interpreter);
+
+        self.environment = interpreter.replace_environment(old_environment);
+
+        match result {
+            Err(RatexError {
+                source: RatexErrorType::Return(_),
+                ..
+            }) => Ok(true),
+            result => result,
+        }
+    }
+
+    fn run(&mut self, interpreter: &mut RatexInterpreter) -> Result<bool, RatexError> {
+        while let Some(frame) = self.frames.last_mut() {
+            let result = match frame {
+                Frame::Statements { statements, next } => match statements.get(*next) {
+                    Some(statement) => {
+                        let statement = Rc::clone(statement);
+                        *next += 1;
+
+                        self.start(statement, interpreter)
+                    }
+                    None => {
+                        self.pop(interpreter);
+                        Ok(false)
+                    }
+                },
+                Frame::While(target) => {
+                    let target = Rc::clone(target);
+
+                    self.step_loop(
+                        &target.condition,
+                        &target.body,
+                        &target.else_stmt,
+                        interpreter,
+                    )
+                }
+                Frame::For { target, started } => {
+                    let target = Rc::clone(target);
+                    let next_iteration = *started;
+                    *started = true;
+
+                    self.step_for(&target, next_iteration, interpreter)
+                }
+                Frame::Scope(_) => {
+                    self.pop(interpreter);
+                    Ok(false)
+                }
+            };
+
+            match result {
+                Ok(true) => return Ok(false),
+                Ok(false) => {}
+                Err(RatexError {
+                    source: RatexErrorType::Break(label, _),
+                    ..
+                }) if self.break_loop(&label, interpreter) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Starts running a statement, returning true if it was a yield.
+    fn start(
+        &mut self,
+        statement: Rc<Stmt>,
+        interpreter: &mut RatexInterpreter,
+    ) -> Result<bool, RatexError> {
+        match statement.as_ref() {
+            Stmt::Yield(_) => return Ok(true),
+            Stmt::Block(block) => {
+                self.enter_scope(interpreter);
+                self.frames.push(Frame::Statements {
+                    statements: block.statements.clone(),
+                    next: 0,
+                });
+            }
+            Stmt::If(target) => {
+                interpreter.step()?;
+
+                if interpreter
+                    .evaluate(Rc::clone(&target.condition))?
+                    .is_truthy()
+                {
+                    self.push_statement(&target.then_stmt);
+                } else if *target.else_stmt != Stmt::Empty {
+                    self.push_statement(&target.else_stmt);
+                }
+            }
+            Stmt::While(target) => {
+                interpreter.step()?;
+                self.frames.push(Frame::While(Rc::clone(target)));
+            }
+            Stmt::For(target) => {
+                interpreter.step()?;
+                self.enter_scope(interpreter);
+
+                if *target.initialiser != Stmt::Empty {
+                    interpreter.execute(Rc::clone(&target.initialiser))?;
+                }
+
+                self.frames.push(Frame::For {
+                    target: Rc::clone(target),
+                    started: false,
+                });
+            }
+            _ => interpreter.execute(statement)?,
+        }
+
+        Ok(false)
+    }
+
+    fn step_for(
+        &mut self,
+        target: &For,
+        next_iteration: bool,
+        interpreter: &mut RatexInterpreter,
+    ) -> Result<bool, RatexError> {
+        if next_iteration {
+            // the same fresh copy of the loop variables that run_for makes
+            let environment = interpreter.current_environment();
+            let next = environment.borrow().clone();
+            interpreter.replace_environment(Rc::new(RefCell::new(next)));
+
+            if *target.increment != Expr::Empty {
+                interpreter.evaluate(Rc::clone(&target.increment))?;
+            }
+        }
+
+        self.step_loop(
+            &target.condition,
+            &target.body,
+            &target.else_stmt,
+            interpreter,
+        )
+    }
+
+    // Checks a loop's condition, queueing up another run of its body or
+    // leaving the loop. Leaving it this way, rather than with a break, queues
+    // up its else branch, which for a for loop still runs in the scope of the
+    // loop variables.
+    fn step_loop(
+        &mut self,
+        condition: &Rc<Expr>,
+        body: &Rc<Stmt>,
+        else_stmt: &Rc<Stmt>,
+        interpreter: &mut RatexInterpreter,
+    ) -> Result<bool, RatexError> {
+        if interpreter.evaluate(Rc::clone(condition))?.is_truthy() {
+            interpreter.check_deadline()?;
+            self.push_statement(body);
+        } else {
+            self.pop(interpreter);
+
+            if **else_stmt != Stmt::Empty {
+                self.push_statement(else_stmt);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn push_statement(&mut self, statement: &Rc<Stmt>) {
+        self.frames.push(Frame::Statements {
+            statements: vec![Rc::clone(statement)],
+            next: 0,
+        });
+    }
+
+    fn enter_scope(&mut self, interpreter: &mut RatexInterpreter) {
+        let environment = interpreter.current_environment();
+        self.frames.push(Frame::Scope(Rc::clone(&environment)));
+        interpreter.replace_environment(Environment::new_child(environment));
+    }
+
+    fn pop(&mut self, interpreter: &mut RatexInterpreter) -> Option<Frame> {
+        let frame = self.frames.pop();
+
+        if let Some(Frame::Scope(environment)) = &frame {
+            interpreter.replace_environment(Rc::clone(environment));
+        }
+
+        frame
+    }
+
+    // Unwinds to just past the loop the break leaves, returning false if the
+    // break wasn't inside it.
+    fn break_loop(
+        &mut self,
+        label: &Option<Rc<String>>,
+        interpreter: &mut RatexInterpreter,
+    ) -> bool {
+        if !self.frames.iter().any(|f| Self::is_broken_out_of(f, label)) {
+            return false;
+        }
+
+        while let Some(frame) = self.pop(interpreter) {
+            if Self::is_broken_out_of(&frame, label) {
+                break;
+            }
+        }
+
+        true
+    }
+
+    fn is_broken_out_of(frame: &Frame, label: &Option<Rc<String>>) -> bool {
+        match frame {
+            Frame::While(target) => breaks_out_of(label, &target.label),
+            Frame::For { target, .. } => breaks_out_of(label, &target.label),
+            _ => false,
+        }
+    }
+}
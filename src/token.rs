@@ -1,6 +1,7 @@
 use std::{
     fmt::{Display, Formatter, Result},
     hash::Hash,
+    rc::Rc,
 };
 
 #[derive(Clone, Debug, PartialEq, Default, Hash, Eq)]
@@ -16,25 +17,13 @@ impl Display for RatexToken {
 
         match &self.token_type {
             RXTT::String(string) => {
-                write!(
-                    f,
-                    "{} {} {}",
-                    self.token_type.to_string(),
-                    self.lexeme,
-                    string
-                )
+                write!(f, "{} {} {}", self.token_type, self.lexeme, string)
             }
             RXTT::Number(number) => {
-                write!(
-                    f,
-                    "{} {} {}",
-                    self.token_type.to_string(),
-                    self.lexeme,
-                    number
-                )
+                write!(f, "{} {} {}", self.token_type, self.lexeme, number)
             }
             _ => {
-                write!(f, "{} {}", self.token_type.to_string(), self.lexeme)
+                write!(f, "{} {}", self.token_type, self.lexeme)
             }
         }
     }
@@ -47,6 +36,9 @@ pub enum RatexTokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
@@ -59,33 +51,49 @@ pub enum RatexTokenType {
     BangEqual,
     Equal,
     EqualEqual,
+    EqualGreater,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
+    PipeGreater,
+    // Three character tokens.
+    Ellipsis,
     // Literals.
     Identifier,
-    String(String),
+    // a loop's name, written with a leading quote: 'outer
+    Label,
+    String(Rc<str>),
     Number(f64),
     // Keywords.
     And,
+    Catch,
     Class,
+    Const,
     Else,
     False,
     Fun,
     For,
     If,
+    Is,
+    Match,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
+    Variant,
     While,
+    With,
+    Yield,
     #[default]
     Break,
+    #[allow(clippy::upper_case_acronyms)]
     EOF,
 }
 
@@ -0,0 +1,717 @@
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::{
+    ast::{
+        Assign, AssignTuple, Binary, Block, Break, Call, Chain, Class, Expr, ExprAccept,
+        ExprVisitor, Expression, For, Fun, Get, Grouping, If, Index, IndexSet, Invariant, Lambda,
+        ListLiteral, Literal, Logical, Loop, Match, Object, Print, Return, Set, Slice, SliceSet,
+        Stmt, StmtAccept, StmtVisitor, This, Throw, Try, TupleLiteral, TypeCheck, Unary, Var,
+        VarTuple, Variable, Variant, While, With, Yield,
+    },
+    error::{RatexError, RatexErrorType},
+    token::{RatexToken as RXT, RatexTokenType as RXTT},
+};
+
+// What the checker knows about the type of a value. Unknown is anything it
+// can't prove, and nothing involving it is ever reported.
+#[derive(Debug, Clone)]
+enum Type {
+    Number,
+    String,
+    Bool,
+    Nil,
+    List,
+    Tuple,
+    // the signature is known for functions declared in the program
+    Function(Option<Rc<Signature>>),
+    Class(String),
+    Instance(String),
+    Unknown,
+}
+
+impl Type {
+    // Spelled the way Object::type_name spells it, so it can be compared with
+    // the types written in annotations.
+    fn name(&self) -> Option<String> {
+        match self {
+            Type::Number => Some("number".to_owned()),
+            Type::String => Some("string".to_owned()),
+            Type::Bool => Some("bool".to_owned()),
+            Type::Nil => Some("nil".to_owned()),
+            Type::List => Some("list".to_owned()),
+            Type::Tuple => Some("tuple".to_owned()),
+            Type::Function(_) => Some("function".to_owned()),
+            Type::Class(_) => Some("class".to_owned()),
+            Type::Instance(class) => Some(class.clone()),
+            Type::Unknown => None,
+        }
+    }
+
+    // Annotations are checked at runtime, so a value that made it past one
+    // is known to have its type.
+    fn from_annotation(annotation: &Option<RXT>) -> Type {
+        match annotation.as_ref().map(|a| a.lexeme.as_str()) {
+            Some("number") => Type::Number,
+            Some("string") => Type::String,
+            Some("bool") => Type::Bool,
+            Some("nil") => Type::Nil,
+            Some("list") => Type::List,
+            Some("tuple") => Type::Tuple,
+            Some("function") => Type::Function(None),
+            Some(class) => Type::Instance(class.to_owned()),
+            None => Type::Unknown,
+        }
+    }
+
+    fn of_literal(value: &Object) -> Type {
+        match value {
+            Object::Number(_) => Type::Number,
+            Object::String(_) => Type::String,
+            Object::Bool(_) => Type::Bool,
+            Object::Nil => Type::Nil,
+            _ => Type::Unknown,
+        }
+    }
+}
+
+// What calls to a function are checked against.
+#[derive(Debug)]
+struct Signature {
+    name: String,
+    params: Vec<RXT>,
+    param_types: Vec<Option<RXT>>,
+    min_arity: usize,
+    // None when the function is variadic
+    max_arity: Option<usize>,
+    return_type: Option<RXT>,
+}
+
+impl Signature {
+    fn new(
+        name: String,
+        params: &[RXT],
+        param_types: &[Option<RXT>],
+        defaults: &[Rc<Expr>],
+        variadic: bool,
+        return_type: &Option<RXT>,
+    ) -> Rc<Self> {
+        let required = defaults.iter().filter(|d| ***d == Expr::Empty).count();
+
+        Rc::new(Signature {
+            name,
+            params: params.to_vec(),
+            param_types: param_types.to_vec(),
+            min_arity: if variadic { required - 1 } else { required },
+            max_arity: if variadic { None } else { Some(params.len()) },
+            return_type: return_type.clone(),
+        })
+    }
+
+    // The parameter an argument is bound to, which past the end of a
+    // variadic function's parameters is the rest parameter.
+    fn param(&self, i: usize) -> Option<(&RXT, &Option<RXT>)> {
+        let i = match self.max_arity {
+            None => i.min(self.params.len() - 1),
+            Some(_) => i,
+        };
+
+        self.params.get(i).zip(self.param_types.get(i))
+    }
+}
+
+// An optional pass run after the resolver that reports operations and calls
+// that can be shown to go wrong before the program runs. It's conservative:
+// variables that are ever assigned to have an unknown type, and so does
+// anything the checker can't work out, such as what a native returns.
+pub struct TypeChecker {
+    scopes: Vec<HashMap<String, Type>>,
+    // names assigned to anywhere, or declared more than once at the top
+    // level. Their type can change, so they're never known.
+    unstable: HashSet<String>,
+    globals: HashSet<String>,
+    // the name and declared return type of each function being checked,
+    // innermost last
+    functions: Vec<(String, Option<RXT>)>,
+    // the first pass only finds the unstable names, and reports nothing
+    collecting: bool,
+    errors: Vec<RatexError>,
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        TypeChecker {
+            scopes: vec![HashMap::new()],
+            unstable: HashSet::new(),
+            globals: HashSet::new(),
+            functions: Vec::new(),
+            collecting: false,
+            errors: Vec::new(),
+        }
+    }
+
+    // Mirrors RatexInterpreter::binary_op, which gives nil for operands it
+    // doesn't handle rather than failing.
+    fn binary_type(&mut self, operator: &RXT, left: Type, right: Type) -> Type {
+        let operation = match operator.token_type {
+            RXTT::Plus => "add",
+            RXTT::Minus => "subtract",
+            RXTT::Star => "multiply",
+            RXTT::Slash => "divide",
+            RXTT::Greater | RXTT::GreaterEqual | RXTT::Less | RXTT::LessEqual => "compare",
+            RXTT::EqualEqual | RXTT::BangEqual => return Type::Bool,
+            _ => return Type::Unknown,
+        };
+
+        match (&left, &right, operation) {
+            (Type::Number, Type::Number, "compare") => Type::Bool,
+            (Type::Number, Type::Number, _) => Type::Number,
+            (Type::String, Type::String, "add") => Type::String,
+            (Type::String, Type::String, "compare") | (Type::Bool, Type::Bool, "compare") => {
+                Type::Bool
+            }
+            _ => match (left.name(), right.name()) {
+                (Some(left), Some(right)) => {
+                    self.report(RatexErrorType::InvalidOperands(
+                        operator.line,
+                        operation,
+                        left,
+                        right,
+                    ));
+                    Type::Unknown
+                }
+                _ => Type::Unknown,
+            },
+        }
+    }
+
+    pub fn errors(&self) -> &[RatexError] {
+        &self.errors
+    }
+
+    // Checks a whole program in two passes, since a variable can be assigned
+    // after a function that reads it was declared.
+    pub fn check_program(&mut self, statements: &[Rc<Stmt>]) -> Result<(), RatexError> {
+        self.collecting = true;
+        self.check_list(statements)?;
+
+        self.collecting = false;
+        self.scopes = vec![HashMap::new()];
+        self.check_list(statements)
+    }
+
+    fn check_list(&mut self, statements: &[Rc<Stmt>]) -> Result<(), RatexError> {
+        for statement in statements {
+            self.check_stmt(statement)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_stmt(&mut self, stmt: &Rc<Stmt>) -> Result<(), RatexError> {
+        match **stmt {
+            Stmt::Empty => Ok(()),
+            _ => stmt.accept(self),
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Rc<Expr>) -> Result<Type, RatexError> {
+        match **expr {
+            Expr::Empty => Ok(Type::Unknown),
+            _ => expr.accept(self),
+        }
+    }
+
+    fn check_exprs(&mut self, exprs: &[Rc<Expr>]) -> Result<Vec<Type>, RatexError> {
+        exprs.iter().map(|e| self.check_expr(e)).collect()
+    }
+
+    fn report(&mut self, source: RatexErrorType) {
+        if !self.collecting {
+            self.errors.push(RatexError {
+                source,
+                backtrace: None,
+            });
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn define(&mut self, name: &RXT, mut value: Type) {
+        if self.collecting && self.scopes.len() == 1 && !self.globals.insert(name.lexeme.clone()) {
+            self.unstable.insert(name.lexeme.clone());
+        }
+
+        if self.unstable.contains(&name.lexeme) {
+            value = Type::Unknown;
+        }
+
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), value);
+        }
+    }
+
+    fn assigned(&mut self, name: &RXT) {
+        if self.collecting {
+            self.unstable.insert(name.lexeme.clone());
+        }
+    }
+
+    fn look_up(&self, name: &RXT) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme))
+            .cloned()
+            .unwrap_or(Type::Unknown)
+    }
+
+    fn check_function(
+        &mut self,
+        signature: &Signature,
+        body: &[Rc<Stmt>],
+    ) -> Result<(), RatexError> {
+        self.begin_scope();
+
+        for (param, param_type) in signature.params.iter().zip(&signature.param_types) {
+            // a rest parameter holds a list of the extra arguments
+            let value = match signature.max_arity {
+                None if param == signature.params.last().unwrap() => Type::List,
+                _ => Type::from_annotation(param_type),
+            };
+
+            self.define(param, value);
+        }
+
+        self.functions
+            .push((signature.name.clone(), signature.return_type.clone()));
+        let result = self.check_body(body);
+        self.functions.pop();
+
+        self.end_scope();
+        result
+    }
+
+    // A body's last expression statement is the function's value, unless
+    // it's declared to return nil, so it's checked like a return.
+    fn check_body(&mut self, body: &[Rc<Stmt>]) -> Result<(), RatexError> {
+        let Some((last, rest)) = body.split_last() else {
+            return Ok(());
+        };
+
+        self.check_list(rest)?;
+
+        let Stmt::Expression(expression) = last.as_ref() else {
+            return self.check_stmt(last);
+        };
+
+        let value = self.check_expr(&expression.expr)?;
+
+        if let Some((name, Some(expected))) = self.functions.last().cloned() {
+            if expected.lexeme != "nil" {
+                self.check_return_type(&name, &expected, value, expected.line);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_return_type(&mut self, name: &str, expected: &RXT, value: Type, line: u32) {
+        match value.name() {
+            Some(got) if got != expected.lexeme => self.report(RatexErrorType::TypeMismatch {
+                what: format!("the return value of {}", name),
+                expected: expected.lexeme.clone(),
+                got,
+                line,
+            }),
+            _ => {}
+        }
+    }
+
+    fn check_call(&mut self, signature: &Signature, arguments: &[Type], line: u32) {
+        let got = arguments.len();
+
+        if got < signature.min_arity || signature.max_arity.is_some_and(|max| got > max) {
+            self.report(RatexErrorType::IncompatibleArity {
+                name: signature.name.clone(),
+                expected: signature.min_arity,
+                maximum: signature.max_arity,
+                got,
+                line: Some(line),
+            });
+            return;
+        }
+
+        for (i, argument) in arguments.iter().enumerate() {
+            let Some((param, Some(expected))) = signature.param(i) else {
+                continue;
+            };
+
+            match argument.name() {
+                Some(got) if got != expected.lexeme => self.report(RatexErrorType::TypeMismatch {
+                    what: format!("parameter '{}' of {}", param.lexeme, signature.name),
+                    expected: expected.lexeme.clone(),
+                    got,
+                    line,
+                }),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl ExprVisitor<Type> for TypeChecker {
+    fn visit_binary(&mut self, target: Rc<Binary>) -> Result<Type, RatexError> {
+        let left = self.check_expr(&target.left)?;
+        let right = self.check_expr(&target.right)?;
+
+        Ok(self.binary_type(&target.operator, left, right))
+    }
+
+    // The result is one of the comparisons, so it's only known to be a bool
+    // when they all are.
+    fn visit_chain(&mut self, target: Rc<Chain>) -> Result<Type, RatexError> {
+        let mut left = self.check_expr(&target.operands[0])?;
+        let mut all_bool = true;
+
+        for (operator, operand) in target.operators.iter().zip(&target.operands[1..]) {
+            let right = self.check_expr(operand)?;
+            all_bool &= matches!(self.binary_type(operator, left, right.clone()), Type::Bool);
+            left = right;
+        }
+
+        Ok(if all_bool { Type::Bool } else { Type::Unknown })
+    }
+
+    fn visit_unary(&mut self, target: Rc<Unary>) -> Result<Type, RatexError> {
+        let right = self.check_expr(&target.right)?;
+
+        Ok(match (&target.operator.token_type, right) {
+            (RXTT::Minus, Type::Number) => Type::Number,
+            (RXTT::Minus, Type::Bool) => Type::Bool,
+            (RXTT::Bang, Type::Bool | Type::String | Type::Number) => Type::Bool,
+            _ => Type::Unknown,
+        })
+    }
+
+    // either operand can be the result
+    fn visit_logical(&mut self, target: Rc<Logical>) -> Result<Type, RatexError> {
+        self.check_expr(&target.left)?;
+        self.check_expr(&target.right)?;
+        Ok(Type::Unknown)
+    }
+
+    fn visit_literal(&mut self, target: Rc<Literal>) -> Result<Type, RatexError> {
+        Ok(Type::of_literal(&target.value))
+    }
+
+    fn visit_grouping(&mut self, target: Rc<Grouping>) -> Result<Type, RatexError> {
+        self.check_expr(&target.expr)
+    }
+
+    fn visit_variable(&mut self, target: Rc<Variable>) -> Result<Type, RatexError> {
+        Ok(self.look_up(&target.name))
+    }
+
+    fn visit_assign(&mut self, target: Rc<Assign>) -> Result<Type, RatexError> {
+        self.assigned(&target.name);
+        self.check_expr(&target.value)
+    }
+
+    fn visit_assign_tuple(&mut self, target: Rc<AssignTuple>) -> Result<Type, RatexError> {
+        for name in target.names() {
+            self.assigned(&name);
+        }
+
+        self.check_expr(&target.value)
+    }
+
+    fn visit_tuple_literal(&mut self, target: Rc<TupleLiteral>) -> Result<Type, RatexError> {
+        self.check_exprs(&target.elements)?;
+        Ok(Type::Tuple)
+    }
+
+    fn visit_call(&mut self, target: Rc<Call>) -> Result<Type, RatexError> {
+        let callee = self.check_expr(&target.callee)?;
+        let arguments = self.check_exprs(&target.arguments)?;
+
+        match callee {
+            Type::Function(Some(signature)) => {
+                self.check_call(&signature, &arguments, target.paren.line);
+                Ok(Type::from_annotation(&signature.return_type))
+            }
+            Type::Class(name) => Ok(Type::Instance(name)),
+            _ => Ok(Type::Unknown),
+        }
+    }
+
+    fn visit_lambda(&mut self, target: Rc<Lambda>) -> Result<Type, RatexError> {
+        let name = match &target.name {
+            Some(name) => name.lexeme.clone(),
+            None => "anonymous".to_owned(),
+        };
+
+        let signature = Signature::new(
+            name,
+            &target.params,
+            &target.param_types,
+            &target.defaults,
+            target.variadic,
+            &None,
+        );
+        let value = Type::Function(Some(Rc::clone(&signature)));
+
+        self.begin_scope();
+        if let Some(name) = &target.name {
+            self.define(name, value.clone());
+        }
+
+        let result = self.check_function(&signature, &target.body);
+        self.end_scope();
+        result?;
+
+        Ok(value)
+    }
+
+    fn visit_type_check(&mut self, target: Rc<TypeCheck>) -> Result<Type, RatexError> {
+        self.check_expr(&target.object)?;
+        self.check_expr(&target.class)?;
+        Ok(Type::Bool)
+    }
+
+    fn visit_loop(&mut self, target: Rc<Loop>) -> Result<Type, RatexError> {
+        self.check_stmt(&target.statement)?;
+        Ok(Type::Unknown)
+    }
+
+    fn visit_match(&mut self, target: Rc<Match>) -> Result<Type, RatexError> {
+        self.check_expr(&target.scrutinee)?;
+
+        for (pattern, value) in &target.arms {
+            self.begin_scope();
+
+            for name in Match::bindings(pattern) {
+                self.define(&name, Type::Unknown);
+            }
+
+            self.check_expr(value)?;
+            self.end_scope();
+        }
+
+        Ok(Type::Unknown)
+    }
+
+    fn visit_invariant(&mut self, target: Rc<Invariant>) -> Result<Type, RatexError> {
+        self.check_expr(&target.expr)
+    }
+
+    fn visit_get(&mut self, target: Rc<Get>) -> Result<Type, RatexError> {
+        self.check_expr(&target.object)?;
+        Ok(Type::Unknown)
+    }
+
+    fn visit_set(&mut self, target: Rc<Set>) -> Result<Type, RatexError> {
+        self.check_expr(&target.object)?;
+        self.check_expr(&target.value)
+    }
+
+    fn visit_this(&mut self, _: Rc<This>) -> Result<Type, RatexError> {
+        Ok(Type::Unknown)
+    }
+
+    fn visit_list_literal(&mut self, target: Rc<ListLiteral>) -> Result<Type, RatexError> {
+        self.check_exprs(&target.elements)?;
+        Ok(Type::List)
+    }
+
+    fn visit_index(&mut self, target: Rc<Index>) -> Result<Type, RatexError> {
+        self.check_expr(&target.object)?;
+        self.check_expr(&target.index)?;
+        Ok(Type::Unknown)
+    }
+
+    fn visit_index_set(&mut self, target: Rc<IndexSet>) -> Result<Type, RatexError> {
+        self.check_expr(&target.object)?;
+        self.check_expr(&target.index)?;
+        self.check_expr(&target.value)
+    }
+
+    fn visit_slice(&mut self, target: Rc<Slice>) -> Result<Type, RatexError> {
+        self.check_expr(&target.object)?;
+        self.check_expr(&target.start)?;
+        self.check_expr(&target.end)?;
+        Ok(Type::Unknown)
+    }
+
+    fn visit_slice_set(&mut self, target: Rc<SliceSet>) -> Result<Type, RatexError> {
+        self.check_expr(&target.object)?;
+        self.check_expr(&target.start)?;
+        self.check_expr(&target.end)?;
+        self.check_expr(&target.value)
+    }
+}
+
+impl StmtVisitor<()> for TypeChecker {
+    fn visit_block(&mut self, target: Rc<Block>) -> Result<(), RatexError> {
+        self.begin_scope();
+        let result = self.check_list(&target.statements);
+        self.end_scope();
+        result
+    }
+
+    fn visit_expression(&mut self, target: Rc<Expression>) -> Result<(), RatexError> {
+        self.check_expr(&target.expr)?;
+        Ok(())
+    }
+
+    fn visit_if(&mut self, target: Rc<If>) -> Result<(), RatexError> {
+        self.check_expr(&target.condition)?;
+        self.check_stmt(&target.then_stmt)?;
+        self.check_stmt(&target.else_stmt)
+    }
+
+    // The name is defined before the body is checked, so recursive calls are
+    // checked too.
+    fn visit_fun(&mut self, target: Rc<Fun>) -> Result<(), RatexError> {
+        let signature = Signature::new(
+            target.name.lexeme.clone(),
+            &target.params,
+            &target.param_types,
+            &target.defaults,
+            target.variadic,
+            &target.return_type,
+        );
+
+        self.define(&target.name, Type::Function(Some(Rc::clone(&signature))));
+        self.check_function(&signature, &target.body)
+    }
+
+    fn visit_while(&mut self, target: Rc<While>) -> Result<(), RatexError> {
+        self.check_expr(&target.condition)?;
+        self.check_stmt(&target.body)?;
+        self.check_stmt(&target.else_stmt)
+    }
+
+    fn visit_for(&mut self, target: Rc<For>) -> Result<(), RatexError> {
+        self.begin_scope();
+        self.check_stmt(&target.initialiser)?;
+        self.check_expr(&target.condition)?;
+        self.check_stmt(&target.body)?;
+        self.check_expr(&target.increment)?;
+        self.check_stmt(&target.else_stmt)?;
+        self.end_scope();
+
+        Ok(())
+    }
+
+    fn visit_yield(&mut self, _: Rc<Yield>) -> Result<(), RatexError> {
+        Ok(())
+    }
+
+    fn visit_try(&mut self, target: Rc<Try>) -> Result<(), RatexError> {
+        self.begin_scope();
+        self.check_list(&target.body)?;
+        self.end_scope();
+
+        self.begin_scope();
+        self.define(&target.name, Type::Unknown);
+        self.check_list(&target.handler)?;
+        self.end_scope();
+
+        Ok(())
+    }
+
+    fn visit_with(&mut self, target: Rc<With>) -> Result<(), RatexError> {
+        let resource = self.check_expr(&target.resource)?;
+
+        self.begin_scope();
+        self.define(&target.name, resource);
+        self.check_list(&target.body)?;
+        self.end_scope();
+
+        Ok(())
+    }
+
+    fn visit_throw(&mut self, target: Rc<Throw>) -> Result<(), RatexError> {
+        self.check_expr(&target.value)?;
+        Ok(())
+    }
+
+    fn visit_break(&mut self, target: Rc<Break>) -> Result<(), RatexError> {
+        self.check_expr(&target.value)?;
+        Ok(())
+    }
+
+    fn visit_print(&mut self, target: Rc<Print>) -> Result<(), RatexError> {
+        self.check_expr(&target.expr)?;
+        Ok(())
+    }
+
+    fn visit_return(&mut self, target: Rc<Return>) -> Result<(), RatexError> {
+        let value = match *target.value {
+            Expr::Empty => Type::Nil,
+            _ => self.check_expr(&target.value)?,
+        };
+
+        let Some((name, Some(expected))) = self.functions.last().cloned() else {
+            return Ok(());
+        };
+
+        self.check_return_type(&name, &expected, value, target.keyword.line);
+
+        Ok(())
+    }
+
+    fn visit_var(&mut self, target: Rc<Var>) -> Result<(), RatexError> {
+        let value = match *target.initialiser {
+            Expr::Empty => Type::Nil,
+            _ => self.check_expr(&target.initialiser)?,
+        };
+
+        self.define(&target.name, value);
+        Ok(())
+    }
+
+    fn visit_var_tuple(&mut self, target: Rc<VarTuple>) -> Result<(), RatexError> {
+        self.check_expr(&target.initialiser)?;
+
+        for name in &target.names {
+            self.define(name, Type::Unknown);
+        }
+
+        Ok(())
+    }
+
+    fn visit_variant(&mut self, target: Rc<Variant>) -> Result<(), RatexError> {
+        self.check_list(&target.constructors)
+    }
+
+    fn visit_class(&mut self, target: Rc<Class>) -> Result<(), RatexError> {
+        self.define(&target.name, Type::Class(target.name.lexeme.clone()));
+
+        for method in &target.methods {
+            if let Stmt::Fun(fun) = method.as_ref() {
+                let signature = Signature::new(
+                    fun.name.lexeme.clone(),
+                    &fun.params,
+                    &fun.param_types,
+                    &fun.defaults,
+                    fun.variadic,
+                    &fun.return_type,
+                );
+
+                self.check_function(&signature, &fun.body)?;
+            }
+        }
+
+        Ok(())
+    }
+}
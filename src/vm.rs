@@ -0,0 +1,107 @@
+use crate::{ast::Object, compiler::Instruction, error::RatexError, interpreter::RatexInterpreter};
+
+// A stack machine for the instructions made by the compiler. Operators,
+// printing, calls and globals all go through the interpreter, so programs
+// behave the same on either backend.
+pub struct Vm {
+    code: Vec<Instruction>,
+    stack: Vec<Object>,
+    ip: usize,
+}
+
+impl Vm {
+    pub fn new(code: Vec<Instruction>) -> Self {
+        Vm {
+            code,
+            stack: Vec::new(),
+            ip: 0,
+        }
+    }
+
+    pub fn run(&mut self, interpreter: &mut RatexInterpreter) -> Result<(), RatexError> {
+        while self.ip < self.code.len() {
+            interpreter.step()?;
+
+            let ip = self.ip;
+            self.ip += 1;
+
+            match &self.code[ip] {
+                Instruction::Constant(value) => self.stack.push(value.clone()),
+                Instruction::Pop => {
+                    pop(&mut self.stack);
+                }
+                Instruction::DefineGlobal(name) => {
+                    let value = pop(&mut self.stack);
+                    interpreter.define_variable(name.clone(), value)?;
+                }
+                Instruction::GetGlobal(name) => {
+                    let value = interpreter.get_global(name.clone())?;
+                    self.stack.push(value);
+                }
+                Instruction::SetGlobal(name) => {
+                    let value = peek(&self.stack).clone();
+                    interpreter.assign_variable(name.clone(), value)?;
+                }
+                Instruction::GetLocal(slot) => self.stack.push(self.stack[*slot].clone()),
+                Instruction::SetLocal(slot) => self.stack[*slot] = peek(&self.stack).clone(),
+                Instruction::Binary(operator) => {
+                    let right = pop(&mut self.stack);
+                    let left = pop(&mut self.stack);
+                    let value = interpreter.binary_op(operator, left, right)?;
+                    self.stack.push(value);
+                }
+                Instruction::Compare(operator) => {
+                    let right = pop(&mut self.stack);
+                    let left = pop(&mut self.stack);
+                    let value = interpreter.binary_op(operator, left, right.clone())?;
+                    self.stack.push(right);
+                    self.stack.push(value);
+                }
+                Instruction::Swap => {
+                    let len = self.stack.len();
+                    self.stack.swap(len - 1, len - 2);
+                }
+                Instruction::Unary(operator) => {
+                    let right = pop(&mut self.stack);
+                    let value = RatexInterpreter::unary_op(operator, right)?;
+                    self.stack.push(value);
+                }
+                Instruction::Print => {
+                    let value = pop(&mut self.stack);
+                    let text = interpreter.stringify(&value)?;
+                    interpreter.print_line(&text)?;
+                }
+                Instruction::Jump(target) => self.ip = *target,
+                Instruction::JumpIfFalse(target) => {
+                    if !peek(&self.stack).is_truthy() {
+                        self.ip = *target;
+                    }
+                }
+                Instruction::Loop(target) => {
+                    interpreter.check_deadline()?;
+                    self.ip = *target;
+                }
+                Instruction::Call(count, line) => {
+                    interpreter.check_deadline()?;
+
+                    let arguments = self.stack.split_off(self.stack.len() - count);
+                    let callee = pop(&mut self.stack);
+                    let value = interpreter.call_value(callee, arguments, *line)?;
+                    self.stack.push(value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// the compiler only emits pops for values it pushed, so an empty stack here
+// means the instructions themselves are wrong
+fn pop(stack: &mut Vec<Object>) -> Object {
+    stack.pop().expect("the VM stack should not underflow")
+}
+
+fn peek(stack: &[Object]) -> &Object {
+    stack.last().expect("the VM stack should not underflow")
+}
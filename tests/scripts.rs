@@ -0,0 +1,170 @@
+// Runs each script in tests/scripts through the ratex binary and compares
+// what it prints with the .expected file beside it. A .rtx file is run as a
+// script and a .in file is typed into the REPL.
+//
+// A script's first line can be `// flags: ...` for the arguments that go
+// before the script's name, and its second line (or first, without flags)
+// `// args: ...` for the ones after it. Double quotes keep spaces in one
+// argument.
+//
+// Run with RATEX_BLESS=1 to write out the .expected files instead.
+
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+#[test]
+fn scripts() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts");
+    let bless = env::var_os("RATEX_BLESS").is_some();
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| matches!(extension(path), "rtx" | "in"))
+        .collect();
+    paths.sort();
+
+    assert!(!paths.is_empty(), "no scripts in {}", dir.display());
+
+    let mut failures = Vec::new();
+
+    for path in &paths {
+        let actual = run(&dir, path);
+        let expected_path = path.with_extension("expected");
+
+        if bless {
+            fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{}\n--- expected\n{}--- actual\n{}",
+                path.display(),
+                expected,
+                actual
+            )),
+            Err(_) => failures.push(format!("{} has no .expected file", path.display())),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} scripts failed:\n\n{}",
+        failures.len(),
+        paths.len(),
+        failures.join("\n")
+    );
+}
+
+// A script that isn't there is reported, not a panic.
+#[test]
+fn missing_script() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts");
+    let output = Command::new(env!("CARGO_BIN_EXE_ratex"))
+        .current_dir(&dir)
+        .arg("no_such_script.rtx")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(output.status.success(), "exited with {}", output.status);
+    assert!(
+        stdout.starts_with("Error: could not read file: ") && stdout.contains("no_such_script.rtx"),
+        "unexpected output: {}",
+        stdout
+    );
+    assert!(!stderr.contains("panicked"), "panicked: {}", stderr);
+}
+
+fn extension(path: &Path) -> &str {
+    path.extension().and_then(|e| e.to_str()).unwrap_or("")
+}
+
+// The script's output, with the binary run from the scripts directory since
+// it looks scripts up relative to where it's run.
+fn run(dir: &Path, path: &Path) -> String {
+    let source = fs::read_to_string(path).unwrap();
+    let name = path.file_name().unwrap().to_str().unwrap();
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ratex"));
+    command.current_dir(dir).stdin(Stdio::piped());
+
+    if extension(path) == "rtx" {
+        command
+            .args(header(&source, "flags"))
+            .arg(name)
+            .args(header(&source, "args"));
+    }
+
+    let mut child = command.stdout(Stdio::piped()).spawn().unwrap();
+
+    // the REPL reads its input from stdin, and a script gets none
+    let input = if extension(path) == "in" {
+        source
+    } else {
+        String::new()
+    };
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// The arguments from a `// <key>: ...` line at the top of the script.
+fn header(source: &str, key: &str) -> Vec<String> {
+    let prefix = format!("// {}:", key);
+
+    source
+        .lines()
+        .take(2)
+        .find_map(|line| line.strip_prefix(prefix.as_str()))
+        .map(split_args)
+        .unwrap_or_default()
+}
+
+fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut started = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    args.push(std::mem::take(&mut current));
+                    started = false;
+                }
+            }
+            c => {
+                current.push(c);
+                started = true;
+            }
+        }
+    }
+
+    if started {
+        args.push(current);
+    }
+
+    args
+}